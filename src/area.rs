@@ -0,0 +1,75 @@
+use std::error::Error;
+
+use regex::Regex;
+
+use crate::http_client::{build_client, send_with_retry};
+
+/// エリアIDと都道府県名の対応表（JP1〜JP47）
+pub const AREAS: [(&str, &str); 47] = [
+    ("JP1", "北海道"),
+    ("JP2", "青森県"),
+    ("JP3", "岩手県"),
+    ("JP4", "宮城県"),
+    ("JP5", "秋田県"),
+    ("JP6", "山形県"),
+    ("JP7", "福島県"),
+    ("JP8", "茨城県"),
+    ("JP9", "栃木県"),
+    ("JP10", "群馬県"),
+    ("JP11", "埼玉県"),
+    ("JP12", "千葉県"),
+    ("JP13", "東京都"),
+    ("JP14", "神奈川県"),
+    ("JP15", "新潟県"),
+    ("JP16", "富山県"),
+    ("JP17", "石川県"),
+    ("JP18", "福井県"),
+    ("JP19", "山梨県"),
+    ("JP20", "長野県"),
+    ("JP21", "岐阜県"),
+    ("JP22", "静岡県"),
+    ("JP23", "愛知県"),
+    ("JP24", "三重県"),
+    ("JP25", "滋賀県"),
+    ("JP26", "京都府"),
+    ("JP27", "大阪府"),
+    ("JP28", "兵庫県"),
+    ("JP29", "奈良県"),
+    ("JP30", "和歌山県"),
+    ("JP31", "鳥取県"),
+    ("JP32", "島根県"),
+    ("JP33", "岡山県"),
+    ("JP34", "広島県"),
+    ("JP35", "山口県"),
+    ("JP36", "徳島県"),
+    ("JP37", "香川県"),
+    ("JP38", "愛媛県"),
+    ("JP39", "高知県"),
+    ("JP40", "福岡県"),
+    ("JP41", "佐賀県"),
+    ("JP42", "長崎県"),
+    ("JP43", "熊本県"),
+    ("JP44", "大分県"),
+    ("JP45", "宮崎県"),
+    ("JP46", "鹿児島県"),
+    ("JP47", "沖縄県"),
+];
+
+/// エリアIDに対応する都道府県名を返す
+pub fn prefecture_name(area_id: &str) -> Option<&'static str> {
+    AREAS.iter().find(|(id, _)| *id == area_id).map(|(_, name)| *name)
+}
+
+/// Radiko のエリア判定 API から、現在の接続元の IP アドレスで判定されたエリアIDを取得する
+///
+/// 応答は `document.write('<span class="JP13">TOKYO JAPAN</span>');` のような JavaScript で、
+/// `class` 属性の値がエリアIDとなる。日本国外からの接続など、判定できない場合はエラーを返す。
+pub fn detect_area() -> Result<String, Box<dyn Error>> {
+    let resp: reqwest::blocking::Response = send_with_retry(build_client()?.get("https://radiko.jp/area"))?;
+    let content: String = resp.text()?;
+    let re: Regex = Regex::new(r#"class="(JP\d+)""#).unwrap();
+    re.captures(&content)
+        .map(|caps| caps[1].to_string())
+        .filter(|area_id| prefecture_name(area_id).is_some())
+        .ok_or_else(|| format!("Could not detect the area from the response: {}", content.trim()).into())
+}
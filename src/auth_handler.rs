@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
+use std::fs;
 use std::time::Duration;
 use std::thread::sleep;
 
@@ -7,51 +9,302 @@ use reqwest::blocking::{Client, Response};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use base64::{engine::general_purpose, Engine as _};
 use log::{debug, warn};
+use serde::Deserialize;
+
+use crate::http_client::{build_client, send_with_retry};
+
+/// 認可処理の段階
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthStep {
+    /// auth1（認可トークンと部分鍵の位置の取得）
+    Auth1,
+    /// auth2（部分鍵による認可とエリアの判定）
+    Auth2,
+}
+
+impl AuthStep {
+    /// 段階に対応する API の URL
+    fn url(self) -> &'static str {
+        match self {
+            AuthStep::Auth1 => "https://radiko.jp/v2/api/auth1",
+            AuthStep::Auth2 => "https://radiko.jp/v2/api/auth2",
+        }
+    }
+}
+
+impl fmt::Display for AuthStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthStep::Auth1 => write!(f, "auth1"),
+            AuthStep::Auth2 => write!(f, "auth2"),
+        }
+    }
+}
+
+/// 認可リクエストで名乗る Radiko のアプリの種類（認可プロファイル）
+///
+/// アプリの種類ごとに、認可リクエストのヘッダ（`X-Radiko-App` など）と部分鍵の作成に使う認可キーが異なる。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+pub enum AuthProfile {
+    /// ブラウザ版（組み込みの認可キーを使う）
+    #[default]
+    #[serde(rename = "pc_html5")]
+    #[value(name = "pc_html5")]
+    PcHtml5,
+    /// Android アプリ版（アプリの認可キーと、位置情報のヘッダ `X-Radiko-Location` の指定が必要）
+    #[serde(rename = "aSmartPhone7a")]
+    #[value(name = "aSmartPhone7a")]
+    ASmartPhone7a,
+}
+
+impl AuthProfile {
+    /// `X-Radiko-App` に指定するアプリ名
+    pub fn app_name(self) -> &'static str {
+        match self {
+            AuthProfile::PcHtml5 => "pc_html5",
+            AuthProfile::ASmartPhone7a => "aSmartPhone7a",
+        }
+    }
+
+    /// 認可リクエストの初期ヘッダ（`X-Radiko-App` 以外のアプリ固有の値）
+    fn headers(self) -> Vec<(&'static str, &'static str)> {
+        match self {
+            AuthProfile::PcHtml5 => vec![
+                ("User-Agent", "python3.7"),
+                ("X-Radiko-App-Version", "0.0.1"),
+                ("X-Radiko-User", "dummy_user"),
+                ("X-Radiko-Device", "pc"),
+            ],
+            // X-Radiko-Device は `<Android の API レベル>.<端末のモデル名>` の形式
+            AuthProfile::ASmartPhone7a => vec![
+                ("User-Agent", "Dalvik/2.1.0 (Linux; U; Android 10; Pixel 4 Build/QQ3A.200805.001)"),
+                ("X-Radiko-App-Version", "7.5.0"),
+                ("X-Radiko-User", "0123456789abcdef0123456789abcdef"),
+                ("X-Radiko-Device", "29.Pixel_4"),
+                ("X-Radiko-Connection", "wifi"),
+            ],
+        }
+    }
+
+    /// 組み込みの認可キー（アプリから取り出す必要があり組み込んでいない場合は `None`）
+    fn built_in_key(self) -> Option<&'static [u8]> {
+        match self {
+            AuthProfile::PcHtml5 => Some(b"bcd151073c03b352e1ef2fd66c32209da9ca0afa"),
+            AuthProfile::ASmartPhone7a => None,
+        }
+    }
+
+    /// auth2 で位置情報のヘッダ（`X-Radiko-Location`）が必要か
+    fn requires_location(self) -> bool {
+        matches!(self, AuthProfile::ASmartPhone7a)
+    }
+}
+
+/// 認可処理のエラー
+///
+/// 失敗した段階やステータスコード、不足していたヘッダなどを保持する。
+/// `Box<dyn Error>` として返されるため、利用側では `downcast_ref::<AuthError>()` で取り出せる。
+#[derive(Debug)]
+pub enum AuthError {
+    /// 認可リクエストの送信に失敗した
+    Request { step: AuthStep, message: String },
+    /// 認可 API が成功以外のステータスコードを返した
+    Status { step: AuthStep, status: u16, body: String },
+    /// 必要なレスポンスヘッダがない
+    MissingHeader { step: AuthStep, header: &'static str },
+    /// レスポンスヘッダの値が不正
+    InvalidHeader { step: AuthStep, header: &'static str, value: String },
+    /// 部分鍵を作成できない
+    PartialKey { message: String },
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Request { step, message } => {
+                write!(f, "Radiko authentication failed in {}: request error: {}", step, message)
+            }
+            AuthError::Status { step, status, body } => {
+                write!(f, "Radiko authentication failed in {}: status {}: {}", step, status, body.trim())
+            }
+            AuthError::MissingHeader { step, header } => {
+                write!(f, "Radiko authentication failed in {}: missing {} header", step, header)
+            }
+            AuthError::InvalidHeader { step, header, value } => {
+                write!(f, "Radiko authentication failed in {}: invalid {} header: {}", step, header, value)
+            }
+            AuthError::PartialKey { message } => {
+                write!(f, "Radiko authentication failed: cannot derive partial key: {}", message)
+            }
+        }
+    }
+}
+
+impl Error for AuthError {}
+
+/// Radiko プレミアム会員のログイン API の URL
+const PREMIUM_LOGIN_URL: &str = "https://radiko.jp/v4/api/member/login";
+
+/// Radiko プレミアム会員としてログインし、認可（auth2）に使うセッションIDを返す
+///
+/// エリアフリーが有効な会員でない場合は、ログインはできてもエリア外の放送局を聴取できないため警告を出す。
+pub fn premium_login(mail: &str, password: &str) -> Result<String, Box<dyn Error>> {
+    let client: Client = build_client()?;
+    let res: Response = send_with_retry(client.post(PREMIUM_LOGIN_URL).form(&[("mail", mail), ("pass", password)]))
+        .map_err(|e| format!("Radiko premium login failed: request error: {}", e))?;
+    let status: reqwest::StatusCode = res.status();
+    if !status.is_success() {
+        return Err(format!(
+            "Radiko premium login failed: status {} (check the premium mail address and password)",
+            status
+        )
+        .into());
+    }
+    let body: serde_json::Value = serde_json::from_str(&res.text()?)
+        .map_err(|e| format!("Radiko premium login failed: invalid response: {}", e))?;
+    let session: String = body
+        .get("radiko_session")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .ok_or("Radiko premium login failed: no radiko_session in the response")?
+        .to_string();
+    // areafree は "1" または 1 で返る
+    let areafree: bool = body
+        .get("areafree")
+        .is_some_and(|v| v.as_str() == Some("1") || v.as_i64() == Some(1));
+    if !areafree {
+        warn!("The Radiko premium account is not area-free; stations outside the area will still be unavailable");
+    }
+    debug!("logged in to Radiko premium");
+    Ok(session)
+}
 
 /// Radiko API の認可ハンドラ
 pub struct RadikoAuthHandler {
     headers: HashMap<String, String>,
+    /// 部分鍵の作成に使う認可キー
+    auth_key: Vec<u8>,
+    /// プレミアム会員のセッションID（auth2 に渡す、匿名の認可の場合は `None`）
+    session: Option<String>,
+    /// auth2 の応答で示された、認可トークンで聴取できるエリアID
+    authorized_areas: Vec<String>,
 }
 
 impl RadikoAuthHandler {
-    const AUTH1_URL: &'static str = "https://radiko.jp/v2/api/auth1";
-    const AUTH2_URL: &'static str = "https://radiko.jp/v2/api/auth2";
-    /// Radiko の認可キー（固定値）
-    const RADIKO_AUTH_KEY: &'static [u8] = b"bcd151073c03b352e1ef2fd66c32209da9ca0afa";
-
     /// コンストラクタ
-    /// `area_id` に指定されたエリアIDを使い、認可処理を実行する。
-    pub fn new(area_id: &str) -> Result<Self, Box<dyn Error>> {
+    /// `area_id` に指定されたエリアIDを使い、`profile` のアプリとして認可処理を実行する。
+    /// `extra_headers` は初期ヘッダに追加（同名の場合は上書き）される。
+    /// `auth_key` が指定された場合は組み込みの認可キーの代わりに使う（形式は `parse_auth_key` を参照）。
+    pub fn new(
+        area_id: &str,
+        profile: AuthProfile,
+        extra_headers: &HashMap<String, String>,
+        auth_key: Option<&str>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::authenticate(area_id, profile, extra_headers, auth_key, None)
+    }
+
+    /// プレミアム会員として認可処理を実行するコンストラクタ
+    ///
+    /// `session` は `premium_login` で取得したセッションID。その他の引数は `new` と同じ。
+    pub fn premium(
+        area_id: &str,
+        profile: AuthProfile,
+        extra_headers: &HashMap<String, String>,
+        auth_key: Option<&str>,
+        session: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::authenticate(area_id, profile, extra_headers, auth_key, Some(session))
+    }
+
+    /// 認可処理を実行する（`session` が指定された場合はプレミアム会員として認可する）
+    fn authenticate(
+        area_id: &str,
+        profile: AuthProfile,
+        extra_headers: &HashMap<String, String>,
+        auth_key: Option<&str>,
+        session: Option<&str>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let auth_key: Vec<u8> = Self::effective_auth_key(profile, auth_key)?;
+        if profile.requires_location() && !extra_headers.keys().any(|k| k.eq_ignore_ascii_case("X-Radiko-Location")) {
+            return Err(format!(
+                "The {} auth profile requires an X-Radiko-Location header (\"<latitude>,<longitude>,gps\") in [auth.headers]",
+                profile.app_name()
+            )
+            .into());
+        }
+
         // 初期ヘッダの設定
         let mut headers: HashMap<String, String> = HashMap::new();
-        headers.insert("User-Agent".to_string(), "python3.7".to_string());
         headers.insert("Accept".to_string(), "*/*".to_string());
-        headers.insert("X-Radiko-App".to_string(), "pc_html5".to_string());
-        headers.insert("X-Radiko-App-Version".to_string(), "0.0.1".to_string());
-        headers.insert("X-Radiko-User".to_string(), "dummy_user".to_string());
-        headers.insert("X-Radiko-Device".to_string(), "pc".to_string());
+        headers.insert("X-Radiko-App".to_string(), profile.app_name().to_string());
+        for (key, value) in profile.headers() {
+            headers.insert(key.to_string(), value.to_string());
+        }
         headers.insert("X-Radiko-AuthToken".to_string(), "".to_string());
         headers.insert("X-Radiko-Partialkey".to_string(), "".to_string());
         headers.insert("X-Radiko-AreaId".to_string(), area_id.to_string());
 
-        let mut handler: RadikoAuthHandler = RadikoAuthHandler { headers };
+        // 設定ファイルで指定されたヘッダを追加・上書き
+        for (key, value) in extra_headers {
+            HeaderName::from_bytes(key.as_bytes())
+                .map_err(|_| format!("Invalid auth header name: {}", key))?;
+            HeaderValue::from_str(value)
+                .map_err(|_| format!("Invalid auth header value for {}: {}", key, value))?;
+            // ヘッダ名の大文字・小文字の違いによる重複を避ける
+            headers.retain(|k, _| !k.eq_ignore_ascii_case(key));
+            headers.insert(key.clone(), value.clone());
+        }
+        debug!("effective auth headers: {:?}", headers);
+
+        let mut handler: RadikoAuthHandler = RadikoAuthHandler {
+            headers,
+            auth_key,
+            session: session.map(str::to_string),
+            authorized_areas: Vec::new(),
+        };
         // 認可処理（auth1 → auth2）を実行
         handler.auth()?;
         Ok(handler)
     }
 
+    /// 部分鍵の作成に使う認可キーを返す
+    ///
+    /// `auth_key` が指定された場合はそれを解釈したもの、指定されない場合は `profile` の組み込みの認可キーを返す。
+    pub fn effective_auth_key(profile: AuthProfile, auth_key: Option<&str>) -> Result<Vec<u8>, Box<dyn Error>> {
+        match (auth_key, profile.built_in_key()) {
+            (Some(key), _) => {
+                debug!("using auth key from configuration");
+                parse_auth_key(key)
+            }
+            (None, Some(key)) => Ok(key.to_vec()),
+            (None, None) => Err(format!(
+                "The {} auth profile has no built-in auth key; set one with --auth-key (e.g. file:<path>)",
+                profile.app_name()
+            )
+            .into()),
+        }
+    }
+
     /// 認可済みのヘッダを取得する
     pub fn get_authenticated_headers(&self) -> HashMap<String, String> {
         self.headers.clone()
     }
 
+    /// 認可トークンで聴取できるエリアID（`JPxx`）の一覧を取得する
+    ///
+    /// auth2 の応答本文（`JP13,東京都,tokyo Japan` の形式）に含まれるエリアIDを返す。
+    pub fn authorized_areas(&self) -> &[String] {
+        &self.authorized_areas
+    }
+
     /// 内部で認可処理を行う  
     ///  
     /// 1. AUTH1 API を呼び出し、認可用トークンと部分鍵を取得する。  
     /// 2. 取得した情報をヘッダに設定後、AUTH2 API を呼び出す。
     fn auth(&mut self) -> Result<(), Box<dyn Error>> {
         // AUTH1 API 呼び出し
-        let res: Response = self.call_auth_api(Self::AUTH1_URL)?;
+        let res: Response = self.call_auth_api(AuthStep::Auth1)?;
         // レスポンスから認可用トークンと部分鍵を取得
         let auth_token: String = self.get_auth_token(&res)?;
         let partial_key: String = self.get_partial_key(&res)?;
@@ -59,21 +312,28 @@ impl RadikoAuthHandler {
         self.headers.insert("X-Radiko-Partialkey".to_string(), partial_key);
 
         // AUTH2 API 呼び出し（認可トークンが設定されたヘッダを利用）
-        let res2 = self.call_auth_api(Self::AUTH2_URL)?;
+        let res2 = self.call_auth_api(AuthStep::Auth2)?;
         debug!("authenticated headers: {:?}", self.headers);
         debug!("auth2 response headers: {:?}", res2.headers());
-        let content = res2.text()?;
+        let content = res2.text().map_err(|e| AuthError::Request {
+            step: AuthStep::Auth2,
+            message: e.to_string(),
+        })?;
         debug!("auth2 response content: {}", content.replace("\n", ""));
+        self.authorized_areas = parse_authorized_areas(&content);
         Ok(())
     }
 
     /// RadikoAPIに認可リクエストを送信する
     /// タイムアウトは 5 秒、リクエスト後に 1 秒のスリープを行う。
-    fn call_auth_api(&self, api_url: &str) -> Result<Response, Box<dyn Error>> {
+    /// プレミアム会員の場合は auth2 にセッションIDを付ける。
+    fn call_auth_api(&self, step: AuthStep) -> Result<Response, Box<dyn Error>> {
+        let api_url: String = match (step, &self.session) {
+            (AuthStep::Auth2, Some(session)) => format!("{}?radiko_session={}", step.url(), session),
+            _ => step.url().to_string(),
+        };
         // タイムアウト付きのクライアントを作成
-        let client: Client = Client::builder()
-            .timeout(Duration::from_secs(5))
-            .build()?;
+        let client: Client = build_client()?;
         // self.headers (HashMap) を HeaderMap に変換
         let mut header_map: HeaderMap = HeaderMap::new();
         for (key, value) in &self.headers {
@@ -82,48 +342,120 @@ impl RadikoAuthHandler {
                 HeaderValue::from_str(value)?
             );
         }
-        // GET リクエストを送信
-        let res: Response = client.get(api_url)
-            .headers(header_map)
-            .send()?;
+        // GET リクエストを送信（429 の場合は Retry-After に従って再送）
+        let res: Response = send_with_retry(client.get(&api_url).headers(header_map))
+            .map_err(|e| AuthError::Request { step, message: e.to_string() })?;
         // リクエスト後、1 秒待機
         sleep(Duration::from_secs(1));
         if !res.status().is_success() {
-            warn!("failed in {}.", api_url);
+            warn!("failed in {}.", step.url());
             warn!("status code: {}", res.status());
-            let text = res.text()?;
+            let status: u16 = res.status().as_u16();
+            let text: String = res.text().unwrap_or_default();
             warn!("content: {}", text);
-            return Err(format!("failed in {}.", api_url).into());
+            return Err(AuthError::Status { step, status, body: text }.into());
         }
-        debug!("auth in {} is success.", api_url);
+        debug!("auth in {} is success.", step.url());
         Ok(res)
     }
 
     /// レスポンスヘッダから認可用トークン（X-Radiko-AUTHTOKEN）を取得する
     fn get_auth_token(&self, response: &Response) -> Result<String, Box<dyn Error>> {
-        match response.headers().get("X-Radiko-AUTHTOKEN") {
-            Some(val) => Ok(val.to_str()?.to_string()),
-            None => Err("Missing X-Radiko-AUTHTOKEN header".into()),
-        }
+        Ok(Self::auth1_header(response, "X-Radiko-AUTHTOKEN")?.to_string())
     }
 
     /// レスポンスヘッダから部分鍵用の情報を取得し、  
-    /// 固定の認可キーから指定範囲のバイト列を Base64 エンコードして返す
+    /// 認可キーから指定範囲のバイト列を Base64 エンコードして返す
     fn get_partial_key(&self, response: &Response) -> Result<String, Box<dyn Error>> {
-        let key_length: usize = match response.headers().get("X-Radiko-KeyLength") {
-            Some(val) => val.to_str()?.parse::<usize>()?,
-            None => return Err("Missing X-Radiko-KeyLength header".into()),
-        };
-        let key_offset: usize = match response.headers().get("X-Radiko-KeyOffset") {
-            Some(val) => val.to_str()?.parse::<usize>()?,
-            None => return Err("Missing X-Radiko-KeyOffset header".into()),
-        };
+        let key_length: usize = Self::auth1_number_header(response, "X-Radiko-KeyLength")?;
+        let key_offset: usize = Self::auth1_number_header(response, "X-Radiko-KeyOffset")?;
+
+        derive_partial_key(&self.auth_key, key_offset, key_length)
+            .map_err(|e| AuthError::PartialKey { message: e.to_string() }.into())
+    }
+
+    /// auth1 のレスポンスヘッダの値を文字列として取得する
+    fn auth1_header<'a>(response: &'a Response, header: &'static str) -> Result<&'a str, AuthError> {
+        let value: &HeaderValue = response
+            .headers()
+            .get(header)
+            .ok_or(AuthError::MissingHeader { step: AuthStep::Auth1, header })?;
+        value.to_str().map_err(|_| AuthError::InvalidHeader {
+            step: AuthStep::Auth1,
+            header,
+            value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+        })
+    }
+
+    /// auth1 のレスポンスヘッダの値を数値として取得する
+    fn auth1_number_header(response: &Response, header: &'static str) -> Result<usize, AuthError> {
+        let value: &str = Self::auth1_header(response, header)?;
+        value.trim().parse::<usize>().map_err(|_| AuthError::InvalidHeader {
+            step: AuthStep::Auth1,
+            header,
+            value: value.to_string(),
+        })
+    }
+}
+
+/// auth2 の応答本文からエリアID（`JP` に続く数字）を取り出す（重複は除く）
+pub fn parse_authorized_areas(content: &str) -> Vec<String> {
+    let mut areas: Vec<String> = Vec::new();
+    for field in content.split([',', '\n', '\r']).map(str::trim) {
+        let is_area: bool = field
+            .strip_prefix("JP")
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+        if is_area && !areas.iter().any(|a| a == field) {
+            areas.push(field.to_string());
+        }
+    }
+    areas
+}
 
-        if key_offset + key_length > Self::RADIKO_AUTH_KEY.len() {
-            return Err("Key offset and length out of bounds".into());
+/// 認可キーの指定を解釈してバイト列を返す
+///
+/// `hex:` で始まる場合は 16 進数として、`file:` で始まる場合はそのファイルの内容（バイナリ）として、
+/// それ以外は文字列のバイト列として扱う。
+pub fn parse_auth_key(value: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    if let Some(path) = value.strip_prefix("file:") {
+        let key: Vec<u8> = fs::read(path).map_err(|e| format!("Failed to read auth key file {}: {}", path, e))?;
+        if key.is_empty() {
+            return Err(format!("Auth key file {} is empty", path).into());
         }
-        let slice: &[u8] = &Self::RADIKO_AUTH_KEY[key_offset .. key_offset + key_length];
-        let partial_key: String = general_purpose::STANDARD.encode(slice);
-        Ok(partial_key)
+        return Ok(key);
     }
+    let key: Vec<u8> = match value.strip_prefix("hex:") {
+        Some(hex) => {
+            if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err("Invalid hex auth key".into());
+            }
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()?
+        }
+        None => value.as_bytes().to_vec(),
+    };
+    if key.is_empty() {
+        return Err("Auth key is empty".into());
+    }
+    Ok(key)
+}
+
+/// 認可キーの `offset` から `length` バイトを Base64 エンコードした部分鍵を返す
+///
+/// 範囲が認可キーの長さを超える場合はエラーを返す。
+pub fn derive_partial_key(auth_key: &[u8], offset: usize, length: usize) -> Result<String, Box<dyn Error>> {
+    let end: usize = offset
+        .checked_add(length)
+        .filter(|end| *end <= auth_key.len())
+        .ok_or_else(|| {
+            format!(
+                "Key offset and length out of bounds (offset={}, length={}, key length={})",
+                offset,
+                length,
+                auth_key.len()
+            )
+        })?;
+    Ok(general_purpose::STANDARD.encode(&auth_key[offset..end]))
 }
@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::config::DurationValue;
+
+/// バッチファイルの 1 件分の録音（`[[job]]`）
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchJob {
+    /// 放送局ID
+    pub station: String,
+    /// 録音開始日時（YYYYMMDDHHMMSS 形式または `@<エポック秒>`）
+    pub start: String,
+    /// 録音時間（整数は分、`1h30m` などの形式も可）
+    pub duration: DurationValue,
+    /// エリアID（省略時は全体のエリアID）
+    pub area_id: Option<String>,
+}
+
+/// バッチファイル（TOML）の内容
+#[derive(Debug, Deserialize)]
+struct BatchFile {
+    #[serde(rename = "job", default)]
+    jobs: Vec<BatchJob>,
+}
+
+/// バッチファイルを読み込む
+///
+/// 各録音は `[[job]]` テーブルとして記述する。
+pub fn load_batch(path: &str) -> Result<Vec<BatchJob>, Box<dyn Error>> {
+    let content: String =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read batch file {}: {}", path, e))?;
+    let batch: BatchFile =
+        toml::from_str(&content).map_err(|e| format!("Failed to parse batch file {}: {}", path, e))?;
+    if batch.jobs.is_empty() {
+        return Err(format!("No jobs defined in batch file {}", path).into());
+    }
+    Ok(batch.jobs)
+}
+
+/// ディレクトリ内の TOML ファイル（1 ファイルに 1 件の録音）を読み込む
+///
+/// 各ファイルは `[[job]]` のテーブルを使わず、`station`・`start`・`duration` などをトップレベルに記述する。
+/// ファイル名の順に読み込み、読み込めないファイルは警告を出してスキップする。
+pub fn load_batch_dir(dir: &str) -> Result<Vec<BatchJob>, Box<dyn Error>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read batch directory {}: {}", dir, e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && path.extension().and_then(|ext| ext.to_str()) == Some("toml"))
+        .collect();
+    paths.sort();
+
+    let mut jobs: Vec<BatchJob> = Vec::new();
+    for path in &paths {
+        let result: Result<BatchJob, String> = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| toml::from_str(&content).map_err(|e| e.to_string()));
+        match result {
+            Ok(job) => jobs.push(job),
+            Err(e) => warn!("Skipping batch job file {}: {}", path.display(), e.trim_end()),
+        }
+    }
+    if jobs.is_empty() {
+        return Err(format!("No valid job files in batch directory {}", dir).into());
+    }
+    info!("Loaded {} of {} job files from {}", jobs.len(), paths.len(), dir);
+    Ok(jobs)
+}
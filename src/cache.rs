@@ -0,0 +1,214 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use chrono::NaiveDate;
+use log::{debug, warn};
+use serde::Serialize;
+
+/// 放送局リストのキャッシュを置くサブディレクトリ
+const STATION_LIST_DIR: &str = "stations";
+
+/// 番組表のキャッシュを置くサブディレクトリ
+const SCHEDULE_DIR: &str = "schedules";
+
+/// キャッシュの設定
+#[derive(Debug, Clone)]
+struct CacheSettings {
+    /// キャッシュを置くディレクトリ
+    dir: PathBuf,
+    /// 放送局リストのキャッシュの有効期間（0 の場合はキャッシュしない）
+    station_list_ttl: Duration,
+    /// 番組表のキャッシュの有効期間（0 の場合はキャッシュしない）
+    schedule_ttl: Duration,
+}
+
+/// `configure_cache` で設定されたキャッシュの設定（未設定の場合はキャッシュしない）
+static CACHE: OnceLock<CacheSettings> = OnceLock::new();
+
+/// キャッシュのディレクトリと有効期間を設定する
+///
+/// # 引数
+/// - `dir`: キャッシュを置くディレクトリ
+/// - `station_list_ttl_hours`: 放送局リストのキャッシュの有効期間（時間、0 の場合はキャッシュしない）
+/// - `schedule_ttl_hours`: 番組表のキャッシュの有効期間（時間、0 の場合はキャッシュしない）
+pub fn configure_cache(dir: &Path, station_list_ttl_hours: u64, schedule_ttl_hours: u64) {
+    let _ = CACHE.set(CacheSettings {
+        dir: dir.to_path_buf(),
+        station_list_ttl: Duration::from_secs(station_list_ttl_hours * 3600),
+        schedule_ttl: Duration::from_secs(schedule_ttl_hours * 3600),
+    });
+}
+
+/// キャッシュ済みのファイル 1 件分の情報
+#[derive(Debug, Serialize)]
+pub struct CacheEntry {
+    /// 種類（`station_list`, `schedule`）
+    pub kind: String,
+    /// キャッシュのキー（放送局リストの場合はエリアID、番組表の場合は `<放送局ID>_<YYYYMMDD>`）
+    pub key: String,
+    pub path: String,
+    /// 保存してからの経過秒数
+    pub age_secs: u64,
+    pub size_bytes: u64,
+    /// 有効期間を過ぎている（次回の取得で更新される）
+    pub expired: bool,
+}
+
+/// キャッシュの種類
+#[derive(Debug, Clone, Copy)]
+enum CacheKind {
+    StationList,
+    Schedule,
+}
+
+impl CacheKind {
+    /// `CacheEntry::kind` に表示する名前
+    fn name(self) -> &'static str {
+        match self {
+            CacheKind::StationList => "station_list",
+            CacheKind::Schedule => "schedule",
+        }
+    }
+
+    /// キャッシュを置くサブディレクトリ
+    fn dir(self) -> &'static str {
+        match self {
+            CacheKind::StationList => STATION_LIST_DIR,
+            CacheKind::Schedule => SCHEDULE_DIR,
+        }
+    }
+
+    /// キャッシュの有効期間
+    fn ttl(self, settings: &CacheSettings) -> Duration {
+        match self {
+            CacheKind::StationList => settings.station_list_ttl,
+            CacheKind::Schedule => settings.schedule_ttl,
+        }
+    }
+}
+
+/// キャッシュファイルのパス
+fn cache_path(settings: &CacheSettings, kind: CacheKind, key: &str) -> PathBuf {
+    settings.dir.join(kind.dir()).join(format!("{}.xml", key))
+}
+
+/// ファイルの更新日時からの経過時間（取得できない場合は `None`）
+fn file_age(path: &Path) -> Option<Duration> {
+    let modified: SystemTime = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(SystemTime::now().duration_since(modified).unwrap_or_default())
+}
+
+/// 有効期間内のキャッシュの内容を返す
+///
+/// キャッシュが無効な場合、存在しない場合、有効期間を過ぎている場合は `None` を返す。
+fn load(kind: CacheKind, key: &str) -> Option<String> {
+    let settings: &CacheSettings = CACHE.get()?;
+    let ttl: Duration = kind.ttl(settings);
+    if ttl.is_zero() {
+        return None;
+    }
+    let path: PathBuf = cache_path(settings, kind, key);
+    if file_age(&path)? >= ttl {
+        debug!("{} cache of {} is expired", kind.name(), key);
+        return None;
+    }
+    let content: String = fs::read_to_string(&path).ok()?;
+    debug!("using cached {}: {}", kind.name(), path.display());
+    Some(content)
+}
+
+/// 内容をキャッシュに保存する（キャッシュが無効な場合や内容が空の場合は何もしない）
+///
+/// 保存に失敗しても取得した内容は使えるため、警告のみとする。
+fn store(kind: CacheKind, key: &str, content: &str) {
+    let Some(settings) = CACHE.get() else {
+        return;
+    };
+    if kind.ttl(settings).is_zero() {
+        return;
+    }
+    if content.trim().is_empty() {
+        debug!("not caching the empty {} of {}", kind.name(), key);
+        return;
+    }
+    let path: PathBuf = cache_path(settings, kind, key);
+    let result: std::io::Result<()> = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, content));
+    if let Err(e) = result {
+        warn!("Failed to cache the {} of {} in {}: {}", kind.name(), key, path.display(), e);
+    }
+}
+
+/// 有効期間内にキャッシュされたエリアの放送局リスト（XML）を返す
+pub fn load_station_list(area_id: &str) -> Option<String> {
+    load(CacheKind::StationList, area_id)
+}
+
+/// エリアの放送局リスト（XML）をキャッシュに保存する
+pub fn store_station_list(area_id: &str, content: &str) {
+    store(CacheKind::StationList, area_id, content)
+}
+
+/// 番組表のキャッシュのキー
+fn schedule_key(area_or_station: &str, date: NaiveDate) -> String {
+    format!("{}_{}", area_or_station, date.format("%Y%m%d"))
+}
+
+/// 有効期間内にキャッシュされた番組表（XML）を返す
+///
+/// # 引数
+/// - `area_or_station`: エリアIDまたは放送局ID
+/// - `date`: 番組表の日付
+pub fn load_schedule(area_or_station: &str, date: NaiveDate) -> Option<String> {
+    load(CacheKind::Schedule, &schedule_key(area_or_station, date))
+}
+
+/// 番組表（XML）をキャッシュに保存する
+pub fn store_schedule(area_or_station: &str, date: NaiveDate, content: &str) {
+    store(CacheKind::Schedule, &schedule_key(area_or_station, date), content)
+}
+
+/// キャッシュ済みのファイルの一覧を返す（種類・キーの順）
+pub fn list_entries() -> Result<Vec<CacheEntry>, Box<dyn Error>> {
+    let Some(settings) = CACHE.get() else {
+        return Ok(Vec::new());
+    };
+    let mut entries: Vec<CacheEntry> = Vec::new();
+    for kind in [CacheKind::StationList, CacheKind::Schedule] {
+        let dir: PathBuf = settings.dir.join(kind.dir());
+        if !dir.exists() {
+            continue;
+        }
+        for entry in fs::read_dir(&dir)? {
+            let path: PathBuf = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("xml") {
+                continue;
+            }
+            let age: Duration = file_age(&path).unwrap_or_default();
+            entries.push(CacheEntry {
+                kind: kind.name().to_string(),
+                key: path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default(),
+                path: path.to_string_lossy().to_string(),
+                age_secs: age.as_secs(),
+                size_bytes: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+                expired: age >= kind.ttl(settings),
+            });
+        }
+    }
+    entries.sort_by(|a, b| (&a.kind, &a.key).cmp(&(&b.kind, &b.key)));
+    Ok(entries)
+}
+
+/// キャッシュ済みのファイルをすべて削除し、削除した件数を返す
+pub fn clear() -> Result<usize, Box<dyn Error>> {
+    let entries: Vec<CacheEntry> = list_entries()?;
+    for entry in &entries {
+        fs::remove_file(&entry.path).map_err(|e| format!("Failed to remove {}: {}", entry.path, e))?;
+    }
+    Ok(entries.len())
+}
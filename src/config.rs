@@ -1,2 +1,556 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::area;
+use crate::auth_handler::AuthProfile;
+use crate::duration::MaxDurationAction;
+
 /// デフォルトのRadikoエリアID
-pub const RADIKO_AREA_ID: &str = "JP13";
\ No newline at end of file
+pub const RADIKO_AREA_ID: &str = "JP13";
+
+/// エリアIDの数（JP1〜JP47）
+pub const AREA_COUNT: u32 = 47;
+
+/// 全エリアのエリアID（JP1〜JP47）を返す
+pub fn all_area_ids() -> impl Iterator<Item = String> {
+    (1..=AREA_COUNT).map(|n| format!("JP{}", n))
+}
+
+/// エリアID（`JP1`〜`JP47`）
+///
+/// 文字列からの変換（`parse`）で形式を検証するため、この型の値は常に正しいエリアIDとなる。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AreaId(String);
+
+impl AreaId {
+    /// エリアIDの文字列（例: `JP13`）
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// エリアの都道府県名
+    pub fn prefecture_name(&self) -> &'static str {
+        area::prefecture_name(&self.0).unwrap_or_default()
+    }
+}
+
+impl FromStr for AreaId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let re: Regex = Regex::new(r"^JP([1-9]|[1-3][0-9]|4[0-7])$").unwrap();
+        if !re.is_match(s) {
+            return Err(format!("Invalid area ID: {} (expected JP1 to JP47)", s));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl fmt::Display for AreaId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// 全エリアのエリアIDと都道府県名を、エリアIDの順（JP1〜JP47）に返す
+pub fn all_areas() -> Vec<(AreaId, &'static str)> {
+    area::AREAS
+        .iter()
+        .map(|(id, name)| (AreaId(id.to_string()), *name))
+        .collect()
+}
+
+/// 録音ファイルの出力ディレクトリ
+pub const OUTPUT_DIR: &str = "output";
+
+/// デフォルトの設定ファイルのパス
+pub const CONFIG_FILE: &str = "radiko_recorder.toml";
+
+/// 設定ファイル（TOML）の内容
+///
+/// 各設定値の優先順位は、コマンドライン引数 > 環境変数 > 設定ファイル > 組み込みのデフォルト値となる。
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// エリアID
+    pub area_id: Option<String>,
+    /// 録音ファイルの出力ディレクトリ
+    pub output_dir: Option<String>,
+    /// 作業用ファイルを置くディレクトリ（省略時は出力ファイルと同じディレクトリ）
+    pub temp_dir: Option<String>,
+    /// 録音開始時に出力ディレクトリと作業用ディレクトリに必要な空き容量（MB、0 の場合は確認しない）
+    pub min_free_space_mb: u64,
+    /// 同じ放送局・時間帯の録音が出力ディレクトリにある場合も録音し直す
+    pub overwrite: bool,
+    /// 要求された録音時間に関係なく適用する、録音時間の上限（整数は分、`6h` などの形式も可）
+    pub max_duration: Option<DurationValue>,
+    /// `max_duration` を超える録音時間が要求された場合の扱い（`clamp` または `reject`）
+    pub max_duration_action: MaxDurationAction,
+    /// 録音ファイル名の設定
+    pub filename: FilenameConfig,
+    /// 認可処理の設定
+    pub auth: AuthConfig,
+    /// HTTP 通信の設定
+    pub http: HttpConfig,
+    /// Radiko API のエンドポイントの設定
+    pub endpoints: EndpointConfig,
+    /// ストリームの設定
+    pub stream: StreamConfig,
+    /// 常駐モードの設定
+    pub daemon: DaemonConfig,
+    /// 毎週の録音定義
+    pub recurring: Vec<RecurringConfig>,
+    /// ログ出力の設定
+    pub log: LogConfig,
+    /// 録音ファイルに埋め込むメタデータの設定
+    pub metadata: MetadataConfig,
+    /// 名前付きの録音プロファイル（`--profile` で指定する）
+    pub profiles: HashMap<String, ProfileConfig>,
+    /// 録音後の検証の設定
+    pub verify: VerifyConfig,
+    /// キャッシュの設定
+    pub cache: CacheConfig,
+    /// 放送局ごとの設定（`[stations.<放送局ID>]`、全体の設定より優先する）
+    pub stations: HashMap<String, StationConfig>,
+}
+
+impl Config {
+    /// 放送局ごとの設定で上書きした、埋め込むメタデータの設定を返す
+    ///
+    /// 放送局ごとの設定で指定したタグのみを上書きし、それ以外は全体の設定（`[metadata]`）を使う。
+    pub fn metadata_for(&self, station_id: &str) -> MetadataConfig {
+        let global: &MetadataConfig = &self.metadata;
+        let Some(station) = self.stations.get(station_id) else {
+            return global.clone();
+        };
+        let overrides: &MetadataConfig = &station.metadata;
+        MetadataConfig {
+            title: overrides.title.clone().or_else(|| global.title.clone()),
+            artist: overrides.artist.clone().or_else(|| global.artist.clone()),
+            album: overrides.album.clone().or_else(|| global.album.clone()),
+            genre: overrides.genre.clone().or_else(|| global.genre.clone()),
+            date: overrides.date.clone().or_else(|| global.date.clone()),
+        }
+    }
+
+    /// 放送局ごとの設定で指定された録音プロファイル名
+    pub fn profile_for(&self, station_id: &str) -> Option<&str> {
+        self.stations.get(station_id).and_then(|s| s.profile.as_deref())
+    }
+}
+
+/// 放送局ごとの設定
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StationConfig {
+    /// 録音プロファイル名（`--profile` と同じ名前を指定する）
+    pub profile: Option<String>,
+    /// 埋め込むメタデータ（指定したタグのみ `[metadata]` を上書きする）
+    pub metadata: MetadataConfig,
+}
+
+/// キャッシュの設定
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// キャッシュを置くディレクトリ
+    pub dir: String,
+    /// 放送局リストのキャッシュの有効期間（時間、0 の場合はキャッシュしない）
+    pub station_list_ttl_hours: u64,
+    /// 番組表のキャッシュの有効期間（時間、0 の場合はキャッシュしない）
+    pub schedule_ttl_hours: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            dir: "cache".to_string(),
+            station_list_ttl_hours: 0,
+            schedule_ttl_hours: 0,
+        }
+    }
+}
+
+/// 録音後の検証の設定
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct VerifyConfig {
+    /// 番組単位の録音で、録音ファイルの再生時間と番組表の放送時間の差として許容する秒数
+    pub duration_tolerance_secs: u64,
+    /// 終了時に警告する録音ファイルのサイズの下限（KB、0 の場合は確認しない）
+    pub min_output_kb: u64,
+}
+
+impl Default for VerifyConfig {
+    fn default() -> Self {
+        Self {
+            duration_tolerance_secs: 30,
+            min_output_kb: 10,
+        }
+    }
+}
+
+/// 録音プロファイル（再エンコードのエンコード形式・ビットレート・チャンネル数の組み合わせ）
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProfileConfig {
+    /// エンコード形式（`aac`, `mp3`, `opus`）
+    pub codec: String,
+    /// ビットレート（例: `48k`）
+    pub bitrate: String,
+    /// チャンネル数（省略時は元のストリームと同じ）
+    pub channels: Option<u32>,
+}
+
+/// 録音ファイルに埋め込むメタデータの設定
+///
+/// 指定したタグは番組情報から求めた値の代わりに使い、空文字列を指定したタグは埋め込まない。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MetadataConfig {
+    /// タイトル（省略時は番組名）
+    pub title: Option<String>,
+    /// アーティスト（省略時は出演者）
+    pub artist: Option<String>,
+    /// アルバム（省略時は放送局名）
+    pub album: Option<String>,
+    /// ジャンル（省略時は埋め込まない）
+    pub genre: Option<String>,
+    /// 放送日（省略時は番組表上の放送日、`YYYY-MM-DD` 形式）
+    pub date: Option<String>,
+}
+
+/// 録音ファイル名の設定
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FilenameConfig {
+    /// ファイル名（拡張子を除く）のテンプレート
+    ///
+    /// `{station_id}`, `{start}`, `{recorded_at}`, `{title}` はそれぞれ放送局ID、録音開始日時、
+    /// 録音を実行した日時、番組名に置換される。
+    pub template: String,
+    /// ファイル名に使えない文字の置換文字列（空文字列の場合は削除する）
+    pub replacement: String,
+    /// ファイル名（拡張子を除く）の最大バイト数（超える場合は番組名から切り詰める、0 の場合は切り詰めない）
+    pub max_bytes: usize,
+}
+
+impl Default for FilenameConfig {
+    fn default() -> Self {
+        Self {
+            template: "{station_id}_{recorded_at}".to_string(),
+            replacement: "_".to_string(),
+            max_bytes: 200,
+        }
+    }
+}
+
+/// 認可処理の設定
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// 認可処理を省略する（認可が不要なストリームやテスト用のモックサーバ向け）
+    pub skip: bool,
+    /// 認可リクエストで名乗るアプリの種類（省略時は `pc_html5`）
+    pub profile: AuthProfile,
+    /// 認可リクエストのヘッダに追加・上書きするヘッダ
+    pub headers: HashMap<String, String>,
+    /// 組み込みの認可キーの代わりに使う認可キー
+    ///
+    /// `hex:` で始まる場合は 16 進数として、`file:` で始まる場合はファイルの内容として、
+    /// それ以外は文字列のバイト列として扱う。
+    pub key: Option<String>,
+    /// 認可処理の代わりに使う、ブラウザなどで取得済みの認可トークン
+    ///
+    /// 指定した場合は認可処理を行わずにこのトークンで録音する。トークンには有効期限がある。
+    pub token: Option<String>,
+    /// 録音前に、認可トークンでストリームを取得できるかの確認を省略する
+    pub skip_eligibility_check: bool,
+    /// 認可の前に、放送局が指定エリアの放送局リストに含まれるかを確認する
+    ///
+    /// 指定しない場合も、聴取可否の確認に失敗したときはエリアを確認してエラーメッセージに含める。
+    pub check_area: bool,
+    /// Radiko プレミアム会員のメールアドレス
+    pub premium_mail: Option<String>,
+    /// Radiko プレミアム会員のパスワード
+    pub premium_password: Option<String>,
+    /// プレミアム会員として認可する条件（省略時は `auto`）
+    pub prefer_premium: PreferPremium,
+}
+
+impl AuthConfig {
+    /// プレミアム会員のメールアドレスとパスワード（どちらかが未設定の場合は `None`）
+    pub fn premium_credentials(&self) -> Option<(&str, &str)> {
+        match (self.premium_mail.as_deref(), self.premium_password.as_deref()) {
+            (Some(mail), Some(password)) if !mail.is_empty() && !password.is_empty() => Some((mail, password)),
+            _ => None,
+        }
+    }
+}
+
+/// プレミアム会員として認可する条件
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PreferPremium {
+    /// 匿名で認可し、聴取できなかった場合のみプレミアム会員として認可し直す
+    #[default]
+    Auto,
+    /// 常にプレミアム会員として認可する
+    Always,
+    /// プレミアム会員の設定があっても匿名で認可する
+    Never,
+}
+
+/// ログ出力の設定
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// ログファイルのサイズの上限（MB、0 の場合は上限なし）
+    pub max_size_mb: u64,
+    /// ローテーションしたログファイルを残す数
+    pub max_files: u32,
+    /// ログの日時（各行の日時と日付別のログファイル名）のタイムゾーン
+    pub timezone: LogTimezone,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            max_size_mb: 0,
+            max_files: 5,
+            timezone: LogTimezone::Local,
+        }
+    }
+}
+
+/// ログの日時のタイムゾーン
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTimezone {
+    /// 実行環境のタイムゾーン
+    Local,
+    /// 協定世界時（UTC）
+    Utc,
+    /// 日本標準時（JST）
+    Jst,
+}
+
+/// HTTP 通信の設定
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// 追加で信頼する CA 証明書（PEM 形式）のパス
+    pub ca_cert: Option<String>,
+    /// TLS 証明書の検証を行わない（テスト用）
+    pub insecure: bool,
+    /// プロセス全体で連続して再試行できる回数の上限（省略時は無制限）
+    pub retry_budget: Option<u32>,
+    /// 再試行の予算に 1 分あたりに補充される回数
+    pub retry_refill_per_minute: u32,
+    /// 全エリアの放送局リストを取得する際に同時に行うリクエスト数
+    pub area_concurrency: usize,
+    /// 全エリアの放送局リストを取得する際の、各リクエストの間隔（ミリ秒）
+    pub area_request_delay_ms: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            ca_cert: None,
+            insecure: false,
+            retry_budget: None,
+            retry_refill_per_minute: 6,
+            area_concurrency: 4,
+            area_request_delay_ms: 0,
+        }
+    }
+}
+
+/// Radiko API のエンドポイントの設定（`[endpoints]`）
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct EndpointConfig {
+    /// 放送局リストの API のバージョン（省略時は `v3`）
+    pub station_list_version: StationListVersion,
+}
+
+/// 放送局リストの API のバージョン
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StationListVersion {
+    /// `https://radiko.jp/v2/station/list/<エリアID>.xml`（読み仮名 `ruby` を含まない）
+    V2,
+    /// `https://radiko.jp/v3/station/list/<エリアID>.xml`
+    #[default]
+    V3,
+}
+
+/// ストリームの設定
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StreamConfig {
+    /// 放送局IDごとのストリームURLテンプレート
+    ///
+    /// `{station_id}`, `{ft}`, `{to}` はそれぞれ放送局ID、開始日時、終了日時に置換される。
+    pub url_templates: HashMap<String, String>,
+    /// ffmpeg がストリームを取得する際に追加するヘッダ（User-Agent, Referer など）
+    pub headers: HashMap<String, String>,
+    /// 番組がまだタイムフリーで配信されていない場合に、配信を待ち続ける時間（分、0 の場合は待たない）
+    pub retry_window_minutes: u64,
+    /// 配信を待つ間にプレイリストを確認する間隔（秒）
+    pub retry_interval_secs: u64,
+    /// 録音開始時刻より前から録音する秒数（終了時刻は変えずに録音時間を延ばす）
+    pub pre_roll_secs: u64,
+    /// ffmpeg が失敗した場合に録音をやり直す回数（0 の場合はやり直さない）
+    pub ffmpeg_retries: u32,
+    /// 録音をやり直す ffmpeg の終了コード（省略時は組み込みの設定）
+    pub retry_exit_codes: Option<Vec<i32>>,
+    /// 録音をやり直す ffmpeg のエラー出力の正規表現（省略時は組み込みの設定）
+    pub retry_stderr_patterns: Option<Vec<String>>,
+    /// やり直さずに中止する ffmpeg のエラー出力の正規表現（省略時は組み込みの設定、再試行より優先）
+    pub abort_stderr_patterns: Option<Vec<String>>,
+    /// ffmpeg のスレッド数・HLS 取得のオプション（`[stream.ffmpeg]`）
+    pub ffmpeg: FfmpegTuning,
+    /// 録音ごとに ffmpeg のエラー出力をログファイル（`<録音ファイル名>.ffmpeg.log`）に保存するか
+    pub ffmpeg_log: FfmpegLogMode,
+}
+
+/// 録音ごとの ffmpeg のエラー出力のログファイルを残す条件
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum FfmpegLogMode {
+    /// 保存しない
+    #[default]
+    Off,
+    /// 常に保存する
+    Always,
+    /// 録音に失敗した場合のみ残す
+    OnFailure,
+}
+
+/// ffmpeg のスレッド数・HLS 取得のオプション
+///
+/// 省略した項目は ffmpeg のデフォルト値のまま（オプションを渡さない）とする。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FfmpegTuning {
+    /// エンコードのスレッド数（`-threads`、再エンコードする場合のみ効果がある）
+    pub threads: Option<u32>,
+    /// プレイリストの再取得を試みる回数（HLS の `-max_reload`）
+    pub max_reload: Option<u32>,
+    /// 次のセグメントを並行して取得する（HLS の `-http_multiple`）
+    pub http_multiple: Option<bool>,
+    /// 入力のパケットを溜めておくキューの長さ（`-thread_queue_size`）
+    pub thread_queue_size: Option<u32>,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            url_templates: HashMap::new(),
+            headers: HashMap::new(),
+            retry_window_minutes: 0,
+            retry_interval_secs: 60,
+            pre_roll_secs: 0,
+            ffmpeg_retries: 0,
+            retry_exit_codes: None,
+            retry_stderr_patterns: None,
+            abort_stderr_patterns: None,
+            ffmpeg: FfmpegTuning::default(),
+            ffmpeg_log: FfmpegLogMode::Off,
+        }
+    }
+}
+
+/// 常駐モードの設定
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// 録音済みの回を記録する状態ファイルのパス
+    pub state_file: String,
+    /// 録音待ちの回を保存するファイルのパス（再起動後に読み込む）
+    pub queue_file: String,
+    /// 放送終了から録音を開始するまでの待ち時間（分）
+    pub delay_minutes: i64,
+    /// 停止中に逃した回の扱い
+    pub catch_up: CatchUpPolicy,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            state_file: "daemon_state.json".to_string(),
+            queue_file: "daemon_queue.json".to_string(),
+            delay_minutes: 5,
+            catch_up: CatchUpPolicy::Timeshift,
+        }
+    }
+}
+
+/// 常駐モードの停止中に逃した回の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CatchUpPolicy {
+    /// タイムフリーで遡れる範囲の回を録音する
+    Timeshift,
+    /// 録音しない
+    Skip,
+}
+
+/// 毎週の録音定義（`[[recurring]]`）
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecurringConfig {
+    /// 放送局ID
+    pub station: String,
+    /// エリアID（省略時は全体のエリアID）
+    pub area_id: Option<String>,
+    /// 曜日（Mon, Tue, ...）
+    pub weekday: String,
+    /// 開始時刻（HH:MM）
+    pub time: String,
+    /// 録音時間（整数は分、`1h30m` などの形式も可）
+    pub duration: DurationValue,
+}
+
+/// 設定ファイル中の録音時間（整数または文字列）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum DurationValue {
+    Minutes(i64),
+    Text(String),
+}
+
+impl std::fmt::Display for DurationValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DurationValue::Minutes(m) => write!(f, "{}", m),
+            DurationValue::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl Config {
+    /// 設定ファイルを読み込む
+    ///
+    /// `path` が指定された場合はそのファイルを必ず読み込む。
+    /// 指定されていない場合は `CONFIG_FILE` が存在するときのみ読み込み、
+    /// 存在しなければデフォルト設定を返す。
+    pub fn load(path: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let path: &Path = match path {
+            Some(p) => Path::new(p),
+            None if Path::new(CONFIG_FILE).exists() => Path::new(CONFIG_FILE),
+            None => return Ok(Self::default()),
+        };
+        let content: String = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+        let config: Config = toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse config file {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+}
@@ -0,0 +1,62 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+
+use crate::program::Program;
+
+/// 録音ファイル内の番組の区切りを CUE シートの文字列に変換する
+///
+/// 各トラックの開始位置は、番組の開始日時と録音開始日時の差から求める。
+/// 録音開始より前に始まった番組は、録音の先頭（`00:00:00`）から始まるものとする。
+///
+/// # 引数
+/// - `audio_file`: CUE シートから参照する録音ファイル名
+/// - `station_id`: 放送局ID（アルバムの演奏者として出力する）
+/// - `programs`: 録音に含まれる番組（開始日時の順）
+/// - `recording_start`: 録音開始日時（JST）
+pub fn to_cue(audio_file: &str, station_id: &str, programs: &[Program], recording_start: NaiveDateTime) -> String {
+    let mut lines: Vec<String> = vec![
+        format!("PERFORMER \"{}\"", escape(station_id)),
+        format!("TITLE \"{} {}\"", escape(station_id), recording_start.format("%Y-%m-%d %H:%M")),
+        format!("FILE \"{}\" WAVE", escape(audio_file)),
+    ];
+    for (i, program) in programs.iter().enumerate() {
+        let offset_secs: i64 = (program.ft - recording_start).num_seconds().max(0);
+        lines.push(format!("  TRACK {:02} AUDIO", i + 1));
+        lines.push(format!("    TITLE \"{}\"", escape(&program.title)));
+        if !program.pfm.is_empty() {
+            lines.push(format!("    PERFORMER \"{}\"", escape(&program.pfm)));
+        }
+        lines.push(format!("    INDEX 01 {}", format_index(offset_secs)));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// 録音ファイルと同じ場所に `<録音ファイル名>.cue` を書き出し、そのパスを返す
+pub fn write_cue(
+    audio_path: &str,
+    station_id: &str,
+    programs: &[Program],
+    recording_start: NaiveDateTime,
+) -> Result<String, Box<dyn Error>> {
+    let audio: &Path = Path::new(audio_path);
+    let file_name: String = audio
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .ok_or_else(|| format!("Invalid audio file path: {}", audio_path))?;
+    let cue_path: String = audio.with_extension("cue").to_string_lossy().to_string();
+    fs::write(&cue_path, to_cue(&file_name, station_id, programs, recording_start))?;
+    Ok(cue_path)
+}
+
+/// 秒数を CUE シートの `MM:SS:FF` 形式にする（番組は秒単位のためフレームは常に 0、分は 100 以上になることもある）
+fn format_index(secs: i64) -> String {
+    format!("{:02}:{:02}:00", secs / 60, secs % 60)
+}
+
+/// CUE シートの文字列はエスケープできないため、ダブルクォートをシングルクォートに置き換える
+fn escape(text: &str) -> String {
+    text.replace('"', "'").replace(['\r', '\n'], " ")
+}
@@ -0,0 +1,135 @@
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, Timelike, Utc};
+
+/// タイムフリーで遡って録音できる日数
+pub const TIMESHIFT_DAYS: i64 = 7;
+
+/// Radiko の番組表で 1 日の区切りとなる時刻（時）
+///
+/// 0:00〜4:59 に放送される番組は前日の番組表に含まれる。
+pub const PROGRAM_DAY_START_HOUR: u32 = 5;
+
+/// 日本標準時（JST）の UTC からのオフセット（秒）
+const JST_OFFSET_SECS: i32 = 9 * 3600;
+
+/// 日本標準時（JST, UTC+9）のタイムゾーンを返す
+pub fn jst() -> FixedOffset {
+    FixedOffset::east_opt(JST_OFFSET_SECS).unwrap()
+}
+
+/// 現在時刻を日本標準時で返す
+pub fn now_jst() -> DateTime<FixedOffset> {
+    Utc::now().with_timezone(&jst())
+}
+
+/// 日本標準時の日時として解釈する
+///
+/// Radiko の日時はすべて JST で表記されるため、実行環境のタイムゾーンには依存しない。
+/// JST には夏時間がないため、曖昧な時刻や存在しない時刻は生じない。
+pub fn to_jst(dt: NaiveDateTime) -> DateTime<FixedOffset> {
+    DateTime::from_naive_utc_and_offset(dt - Duration::seconds(JST_OFFSET_SECS as i64), jst())
+}
+
+/// 開始時刻の文字列（YYYYMMDDHHMMSS形式）をパースする
+///
+/// 深夜番組の表記に合わせ、時が 24〜28 の場合は翌日の 0〜4 時として扱う
+/// （番組表の 1 日は `PROGRAM_DAY_START_HOUR` 時に始まるため、29 時以降は受け付けない）。
+/// 例: `20241120253000` は 2024-11-21 01:30:00 となる。
+///
+/// `@<エポック秒>` の形式（例: `@1705323600`）も受け付け、UTC の Unix 時刻として JST に変換する。
+/// この形式はタイムフリーで遡れる範囲（過去 `TIMESHIFT_DAYS` 日以内）にあるかも確認する。
+pub fn parse_start_time(input: &str) -> Result<NaiveDateTime, String> {
+    if let Some(epoch) = input.strip_prefix('@') {
+        return parse_epoch(epoch);
+    }
+    let invalid = || format!("Invalid start time: {} (expected YYYYMMDDHHMMSS or @<epoch>)", input);
+    if input.len() != 14 || !input.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let hour: u32 = input[8..10].parse::<u32>().map_err(|_| invalid())?;
+    if (24..24 + PROGRAM_DAY_START_HOUR).contains(&hour) {
+        // 24 時間を引いた時刻でパースしてから翌日に繰り上げる
+        let normalized: String = format!("{}{:02}{}", &input[..8], hour - 24, &input[10..]);
+        let dt: NaiveDateTime =
+            NaiveDateTime::parse_from_str(&normalized, "%Y%m%d%H%M%S").map_err(|_| invalid())?;
+        return Ok(dt + Duration::days(1));
+    }
+    NaiveDateTime::parse_from_str(input, "%Y%m%d%H%M%S").map_err(|_| invalid())
+}
+
+/// エポック秒を JST の日時に変換し、タイムフリーで遡れる範囲にあるか確認する
+fn parse_epoch(epoch: &str) -> Result<NaiveDateTime, String> {
+    let secs: i64 = epoch
+        .parse::<i64>()
+        .map_err(|_| format!("Invalid start time: @{} (expected @<epoch seconds>)", epoch))?;
+    let start: DateTime<FixedOffset> = DateTime::from_timestamp(secs, 0)
+        .ok_or_else(|| format!("Invalid start time: @{} (out of range)", epoch))?
+        .with_timezone(&jst());
+
+    let now: DateTime<FixedOffset> = now_jst();
+    if start > now || start < now - Duration::days(TIMESHIFT_DAYS) {
+        return Err(format!(
+            "Start time @{} ({}) is outside the timeshift window of the last {} days",
+            epoch,
+            start.format("%Y-%m-%d %H:%M:%S"),
+            TIMESHIFT_DAYS
+        ));
+    }
+    Ok(start.naive_local())
+}
+
+/// 指定日時が属する番組表上の日付を返す
+///
+/// 5:00 より前の時刻は前日の番組表に属する。
+pub fn program_date(dt: NaiveDateTime) -> NaiveDate {
+    if dt.hour() < PROGRAM_DAY_START_HOUR {
+        dt.date() - Duration::days(1)
+    } else {
+        dt.date()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, mo, d).unwrap().and_hms_opt(h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn late_night_hour_rolls_over_to_next_day() {
+        let start: NaiveDateTime = parse_start_time("20241120250000").unwrap();
+        assert_eq!(start, at(2024, 11, 21, 1, 0));
+        // 番組表上は開始日（前日）の番組として扱う
+        assert_eq!(program_date(start), NaiveDate::from_ymd_opt(2024, 11, 20).unwrap());
+
+        // 90 分の番組の終了日時も日付の繰り上がりを含めて正しく求まる
+        let end: DateTime<FixedOffset> = to_jst(start) + Duration::minutes(90);
+        assert_eq!(end.format("%Y%m%d%H%M%S").to_string(), "20241121023000");
+    }
+
+    #[test]
+    fn late_night_hour_range_ends_before_program_day_start() {
+        assert_eq!(parse_start_time("20241120280000").unwrap(), at(2024, 11, 21, 4, 0));
+        assert!(parse_start_time("20241120290000").is_err());
+    }
+
+    #[test]
+    fn nonexistent_start_time_has_clear_error() {
+        let err: String = parse_start_time("20240230120000").unwrap_err();
+        assert_eq!(
+            err,
+            "Invalid start time: 20240230120000 (expected YYYYMMDDHHMMSS or @<epoch>)"
+        );
+        let err: String = parse_start_time("20241120126100").unwrap_err();
+        assert!(err.contains("20241120126100"), "{}", err);
+    }
+
+    #[test]
+    fn dst_gap_of_other_zones_is_valid_in_jst() {
+        // 米国の夏時間の開始で存在しない 2:30 も、JST としては曖昧さなく解釈できる
+        let start: DateTime<FixedOffset> = to_jst(parse_start_time("20240310023000").unwrap());
+        assert_eq!(start.to_rfc3339(), "2024-03-10T02:30:00+09:00");
+    }
+}
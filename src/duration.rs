@@ -0,0 +1,217 @@
+use std::sync::OnceLock;
+
+use log::warn;
+use regex::Regex;
+use serde::Deserialize;
+
+/// 録音時間の上限（秒）
+pub const MAX_DURATION_SECS: i64 = 24 * 60 * 60;
+
+/// `--max-duration` を超える録音時間が要求された場合の扱い
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MaxDurationAction {
+    /// 上限に切り詰めて録音する
+    #[default]
+    Clamp,
+    /// 録音せずにエラーにする
+    Reject,
+}
+
+/// `configure_max_duration` で設定された、運用上の録音時間の上限（秒）と超えた場合の扱い
+static MAX_DURATION: OnceLock<(i64, MaxDurationAction)> = OnceLock::new();
+
+/// 運用上の録音時間の上限を設定する（要求された録音時間に関係なく適用する）
+pub fn configure_max_duration(secs: i64, action: MaxDurationAction) {
+    let _ = MAX_DURATION.set((secs, action));
+}
+
+/// 運用上の録音時間の上限を適用した録音時間（秒）を返す（ログは出さない）
+///
+/// 上限を超える場合は、`MaxDurationAction` に従って上限に切り詰めるか、エラーを返す。
+/// 上限が設定されていない場合はそのまま返す。通信を始める前の検証に使う。
+pub fn capped_duration(secs: i64) -> Result<i64, String> {
+    match MAX_DURATION.get() {
+        Some(&(max_secs, action)) if secs > max_secs => match action {
+            MaxDurationAction::Clamp => Ok(max_secs),
+            MaxDurationAction::Reject => Err(format!(
+                "Duration {} exceeds the maximum of {} (--max-duration)",
+                format_duration(secs),
+                format_duration(max_secs)
+            )),
+        },
+        _ => Ok(secs),
+    }
+}
+
+/// 運用上の録音時間の上限を適用した録音時間（秒）を返す
+///
+/// `capped_duration` と同じく上限を適用し、切り詰めた場合は警告を出す。
+/// 録音ごとに一度だけ、プリロールを含めた実際の録音時間に対して呼び出すこと。
+pub fn apply_max_duration(secs: i64) -> Result<i64, String> {
+    let capped: i64 = capped_duration(secs)?;
+    if capped < secs {
+        warn!(
+            "Clamping duration from {} to {} (--max-duration)",
+            format_duration(secs),
+            format_duration(capped)
+        );
+    }
+    Ok(capped)
+}
+
+/// 録音時間の文字列をパースして秒数を返す
+///
+/// 以下の形式を受け付ける。
+/// - `90`: 整数のみの場合は分として扱う
+/// - `1h30m`, `45m`, `90s`, `1h30m15s`: 単位付き
+/// - `01:30`, `01:30:00`: `HH:MM` または `HH:MM:SS`
+pub fn parse_duration(input: &str) -> Result<i64, String> {
+    let s: &str = input.trim();
+    let secs: i64 = if let Ok(minutes) = s.parse::<i64>() {
+        minutes.checked_mul(60).ok_or_else(|| too_long(input))?
+    } else if s.contains(':') {
+        parse_clock(s).ok_or_else(|| invalid(input))?
+    } else {
+        parse_units(s).ok_or_else(|| invalid(input))?
+    };
+
+    validate_duration(secs).map_err(|_| {
+        if secs <= 0 {
+            format!("Duration must be positive: {}", input)
+        } else {
+            too_long(input)
+        }
+    })
+}
+
+/// 録音時間（秒）が 1 秒以上 `MAX_DURATION_SECS` 以下であることを確認する
+pub fn validate_duration(secs: i64) -> Result<i64, String> {
+    if secs <= 0 {
+        return Err(format!("Duration must be positive: {} seconds", secs));
+    }
+    if secs > MAX_DURATION_SECS {
+        return Err(format!(
+            "Duration too long: {} seconds (maximum is {} hours)",
+            secs,
+            MAX_DURATION_SECS / 3600
+        ));
+    }
+    Ok(secs)
+}
+
+/// 秒数を `1h30m` のような単位付き形式にフォーマットする
+pub fn format_duration(secs: i64) -> String {
+    let (h, m, s) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    let mut out: String = String::new();
+    if h > 0 {
+        out.push_str(&format!("{}h", h));
+    }
+    if m > 0 {
+        out.push_str(&format!("{}m", m));
+    }
+    if s > 0 || out.is_empty() {
+        out.push_str(&format!("{}s", s));
+    }
+    out
+}
+
+/// `HH:MM` または `HH:MM:SS` 形式をパースする
+fn parse_clock(s: &str) -> Option<i64> {
+    let parts: Vec<i64> = s
+        .split(':')
+        .map(|p| p.parse::<i64>().ok().filter(|n| *n >= 0))
+        .collect::<Option<Vec<i64>>>()?;
+    let (h, m, sec): (i64, i64, i64) = match parts[..] {
+        [h, m] if m < 60 => (h, m, 0),
+        [h, m, sec] if m < 60 && sec < 60 => (h, m, sec),
+        _ => return None,
+    };
+    h.checked_mul(3600)?.checked_add(m * 60)?.checked_add(sec)
+}
+
+/// `1h30m15s` のような単位付き形式をパースする
+fn parse_units(s: &str) -> Option<i64> {
+    let re: Regex = Regex::new(r"^(?:(\d+)h)?(?:(\d+)m)?(?:(\d+)s)?$").unwrap();
+    let caps = re.captures(s)?;
+    if caps.iter().skip(1).all(|c| c.is_none()) {
+        return None;
+    }
+    let value = |i: usize| -> Option<i64> {
+        caps.get(i).map_or(Some(0), |m| m.as_str().parse::<i64>().ok())
+    };
+    value(1)?
+        .checked_mul(3600)?
+        .checked_add(value(2)?.checked_mul(60)?)?
+        .checked_add(value(3)?)
+}
+
+fn invalid(input: &str) -> String {
+    format!(
+        "Invalid duration: {} (expected minutes like 90, units like 1h30m, or HH:MM[:SS])",
+        input
+    )
+}
+
+fn too_long(input: &str) -> String {
+    format!(
+        "Duration too long: {} (maximum is {} hours)",
+        input,
+        MAX_DURATION_SECS / 3600
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_duration_boundaries() {
+        assert_eq!(validate_duration(MAX_DURATION_SECS), Ok(MAX_DURATION_SECS));
+        assert_eq!(validate_duration(1), Ok(1));
+        assert!(validate_duration(MAX_DURATION_SECS + 1)
+            .unwrap_err()
+            .starts_with("Duration too long"));
+        assert!(validate_duration(0).unwrap_err().starts_with("Duration must be positive"));
+        assert!(validate_duration(-60).unwrap_err().starts_with("Duration must be positive"));
+    }
+
+    #[test]
+    fn parse_duration_formats() {
+        assert_eq!(parse_duration("90"), Ok(90 * 60));
+        assert_eq!(parse_duration(" 45 "), Ok(45 * 60));
+        assert_eq!(parse_duration("1h30m"), Ok(90 * 60));
+        assert_eq!(parse_duration("1h30m15s"), Ok(90 * 60 + 15));
+        assert_eq!(parse_duration("90s"), Ok(90));
+        assert_eq!(parse_duration("01:30"), Ok(90 * 60));
+        assert_eq!(parse_duration("01:30:00"), Ok(90 * 60));
+        assert_eq!(parse_duration("24:00"), Ok(MAX_DURATION_SECS));
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid_input() {
+        for input in ["", "1:60", "1:30:60", "1:2:3:4", "abc", "1h30x", "-1:30", "1:-30"] {
+            assert!(
+                parse_duration(input).unwrap_err().starts_with("Invalid duration"),
+                "{:?}",
+                input
+            );
+        }
+        for input in ["0", "-5", "0:00", "0m"] {
+            assert!(
+                parse_duration(input).unwrap_err().starts_with("Duration must be positive"),
+                "{:?}",
+                input
+            );
+        }
+        assert!(parse_duration("24:00:01").unwrap_err().starts_with("Duration too long"));
+    }
+
+    #[test]
+    fn parse_duration_overflow_is_an_error() {
+        assert!(parse_duration("9999999999999999:00").is_err());
+        assert!(parse_duration("9999999999999999:00:00").is_err());
+        assert!(parse_duration("9999999999999999999h").is_err());
+        assert!(parse_duration("9223372036854775807").unwrap_err().starts_with("Duration too long"));
+    }
+}
@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::Child;
+use std::thread::{self, JoinHandle};
+
+use log::warn;
+use regex::Regex;
+
+use crate::config::StreamConfig;
+use crate::logger;
+
+/// 再試行する ffmpeg の終了コードの組み込みの設定
+pub const DEFAULT_RETRY_EXIT_CODES: [i32; 1] = [1];
+
+/// 再試行する ffmpeg のエラー出力（正規表現）の組み込みの設定（一時的な通信エラー）
+pub const DEFAULT_RETRY_STDERR_PATTERNS: [&str; 6] = [
+    r"(?i)connection (reset|refused|timed out)",
+    r"(?i)timed? ?out",
+    r"(?i)temporary failure in name resolution",
+    r"(?i)server returned 5(\d\d|xx)",
+    r"(?i)i/o error",
+    r"(?i)end of file",
+];
+
+/// 再試行せずに中止する ffmpeg のエラー出力（正規表現）の組み込みの設定（再試行しても解決しないエラー）
+pub const DEFAULT_ABORT_STDERR_PATTERNS: [&str; 5] = [
+    r"(?i)server returned 40[134]",
+    r"(?i)unauthorized|forbidden",
+    r"(?i)no such file or directory",
+    r"(?i)invalid data found",
+    r"(?i)unknown encoder",
+];
+
+/// 保持する ffmpeg のエラー出力の末尾の大きさ（バイト）
+const STDERR_TAIL_BYTES: usize = 8 * 1024;
+
+/// ffmpeg が失敗した場合に再試行するかを判定する方針
+///
+/// 再試行の回数が上限に達した場合と、エラー出力が中止のパターンに一致する場合は再試行せず、
+/// 再試行のパターンに一致するか、終了コードが再試行する終了コードに含まれる場合に再試行する。
+#[derive(Debug, Clone, Default)]
+pub struct FfmpegRetryPolicy {
+    /// 再試行の回数の上限（0 の場合は再試行しない）
+    pub max_retries: u32,
+    exit_codes: Vec<i32>,
+    retry_patterns: Vec<Regex>,
+    abort_patterns: Vec<Regex>,
+}
+
+impl FfmpegRetryPolicy {
+    /// ストリームの設定から作成する（省略された項目は組み込みの設定を使う）
+    ///
+    /// 正規表現として不正なパターンは警告を出して無視する。
+    pub fn from_config(config: &StreamConfig) -> Self {
+        let defaults = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<String>>();
+        Self {
+            max_retries: config.ffmpeg_retries,
+            exit_codes: config
+                .retry_exit_codes
+                .clone()
+                .unwrap_or_else(|| DEFAULT_RETRY_EXIT_CODES.to_vec()),
+            retry_patterns: compile_patterns(
+                &config
+                    .retry_stderr_patterns
+                    .clone()
+                    .unwrap_or_else(|| defaults(&DEFAULT_RETRY_STDERR_PATTERNS)),
+            ),
+            abort_patterns: compile_patterns(
+                &config
+                    .abort_stderr_patterns
+                    .clone()
+                    .unwrap_or_else(|| defaults(&DEFAULT_ABORT_STDERR_PATTERNS)),
+            ),
+        }
+    }
+
+    /// これまでの再試行の回数、ffmpeg の終了コードとエラー出力から、再試行すべきかを判定する
+    pub fn should_retry(&self, retries: u32, exit_code: Option<i32>, stderr: &str) -> bool {
+        if retries >= self.max_retries {
+            return false;
+        }
+        if self.abort_patterns.iter().any(|re| re.is_match(stderr)) {
+            return false;
+        }
+        if self.retry_patterns.iter().any(|re| re.is_match(stderr)) {
+            return true;
+        }
+        exit_code.is_some_and(|code| self.exit_codes.contains(&code))
+    }
+}
+
+/// 正規表現のパターンをコンパイルする（不正なパターンは警告を出して無視する）
+fn compile_patterns(patterns: &[String]) -> Vec<Regex> {
+    patterns
+        .iter()
+        .filter_map(|pattern| match Regex::new(pattern) {
+            Ok(re) => Some(re),
+            Err(e) => {
+                warn!("Ignoring invalid ffmpeg stderr pattern {}: {}", pattern, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// ffmpeg のエラー出力をそのまま標準エラー出力に流しつつ、末尾を保持するスレッドを起動する
+///
+/// コンソールへのログ出力を止めている間（`logger::set_console_quiet`）は標準エラー出力に流さない。
+/// `log` が指定された場合は、エラー出力をすべてそのファイルにも書き込む。
+/// 子プロセスの標準エラー出力がパイプでない場合は `None` を返す。
+/// スレッドは ffmpeg の終了時に、保持したエラー出力の末尾を返す。
+pub fn capture_stderr(child: &mut Child, mut log: Option<File>) -> Option<JoinHandle<String>> {
+    let mut stderr = child.stderr.take()?;
+    Some(thread::spawn(move || {
+        let mut tail: Vec<u8> = Vec::new();
+        let mut buf: [u8; 4096] = [0; 4096];
+        while let Ok(n) = stderr.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            if !logger::console_quiet() {
+                let _ = io::stderr().write_all(&buf[..n]);
+            }
+            if let Some(file) = log.as_mut() {
+                let _ = file.write_all(&buf[..n]);
+            }
+            tail.extend_from_slice(&buf[..n]);
+            if tail.len() > STDERR_TAIL_BYTES {
+                tail.drain(..tail.len() - STDERR_TAIL_BYTES);
+            }
+        }
+        String::from_utf8_lossy(&tail).to_string()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_retries: u32) -> FfmpegRetryPolicy {
+        FfmpegRetryPolicy::from_config(&StreamConfig {
+            ffmpeg_retries: max_retries,
+            ..StreamConfig::default()
+        })
+    }
+
+    #[test]
+    fn retries_timeouts_and_server_errors() {
+        let policy: FfmpegRetryPolicy = policy(3);
+        assert!(policy.should_retry(0, Some(1), "Connection timed out"));
+        // 終了コードでは再試行しない場合も、エラー出力で判定する
+        assert!(policy.should_retry(0, Some(8), "HTTP error 503 Service Unavailable: Server returned 5XX Server Error reply"));
+        assert!(policy.should_retry(0, Some(8), "Server returned 502 Bad Gateway"));
+        assert!(policy.should_retry(0, Some(1), "Connection reset by peer"));
+    }
+
+    #[test]
+    fn does_not_retry_client_errors_and_bad_input() {
+        let policy: FfmpegRetryPolicy = policy(3);
+        assert!(!policy.should_retry(0, Some(1), "Server returned 403 Forbidden (access denied)"));
+        assert!(!policy.should_retry(0, Some(1), "Server returned 404 Not Found"));
+        assert!(!policy.should_retry(0, Some(1), "Invalid data found when processing input"));
+        // 中止のパターンは再試行のパターンより優先する
+        assert!(!policy.should_retry(0, Some(1), "Server returned 401 Unauthorized; connection timed out"));
+        // パターンに一致しない場合は終了コードで判定する
+        assert!(policy.should_retry(0, Some(1), "something unexpected"));
+        assert!(!policy.should_retry(0, Some(69), "something unexpected"));
+        assert!(!policy.should_retry(0, None, "something unexpected"));
+    }
+
+    #[test]
+    fn stops_at_the_attempt_limit() {
+        let policy: FfmpegRetryPolicy = policy(2);
+        assert!(policy.should_retry(0, Some(1), "Connection timed out"));
+        assert!(policy.should_retry(1, Some(1), "Connection timed out"));
+        assert!(!policy.should_retry(2, Some(1), "Connection timed out"));
+        assert!(!self::policy(0).should_retry(0, Some(1), "Connection timed out"));
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::process::{Command, Output};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// ffprobe で取得した音声ファイルの情報
+#[derive(Debug, Default)]
+pub struct ProbeInfo {
+    /// 再生時間（秒）
+    pub duration_secs: Option<f64>,
+    /// コンテナに埋め込まれたメタデータタグ（キーは小文字）
+    pub tags: HashMap<String, String>,
+    /// 最初の音声ストリームのコーデック名
+    pub audio_codec: Option<String>,
+}
+
+/// ffprobe で取得したストリームの情報
+#[derive(Debug, Default, Serialize)]
+pub struct StreamInfo {
+    /// コンテナ形式
+    pub format_name: Option<String>,
+    /// 音声コーデック名
+    pub codec: Option<String>,
+    /// サンプルレート（Hz）
+    pub sample_rate: Option<u32>,
+    /// チャンネル数
+    pub channels: Option<u32>,
+    /// ビットレート（bps）
+    pub bit_rate: Option<u64>,
+}
+
+/// ffprobe の JSON 出力（format セクション）
+#[derive(Debug, Deserialize)]
+struct FfprobeOutput {
+    format: Option<FfprobeFormat>,
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    sample_rate: Option<String>,
+    channels: Option<u32>,
+    bit_rate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeFormat {
+    format_name: Option<String>,
+    bit_rate: Option<String>,
+    duration: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+/// ffprobe を実行してファイルの再生時間・メタデータタグ・音声コーデックを取得する
+///
+/// # 引数
+/// - `path`: 調査対象のファイルパス
+pub fn probe(path: &str) -> Result<ProbeInfo, Box<dyn Error>> {
+    let output: Output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration:format_tags:stream=codec_type,codec_name",
+            "-of",
+            "json",
+            path,
+        ])
+        .output()
+        .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed for {}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    let parsed: FfprobeOutput = serde_json::from_slice(&output.stdout)?;
+    debug!("ffprobe result for {}: {:?}", path, parsed);
+    let audio_codec: Option<String> = parsed
+        .streams
+        .into_iter()
+        .find(|s| s.codec_type.as_deref() == Some("audio"))
+        .map(|s| s.codec_name.unwrap_or_default());
+    let info: ProbeInfo = match parsed.format {
+        Some(format) => ProbeInfo {
+            duration_secs: format.duration.and_then(|d| d.parse::<f64>().ok()),
+            tags: format
+                .tags
+                .into_iter()
+                .map(|(k, v)| (k.to_lowercase(), v))
+                .collect(),
+            audio_codec,
+        },
+        None => ProbeInfo {
+            audio_codec,
+            ..ProbeInfo::default()
+        },
+    };
+    Ok(info)
+}
+
+/// ffprobe で HLS などのストリームを調べ、最初の音声ストリームの情報を取得する
+///
+/// ファイルは書き出さない。
+///
+/// # 引数
+/// - `url`: 調査対象のストリームURL
+/// - `auth_token`: 指定した場合、`X-Radiko-AuthToken` ヘッダとして送信する
+pub fn probe_stream(url: &str, auth_token: Option<&str>) -> Result<StreamInfo, Box<dyn Error>> {
+    let mut command: Command = Command::new("ffprobe");
+    command.args(["-v", "error"]);
+    if let Some(auth_token) = auth_token {
+        command.args(["-headers", &format!("X-RADIKO-AUTHTOKEN: {}", auth_token)]);
+    }
+    let output: Output = command
+        .args([
+            "-show_entries",
+            "format=format_name,bit_rate:stream=codec_type,codec_name,sample_rate,channels,bit_rate",
+            "-of",
+            "json",
+            url,
+        ])
+        .output()
+        .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed for {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    let parsed: FfprobeOutput = serde_json::from_slice(&output.stdout)?;
+    debug!("ffprobe result for {}: {:?}", url, parsed);
+    let stream: Option<FfprobeStream> = parsed
+        .streams
+        .into_iter()
+        .find(|s| s.codec_type.as_deref() == Some("audio"));
+    let format_bit_rate: Option<u64> = parsed
+        .format
+        .as_ref()
+        .and_then(|f| f.bit_rate.as_deref())
+        .and_then(|b| b.parse::<u64>().ok());
+    let info: StreamInfo = StreamInfo {
+        format_name: parsed.format.and_then(|f| f.format_name),
+        codec: stream.as_ref().and_then(|s| s.codec_name.clone()),
+        sample_rate: stream
+            .as_ref()
+            .and_then(|s| s.sample_rate.as_deref())
+            .and_then(|r| r.parse::<u32>().ok()),
+        channels: stream.as_ref().and_then(|s| s.channels),
+        // HLS ではストリーム単位のビットレートが取れないことがあるため、コンテナの値で補う
+        bit_rate: stream
+            .as_ref()
+            .and_then(|s| s.bit_rate.as_deref())
+            .and_then(|b| b.parse::<u64>().ok())
+            .or(format_bit_rate),
+    };
+    Ok(info)
+}
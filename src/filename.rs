@@ -0,0 +1,140 @@
+use chrono::{DateTime, FixedOffset, Local};
+use log::info;
+
+/// ファイル名に使えない文字（Windows の制約に合わせる）
+const UNSAFE_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Windows で予約されているファイル名（拡張子を除き、大文字小文字を区別しない）
+const WINDOWS_RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// ファイル名テンプレートのプレースホルダに埋め込む値
+#[derive(Debug)]
+pub struct FilenameFields<'a> {
+    /// 放送局ID（`{station_id}`）
+    pub station_id: &'a str,
+    /// 録音開始日時（`{start}`）
+    pub start_time: DateTime<FixedOffset>,
+    /// 録音を実行した日時（`{recorded_at}`）
+    pub recorded_at: DateTime<Local>,
+    /// 番組名（`{title}`、取得できなかった場合は `None`）
+    pub title: Option<&'a str>,
+}
+
+/// ファイル名に使えない文字を `replacement` に置き換える
+///
+/// 制御文字も置き換え対象とし、末尾のドットと空白は取り除く。
+/// Windows の予約名（`CON`, `NUL` など）になる場合は先頭に `replacement` を付ける。
+/// `replacement` が空文字列の場合は該当する文字を削除する。
+pub fn sanitize(name: &str, replacement: &str) -> String {
+    // 置換文字自体に使えない文字が含まれていれば取り除く
+    let replacement: String = replacement
+        .chars()
+        .filter(|c| !UNSAFE_CHARS.contains(c) && !c.is_control())
+        .collect();
+
+    let mut sanitized: String = String::new();
+    for c in name.chars() {
+        if UNSAFE_CHARS.contains(&c) || c.is_control() {
+            sanitized.push_str(&replacement);
+        } else {
+            sanitized.push(c);
+        }
+    }
+    let mut sanitized: String = sanitized.trim_end_matches(['.', ' ']).to_string();
+
+    let base: &str = sanitized.split('.').next().unwrap_or("");
+    if WINDOWS_RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(base)) {
+        let prefix: &str = if replacement.is_empty() { "_" } else { &replacement };
+        sanitized = format!("{}{}", prefix, sanitized);
+    }
+
+    if sanitized != name {
+        info!("Sanitized file name {:?} to {:?}", name, sanitized);
+    }
+    sanitized
+}
+
+/// 文字列を UTF-8 の文字の途中で切らずに `max_bytes` バイト以内に切り詰める
+pub fn truncate_bytes(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end: usize = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// ファイル名テンプレートのプレースホルダを展開する
+///
+/// 利用できるプレースホルダは `{station_id}`, `{start}`, `{recorded_at}`, `{title}` で、
+/// 日時は `YYYYMMDDHHMMSS` 形式になる。番組名が取得できない場合、`{title}` は `untitled` になる。
+/// 各値は `sanitize` で置換してから埋め込み、展開後のファイル名全体も予約名の確認を行う。
+///
+/// 展開後のファイル名が `max_bytes` バイトを超える場合は、まず番組名を、それでも超える場合は
+/// ファイル名全体を文字の途中で切らずに切り詰める（`max_bytes` が 0 の場合は切り詰めない）。
+pub fn expand_template(template: &str, fields: &FilenameFields, replacement: &str, max_bytes: usize) -> String {
+    let title: String = sanitize(fields.title.unwrap_or("untitled"), replacement);
+    let expand = |title: &str| -> String {
+        let expanded: String = template
+            .replace("{station_id}", &sanitize(fields.station_id, replacement))
+            .replace("{start}", &fields.start_time.format("%Y%m%d%H%M%S").to_string())
+            .replace("{recorded_at}", &fields.recorded_at.format("%Y%m%d%H%M%S").to_string())
+            .replace("{title}", title);
+        sanitize(&expanded, replacement)
+    };
+    let expanded: String = expand(&title);
+    if max_bytes == 0 || expanded.len() <= max_bytes {
+        return expanded;
+    }
+
+    // 番組名が複数回使われている場合も考慮し、超過分を番組名の出現回数で割り振る
+    let excess: usize = expanded.len() - max_bytes;
+    let occurrences: usize = template.matches("{title}").count();
+    let mut truncated: String = expanded;
+    if occurrences > 0 {
+        let per_title: usize = excess.div_ceil(occurrences);
+        truncated = expand(truncate_bytes(&title, title.len().saturating_sub(per_title)));
+    }
+    if truncated.len() > max_bytes {
+        truncated = sanitize(truncate_bytes(&truncated, max_bytes), replacement);
+    }
+    info!("Truncated file name to {} bytes: {:?}", truncated.len(), truncated);
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_bytes_keeps_japanese_characters_whole() {
+        // 「日曜」はそれぞれ 3 バイト、「天国」の後に ASCII が続く
+        let title: &str = "日曜天国ABC";
+        for max_bytes in 0..=title.len() + 1 {
+            let truncated: &str = truncate_bytes(title, max_bytes);
+            assert!(truncated.len() <= max_bytes, "{} bytes for limit {}", truncated.len(), max_bytes);
+            assert!(title.starts_with(truncated));
+            // 切り詰めで失うのは最大で 1 文字分（3 バイト未満）
+            assert!(truncated.len() + 3 > max_bytes.min(title.len()));
+        }
+        assert_eq!(truncate_bytes(title, 5), "日");
+        assert_eq!(truncate_bytes(title, 6), "日曜");
+        assert_eq!(truncate_bytes(title, 13), "日曜天国A");
+        assert_eq!(truncate_bytes(title, 2), "");
+    }
+
+    #[test]
+    fn truncate_bytes_handles_four_byte_characters() {
+        let title: &str = "🎵ラジオ🎵";
+        assert_eq!(truncate_bytes(title, 3), "");
+        assert_eq!(truncate_bytes(title, 4), "🎵");
+        assert_eq!(truncate_bytes(title, 13), "🎵ラジオ");
+        assert_eq!(truncate_bytes(title, 16), "🎵ラジオ");
+        assert_eq!(truncate_bytes(title, 17), title);
+    }
+}
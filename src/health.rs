@@ -0,0 +1,188 @@
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use log::{debug, info, warn};
+use serde::Serialize;
+
+use crate::recorder::CancelFlag;
+use crate::schema::{self, SCHEMA_VERSION};
+
+/// 常駐モードの状態（ヘルスチェックの応答内容）
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct DaemonStatus {
+    /// 録音中の放送回
+    pub current: Option<JobStatus>,
+    /// 録音待ちの放送回（録音可能になる順）
+    pub queued: Vec<JobStatus>,
+    /// 直近に発生したエラー
+    pub last_error: Option<LastError>,
+    /// 録音中の放送回の ID と、その録音の中止フラグ
+    #[serde(skip)]
+    pub active: HashMap<String, CancelFlag>,
+    /// 起動してからの録音の集計（`/metrics` で返す）
+    #[serde(skip)]
+    pub metrics: DaemonMetrics,
+}
+
+/// 起動してからの録音の集計
+#[derive(Debug, Default, Clone)]
+pub struct DaemonMetrics {
+    /// 終了した録音の数（失敗を含み、録音済みでスキップしたものは含まない）
+    pub recordings_total: u64,
+    /// 失敗した録音の数
+    pub recordings_failed_total: u64,
+    /// 書き出した録音ファイルの合計サイズ（バイト）
+    pub bytes_written_total: u64,
+}
+
+impl DaemonStatus {
+    /// 集計を Prometheus のテキスト形式で返す
+    fn to_prometheus(&self) -> String {
+        let metrics: [(&str, &str, &str, u64); 4] = [
+            ("recordings_total", "counter", "Recordings finished, including failed ones.", self.metrics.recordings_total),
+            ("recordings_failed_total", "counter", "Recordings that failed.", self.metrics.recordings_failed_total),
+            ("bytes_written_total", "counter", "Bytes written to recording files.", self.metrics.bytes_written_total),
+            ("recordings_in_progress", "gauge", "Recordings currently in progress.", self.active.len() as u64),
+        ];
+        metrics
+            .iter()
+            .map(|(name, kind, help, value)| {
+                format!(
+                    "# HELP {prefix}_{name} {help}\n# TYPE {prefix}_{name} {kind}\n{prefix}_{name} {value}\n",
+                    prefix = METRICS_PREFIX
+                )
+            })
+            .collect()
+    }
+}
+
+/// 放送回の状態
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    /// 放送回の ID（`<放送局ID>-<曜日>-<HHMM>-<YYYYMMDD>`）
+    pub id: String,
+    pub station_id: String,
+    /// 放送開始日時（JST）
+    pub start: NaiveDateTime,
+    /// 録音時間（秒）
+    pub duration_secs: i64,
+}
+
+/// 直近に発生したエラー
+#[derive(Debug, Clone, Serialize)]
+pub struct LastError {
+    /// 発生日時（JST）
+    pub at: NaiveDateTime,
+    pub message: String,
+}
+
+/// `/metrics` で返すメトリクス名の接頭辞
+const METRICS_PREFIX: &str = "radiko_recorder";
+
+/// `/metrics` の応答の Content-Type（Prometheus のテキスト形式）
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// リクエストの読み込み・応答の書き込みのタイムアウト
+///
+/// 応答は 1 件ずつ順に処理するため、途中で止まった接続が他のリクエストを塞がないようにする。
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 常駐モードとヘルスチェックのサーバで共有する状態
+pub type SharedStatus = Arc<Mutex<DaemonStatus>>;
+
+/// ヘルスチェック用の HTTP サーバをバックグラウンドで起動する
+///
+/// `127.0.0.1:<port>` で待ち受け、以下のリクエストに JSON で応答する。それ以外のパスには 404 を返す。
+///
+/// - `GET /`, `GET /health`: 常駐モードの状態
+/// - `GET /recordings`: 録音中の放送回の一覧
+/// - `GET /metrics`: 録音の集計（Prometheus のテキスト形式）
+/// - `POST /recordings/<ID>/cancel`: 指定した放送回の録音を中止する（録音ファイルは正常に閉じる）
+pub fn serve(port: u16, status: SharedStatus) -> Result<(), Box<dyn Error>> {
+    let listener: TcpListener = TcpListener::bind(("127.0.0.1", port))
+        .map_err(|e| format!("Failed to listen on port {} for health checks: {}", port, e))?;
+    info!("Health check endpoint listening on http://127.0.0.1:{}/health", port);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result: Result<(), Box<dyn Error>> = stream
+                .map_err(|e| e.into())
+                .and_then(|stream| respond(stream, &status));
+            if let Err(e) = result {
+                warn!("Failed to respond to health check: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// 1 件のリクエストに応答する
+fn respond(mut stream: TcpStream, status: &SharedStatus) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut request_line: String = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    debug!("health check request: {}", request_line.trim_end());
+
+    let mut parts = request_line.split_whitespace();
+    let method: &str = parts.next().unwrap_or("");
+    let path: &str = parts.next().unwrap_or("");
+    let cancel_id: Option<&str> = path
+        .strip_prefix("/recordings/")
+        .and_then(|rest| rest.strip_suffix("/cancel"));
+    if (method, path) == ("GET", "/metrics") {
+        let body: String = status.lock().map_err(|_| "Daemon status lock poisoned")?.to_prometheus();
+        return write_response(&mut stream, "200 OK", PROMETHEUS_CONTENT_TYPE, &body);
+    }
+    let (status_line, body): (&str, String) = match (method, path, cancel_id) {
+        ("GET", "/" | "/health", _) => {
+            let snapshot: DaemonStatus = status.lock().map_err(|_| "Daemon status lock poisoned")?.clone();
+            ("200 OK", serde_json::to_string(&schema::versioned(&snapshot))?)
+        }
+        ("GET", "/recordings", _) => {
+            let current: Option<JobStatus> = status.lock().map_err(|_| "Daemon status lock poisoned")?.current.clone();
+            let jobs: Vec<JobStatus> = current.into_iter().collect();
+            ("200 OK", serde_json::to_string(&schema::versioned_list(&jobs))?)
+        }
+        ("POST", _, Some(id)) => {
+            let cancel: Option<CancelFlag> =
+                status.lock().map_err(|_| "Daemon status lock poisoned")?.active.get(id).cloned();
+            match cancel {
+                Some(cancel) => {
+                    info!("Cancellation requested for {}", id);
+                    cancel.store(true, Ordering::SeqCst);
+                    ("202 Accepted", serde_json::json!({ "schema_version": SCHEMA_VERSION, "cancelled": id }).to_string())
+                }
+                None => (
+                    "404 Not Found",
+                    serde_json::json!({ "schema_version": SCHEMA_VERSION, "error": format!("no active recording {}", id) })
+                        .to_string(),
+                ),
+            }
+        }
+        _ => (
+            "404 Not Found",
+            serde_json::json!({ "schema_version": SCHEMA_VERSION, "error": "not found" }).to_string(),
+        ),
+    };
+    write_response(&mut stream, status_line, "application/json", &body)
+}
+
+/// HTTP レスポンスを書き出す
+fn write_response(stream: &mut TcpStream, status_line: &str, content_type: &str, body: &str) -> Result<(), Box<dyn Error>> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status_line,
+        content_type,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
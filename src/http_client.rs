@@ -0,0 +1,275 @@
+use std::error::Error;
+use std::fs;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use reqwest::{Certificate, StatusCode, Url};
+use reqwest::blocking::{Client, ClientBuilder, Request, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+/// Radiko API 呼び出しのタイムアウト（秒）
+const TIMEOUT_SECS: u64 = 5;
+
+/// 429 (Too Many Requests) を受け取った場合の最大リトライ回数
+const MAX_RETRIES: u32 = 3;
+
+/// Retry-After ヘッダがない場合の待機時間（秒）
+const DEFAULT_RETRY_AFTER_SECS: u64 = 5;
+
+/// Retry-After ヘッダで指定された待機時間の上限（秒）
+const MAX_RETRY_AFTER_SECS: u64 = 60;
+
+/// トレース出力で値を伏せるヘッダ（小文字）
+const SENSITIVE_HEADERS: [&str; 5] =
+    ["x-radiko-authtoken", "x-radiko-partialkey", "authorization", "cookie", "set-cookie"];
+
+/// トレース出力で値を伏せる URL のクエリパラメータ
+const SENSITIVE_QUERY_PARAMS: [&str; 1] = ["radiko_session"];
+
+/// HTTP のリクエスト・レスポンスをトレース出力するかどうか
+static TRACE_HTTP: AtomicBool = AtomicBool::new(false);
+
+/// HTTP のリクエスト・レスポンスのトレース出力を切り替える（デフォルトは無効）
+pub fn set_trace(enabled: bool) {
+    TRACE_HTTP.store(enabled, Ordering::Relaxed);
+}
+
+/// プロセス全体で共有する再試行の予算（トークンバケット）
+///
+/// 再試行のたびにトークンを 1 つ消費し、トークンは一定の割合で補充される。
+/// 失敗が続いてトークンが尽きた場合は、補充されるまで再試行を待たせる。
+struct RetryBudget {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RetryBudget {
+    /// 経過時間に応じてトークンを補充する
+    fn refill(&mut self) {
+        let now: Instant = Instant::now();
+        let elapsed: f64 = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.updated = now;
+    }
+}
+
+/// `configure_retry_budget` で設定された再試行の予算（未設定の場合は無制限）
+static RETRY_BUDGET: Mutex<Option<RetryBudget>> = Mutex::new(None);
+
+/// 再試行の予算を設定する
+///
+/// # 引数
+/// - `capacity`: 連続して再試行できる回数の上限
+/// - `refill_per_minute`: 1 分あたりに補充される再試行の回数
+pub fn configure_retry_budget(capacity: u32, refill_per_minute: u32) {
+    let budget: RetryBudget = RetryBudget {
+        capacity: capacity as f64,
+        refill_per_sec: refill_per_minute as f64 / 60.0,
+        tokens: capacity as f64,
+        updated: Instant::now(),
+    };
+    *RETRY_BUDGET.lock().unwrap() = Some(budget);
+}
+
+/// 再試行の前に、予算からトークンを 1 つ消費する
+///
+/// トークンが尽きている場合は補充されるまで待機する。
+/// 補充されない設定（`refill_per_minute = 0`）で尽きた場合はエラーを返す。
+pub fn acquire_retry() -> Result<(), Box<dyn Error>> {
+    loop {
+        let wait: Duration = {
+            let mut guard: MutexGuard<Option<RetryBudget>> = RETRY_BUDGET.lock().unwrap();
+            let Some(budget) = guard.as_mut() else {
+                return Ok(());
+            };
+            budget.refill();
+            if budget.tokens >= 1.0 {
+                budget.tokens -= 1.0;
+                return Ok(());
+            }
+            if budget.refill_per_sec <= 0.0 {
+                return Err("Retry budget exhausted".into());
+            }
+            Duration::from_secs_f64((1.0 - budget.tokens) / budget.refill_per_sec)
+        };
+        warn!("Retry budget exhausted; backing off for {:.1} seconds", wait.as_secs_f64());
+        sleep(wait);
+    }
+}
+
+/// HTTP クライアントの TLS 設定
+struct TlsSettings {
+    /// 追加で信頼する CA 証明書
+    certificates: Vec<Certificate>,
+    /// 証明書の検証を行わない
+    insecure: bool,
+}
+
+/// `configure_tls` で設定された TLS 設定
+static TLS_SETTINGS: OnceLock<TlsSettings> = OnceLock::new();
+
+/// `build_client` で生成するクライアントの TLS 設定を行う
+///
+/// 起動時に 1 度だけ呼び出す。呼び出さない場合はシステムの標準設定を使う。
+///
+/// # 引数
+/// - `ca_cert`: 追加で信頼する CA 証明書（PEM 形式、複数の証明書を含んでもよい）のパス
+/// - `insecure`: 証明書の検証を行わない（テスト用）
+pub fn configure_tls(ca_cert: Option<&str>, insecure: bool) -> Result<(), Box<dyn Error>> {
+    let certificates: Vec<Certificate> = match ca_cert {
+        Some(path) => {
+            let pem: Vec<u8> =
+                fs::read(path).map_err(|e| format!("Failed to read CA certificate {}: {}", path, e))?;
+            let certificates: Vec<Certificate> = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("Failed to parse CA certificate {}: {}", path, e))?;
+            info!("Loaded {} CA certificates from {}", certificates.len(), path);
+            certificates
+        }
+        None => Vec::new(),
+    };
+    if insecure {
+        warn!("TLS certificate verification is disabled; connections are not secure");
+    }
+    TLS_SETTINGS
+        .set(TlsSettings { certificates, insecure })
+        .map_err(|_| "TLS settings are already configured")?;
+    Ok(())
+}
+
+/// Radiko API 呼び出しに共通で利用する HTTP クライアントを生成する
+///
+/// gzip 圧縮を有効にしており、`Accept-Encoding: gzip` を送信して
+/// レスポンスボディは自動的に展開される。
+///
+/// `configure_tls` で設定された CA 証明書・証明書の検証の有無も反映する。
+pub fn build_client() -> Result<Client, Box<dyn Error>> {
+    let mut builder: ClientBuilder = Client::builder()
+        .timeout(Duration::from_secs(TIMEOUT_SECS))
+        .gzip(true);
+    if let Some(tls) = TLS_SETTINGS.get() {
+        for certificate in &tls.certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        builder = builder.danger_accept_invalid_certs(tls.insecure);
+    }
+    let client: Client = builder.build()?;
+    Ok(client)
+}
+
+/// リクエストを送信し、429 (Too Many Requests) の場合は Retry-After に従って再送する
+///
+/// 再送は最大 `MAX_RETRIES` 回まで行い、上限に達した場合は最後のレスポンスをそのまま返す。
+/// 429 以外のステータスコードの判定は呼び出し側で行う。
+pub fn send_with_retry(request: RequestBuilder) -> Result<Response, Box<dyn Error>> {
+    let mut attempt: u32 = 0;
+    loop {
+        let current: RequestBuilder = request
+            .try_clone()
+            .ok_or("Request body cannot be cloned for retry")?;
+        let (client, request): (Client, reqwest::Result<Request>) = current.build_split();
+        let request: Request = request?;
+        if TRACE_HTTP.load(Ordering::Relaxed) {
+            info!("HTTP request: {} {}", request.method(), redact_url(request.url()));
+            trace_headers(request.headers());
+        }
+        let res: Response = client.execute(request)?;
+        if TRACE_HTTP.load(Ordering::Relaxed) {
+            info!("HTTP response: {} {}", res.status(), redact_url(res.url()));
+            trace_headers(res.headers());
+        }
+        if res.status() != StatusCode::TOO_MANY_REQUESTS || attempt >= MAX_RETRIES {
+            return Ok(res);
+        }
+
+        attempt += 1;
+        acquire_retry()?;
+        let wait: Duration = retry_after(&res);
+        warn!(
+            "{} returned 429 Too Many Requests; retrying in {} seconds ({}/{})",
+            redact_url(res.url()),
+            wait.as_secs(),
+            attempt,
+            MAX_RETRIES
+        );
+        sleep(wait);
+    }
+}
+
+/// Retry-After ヘッダ（秒数または HTTP 日付）から待機時間を求める
+fn retry_after(res: &Response) -> Duration {
+    let secs: u64 = res
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| {
+            v.trim().parse::<u64>().ok().or_else(|| {
+                DateTime::parse_from_rfc2822(v.trim())
+                    .ok()
+                    .map(|dt| (dt.with_timezone(&Utc) - Utc::now()).num_seconds().max(0) as u64)
+            })
+        })
+        .unwrap_or(DEFAULT_RETRY_AFTER_SECS);
+    Duration::from_secs(secs.min(MAX_RETRY_AFTER_SECS))
+}
+
+/// ヘッダを 1 行ずつトレース出力する（認可トークンなどの値は伏せる）
+fn trace_headers(headers: &HeaderMap) {
+    for (name, value) in headers {
+        let value: String = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+            mask(value.to_str().unwrap_or(""))
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        info!("  {}: {}", name, value);
+    }
+}
+
+/// URL をログに出力できる形にする（セッションIDなどのクエリパラメータの値は伏せる）
+fn redact_url(url: &Url) -> String {
+    if !url.query_pairs().any(|(key, _)| SENSITIVE_QUERY_PARAMS.contains(&key.as_ref())) {
+        return url.to_string();
+    }
+    let mut redacted: Url = url.clone();
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| {
+            let value: String = if SENSITIVE_QUERY_PARAMS.contains(&key.as_ref()) {
+                mask(&value)
+            } else {
+                value.into_owned()
+            };
+            (key.into_owned(), value)
+        })
+        .collect();
+    redacted.query_pairs_mut().clear().extend_pairs(pairs);
+    redacted.to_string()
+}
+
+/// 値の先頭 4 文字だけを残して伏せる
+fn mask(value: &str) -> String {
+    let visible: String = value.chars().take(4).collect();
+    format!("{}*** ({} chars)", visible, value.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_url_masks_the_premium_session() {
+        let url: Url = Url::parse("https://radiko.jp/v2/api/auth2?radiko_session=0123456789abcdef&x=1").unwrap();
+        let redacted: String = redact_url(&url);
+        assert!(!redacted.contains("0123456789abcdef"), "{}", redacted);
+        assert!(redacted.contains("radiko_session=0123"), "{}", redacted);
+        assert!(redacted.ends_with("&x=1"), "{}", redacted);
+
+        let plain: Url = Url::parse("https://radiko.jp/v2/api/auth1").unwrap();
+        assert_eq!(redact_url(&plain), "https://radiko.jp/v2/api/auth1");
+    }
+}
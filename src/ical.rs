@@ -0,0 +1,95 @@
+use std::error::Error;
+use std::fs;
+
+use chrono::Utc;
+
+use crate::program::{html_to_text, Program};
+
+/// 番組表の日時のタイムゾーン
+const TZID: &str = "Asia/Tokyo";
+
+/// 番組リストを iCalendar 形式（RFC 5545）の文字列に変換する
+///
+/// 日時は `Asia/Tokyo` タイムゾーン付きで出力する。
+pub fn to_ical(station_id: &str, programs: &[Program]) -> String {
+    let dtstamp: String = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines: Vec<String> = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//radiko_recorder//Radiko Recorder//JA".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(station_id)),
+        // 日本標準時は夏時間がないため STANDARD のみ定義する
+        "BEGIN:VTIMEZONE".to_string(),
+        format!("TZID:{}", TZID),
+        "BEGIN:STANDARD".to_string(),
+        "DTSTART:19700101T000000".to_string(),
+        "TZOFFSETFROM:+0900".to_string(),
+        "TZOFFSETTO:+0900".to_string(),
+        "TZNAME:JST".to_string(),
+        "END:STANDARD".to_string(),
+        "END:VTIMEZONE".to_string(),
+    ];
+
+    for program in programs {
+        let mut description: String = html_to_text(&program.desc);
+        if !program.pfm.is_empty() {
+            description = format!("{}\n{}", program.pfm, description);
+        }
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!(
+            "UID:{}-{}@radiko.jp",
+            station_id,
+            program.ft.format("%Y%m%d%H%M%S")
+        ));
+        lines.push(format!("DTSTAMP:{}", dtstamp));
+        lines.push(format!("DTSTART;TZID={}:{}", TZID, program.ft.format("%Y%m%dT%H%M%S")));
+        lines.push(format!("DTEND;TZID={}:{}", TZID, program.to.format("%Y%m%dT%H%M%S")));
+        lines.push(format!("SUMMARY:{}", escape_text(&program.title)));
+        lines.push(format!("LOCATION:{}", escape_text(station_id)));
+        if !description.trim().is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape_text(description.trim())));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold_line(line))
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+/// 番組リストを iCalendar ファイルとして書き出す
+pub fn write_ical(station_id: &str, programs: &[Program], path: &str) -> Result<(), Box<dyn Error>> {
+    fs::write(path, to_ical(station_id, programs))?;
+    Ok(())
+}
+
+/// TEXT 型の値をエスケープする
+fn escape_text(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// 1 行を 75 オクテット以内に折り返し、CRLF を付与する
+///
+/// マルチバイト文字の途中では折り返さない。
+fn fold_line(line: &str) -> String {
+    let mut folded: String = String::new();
+    let mut width: usize = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
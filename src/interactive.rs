@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+
+use chrono::NaiveDate;
+
+use crate::datetime::parse_start_time;
+use crate::duration::{self, parse_duration};
+use crate::program::Program;
+use crate::recorder::Station;
+
+/// プロンプトを表示し、標準入力から 1 行読み込む
+///
+/// 入力が終端（EOF）に達した場合はエラーを返す。
+fn prompt(message: &str) -> Result<String, Box<dyn Error>> {
+    print!("{}", message);
+    io::stdout().flush()?;
+
+    let mut line: String = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err("Input cancelled".into());
+    }
+    Ok(line.trim().to_string())
+}
+
+/// 放送局リストを番号付きメニューとして表示し、選択された放送局を返す
+///
+/// 番号の代わりに放送局名の一部を入力すると、一致する放送局を放送の種類（AM/FM）とともに
+/// 表示して再入力を求める。不正な番号が入力された場合も再入力を求める。
+pub fn select_station(stations: &[Station]) -> Result<&Station, Box<dyn Error>> {
+    if stations.is_empty() {
+        return Err("No stations available".into());
+    }
+
+    for (i, station) in stations.iter().enumerate() {
+        print_station(i + 1, station);
+    }
+
+    loop {
+        let input: String = prompt(&format!("Select a station [1-{}] or search by name: ", stations.len()))?;
+        match input.parse::<usize>() {
+            Ok(n) if (1..=stations.len()).contains(&n) => return Ok(&stations[n - 1]),
+            Ok(_) => println!("Invalid selection: {}", input),
+            Err(_) => {
+                let matches: Vec<&Station> = Station::find_by_name(stations, &input);
+                if matches.is_empty() {
+                    println!("No stations match: {}", input);
+                }
+                for station in matches {
+                    let number: usize = stations.iter().position(|s| s.id == station.id).unwrap_or(0) + 1;
+                    print_station(number, station);
+                }
+            }
+        }
+    }
+}
+
+/// メニューの 1 行（番号・放送局ID・放送局名・放送の種類）を表示する
+fn print_station(number: usize, station: &Station) {
+    println!("{:>3}) {:<12} {} [{}]", number, station.id, station.name, station.band_hint());
+}
+
+/// 番組表から番組を選ぶ場合の、番組表の日付（YYYYMMDD形式）の入力を求める
+///
+/// 空入力の場合は `None`（開始時刻と録音時間を直接入力する）を返す。
+pub fn input_schedule_date() -> Result<Option<NaiveDate>, Box<dyn Error>> {
+    loop {
+        let input: String = prompt("Pick a program from the schedule of (YYYYMMDD), or press Enter to type a start time: ")?;
+        if input.is_empty() {
+            return Ok(None);
+        }
+        match NaiveDate::parse_from_str(&input, "%Y%m%d") {
+            Ok(date) => return Ok(Some(date)),
+            Err(_) => println!("Invalid date: {}", input),
+        }
+    }
+}
+
+/// 番組表を番号付きメニューとして表示し、選択された番組を返す
+///
+/// 空入力の場合は `None`（開始時刻と録音時間を直接入力する）を返す。不正な番号が入力された場合は再入力を求める。
+pub fn select_program(programs: &[Program]) -> Result<Option<&Program>, Box<dyn Error>> {
+    if programs.is_empty() {
+        println!("No programs in the schedule");
+        return Ok(None);
+    }
+
+    for (i, program) in programs.iter().enumerate() {
+        println!(
+            "{:>3}) {}-{} {}",
+            i + 1,
+            program.ft.format("%m/%d %H:%M"),
+            program.to.format("%H:%M"),
+            program.title
+        );
+    }
+
+    loop {
+        let input: String = prompt(&format!(
+            "Select a program [1-{}], or press Enter to type a start time: ",
+            programs.len()
+        ))?;
+        if input.is_empty() {
+            return Ok(None);
+        }
+        match input.parse::<usize>() {
+            Ok(n) if (1..=programs.len()).contains(&n) => return Ok(Some(&programs[n - 1])),
+            _ => println!("Invalid selection: {}", input),
+        }
+    }
+}
+
+/// 録音開始時刻（YYYYMMDDHHMMSS形式）の入力を求める
+pub fn input_start_time() -> Result<String, Box<dyn Error>> {
+    loop {
+        let input: String = prompt("Start time (YYYYMMDDHHMMSS): ")?;
+        match parse_start_time(&input) {
+            Ok(_) => return Ok(input),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// 録音時間の入力を求め、秒数を返す
+///
+/// 空入力の場合は `default_secs` を返す。
+pub fn input_duration(default_secs: i64) -> Result<i64, Box<dyn Error>> {
+    let default_label: String = duration::format_duration(default_secs);
+    loop {
+        let input: String = prompt(&format!("Duration (e.g. 90, 1h30m, 01:30) [{}]: ", default_label))?;
+        if input.is_empty() {
+            return Ok(default_secs);
+        }
+        match parse_duration(&input) {
+            Ok(secs) => return Ok(secs),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
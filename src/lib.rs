@@ -0,0 +1,31 @@
+//! Radiko のタイムフリー放送を録音するためのライブラリ
+
+pub mod area;
+pub mod auth_handler;
+pub mod batch;
+pub mod cache;
+pub mod config;
+pub mod cue;
+pub mod datetime;
+pub mod duration;
+pub mod ffmpeg_retry;
+pub mod ffprobe;
+pub mod filename;
+pub mod health;
+pub mod http_client;
+pub mod ical;
+pub mod interactive;
+pub mod library;
+pub mod log_stats;
+pub mod logger;
+pub mod postprocess;
+pub mod profile;
+pub mod program;
+pub mod recorder;
+pub mod schema;
+pub mod scheduler;
+pub mod segment_downloader;
+pub mod selftest;
+pub mod sidecar;
+pub mod sink;
+pub mod station_diff;
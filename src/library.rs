@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::ffprobe::{self, ProbeInfo};
+
+/// 録音済みファイルとして扱う拡張子
+const RECORDING_EXTENSIONS: &[&str] = &["aac", "m4a", "mp3", "opus", "ogg"];
+
+/// 録音した放送局を記録するタグ
+pub const TAG_STATION: &str = "radiko_station";
+/// 録音した時間帯の開始日時（JST、`YYYYMMDDHHMMSS`）を記録するタグ
+pub const TAG_START: &str = "radiko_start";
+/// 録音した時間帯の終了日時（JST、`YYYYMMDDHHMMSS`）を記録するタグ
+pub const TAG_END: &str = "radiko_end";
+
+/// 録音済みとみなす、要求した時間帯のうち既存の録音と重なる割合
+const RECORDED_OVERLAP_RATIO: f64 = 0.5;
+
+/// 読み取り済みの録音ファイルの放送局と時間帯（ファイルパスごと）
+///
+/// バッチ録音やスケジューラで録音のたびに全ファイルを ffprobe にかけないよう、プロセス内で使い回す。
+/// 録音ファイルかサイドカーファイルが更新された場合は読み直す。
+static RECORDED_WINDOWS: Mutex<BTreeMap<PathBuf, (FileStamp, Option<RecordedWindow>)>> = Mutex::new(BTreeMap::new());
+
+/// 読み取り済みの情報が最新かを判定するための、録音ファイルとサイドカーファイルの更新日時とサイズ
+type FileStamp = [Option<(SystemTime, u64)>; 2];
+
+/// 出力ディレクトリ内の録音済みファイルの情報
+#[derive(Debug, Serialize)]
+pub struct RecordedFile {
+    pub path: String,
+    pub station_id: String,
+    pub title: Option<String>,
+    /// ファイル名に付与された録音日時
+    pub recorded_at: Option<NaiveDateTime>,
+    pub duration_secs: Option<f64>,
+}
+
+/// 一覧の並び替えキー
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortKey {
+    Date,
+    Station,
+    Title,
+    Duration,
+}
+
+/// 出力ディレクトリを走査し、録音済みファイルの一覧を返す
+///
+/// 放送局IDと録音日時はファイル名（`<放送局ID>_<YYYYMMDDHHMMSS>[_...].<拡張子>`）から、
+/// タイトルと再生時間は ffprobe のメタデータから取得する。
+pub fn scan_recordings(output_dir: &Path) -> Result<Vec<RecordedFile>, Box<dyn Error>> {
+    let mut recordings: Vec<RecordedFile> = Vec::new();
+    if !output_dir.exists() {
+        return Ok(recordings);
+    }
+
+    for entry in fs::read_dir(output_dir)? {
+        let path: PathBuf = entry?.path();
+        let is_recording: bool = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| RECORDING_EXTENSIONS.contains(&ext));
+        if !path.is_file() || !is_recording {
+            continue;
+        }
+        recordings.push(read_recording(&path));
+    }
+    Ok(recordings)
+}
+
+/// ファイル名（`<放送局ID>_<YYYYMMDDHHMMSS>[_...].<拡張子>`）から放送局IDと録音日時を読み取る
+///
+/// ファイル名テンプレートで後ろに番組名などが続く場合も、先頭の 2 要素を読み取る。
+pub fn parse_recording_name(path: &Path) -> (String, Option<NaiveDateTime>) {
+    let stem: &str = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let mut parts: std::str::SplitN<char> = stem.splitn(3, '_');
+    let station_id: String = parts.next().unwrap_or_default().to_string();
+    let recorded_at: Option<NaiveDateTime> = parts
+        .next()
+        .and_then(|timestamp| NaiveDateTime::parse_from_str(timestamp, "%Y%m%d%H%M%S").ok());
+    (station_id, recorded_at)
+}
+
+/// 録音済みファイル 1 件分の情報を読み込む
+fn read_recording(path: &Path) -> RecordedFile {
+    let (station_id, recorded_at): (String, Option<NaiveDateTime>) = parse_recording_name(path);
+
+    let path_str: String = path.to_string_lossy().to_string();
+    let info: ProbeInfo = ffprobe::probe(&path_str).unwrap_or_else(|e| {
+        warn!("{}", e);
+        ProbeInfo::default()
+    });
+
+    RecordedFile {
+        path: path_str,
+        station_id,
+        title: info.tags.get("title").cloned(),
+        recorded_at,
+        duration_secs: info.duration_secs,
+    }
+}
+
+/// 録音済みファイルの放送局と時間帯（JST）
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordedWindow {
+    pub path: String,
+    pub station_id: String,
+    pub start: NaiveDateTime,
+    /// 終了日時（録音が途中で途切れている場合は再生時間から求めた日時）
+    pub end: NaiveDateTime,
+}
+
+/// 録音ファイルに埋め込む、放送局と時間帯のタグを返す
+pub fn window_tags(station_id: &str, start: NaiveDateTime, end: NaiveDateTime) -> Vec<(String, String)> {
+    vec![
+        (TAG_STATION.to_string(), station_id.to_string()),
+        (TAG_START.to_string(), start.format("%Y%m%d%H%M%S").to_string()),
+        (TAG_END.to_string(), end.format("%Y%m%d%H%M%S").to_string()),
+    ]
+}
+
+/// 録音済みファイルの放送局と時間帯を、埋め込まれたタグまたはサイドカーファイル（`.txt`）から読み取る
+///
+/// ファイル名の日時は録音を実行した日時の場合があるため使わない。
+/// 再生時間が取得できた場合は、途中で途切れた録音の終了日時をその分だけ早める。
+pub fn recorded_window(path: &Path) -> Option<RecordedWindow> {
+    let path_str: String = path.to_string_lossy().to_string();
+    // ffprobe で読めない場合もサイドカーファイルから判定する
+    let info: Option<ProbeInfo> = ffprobe::probe(&path_str).ok();
+    let parse = |value: &str| NaiveDateTime::parse_from_str(value, "%Y%m%d%H%M%S").ok();
+    let tagged: Option<(String, NaiveDateTime, NaiveDateTime)> = info.as_ref().and_then(|info| {
+        Some((
+            info.tags.get(TAG_STATION)?.clone(),
+            parse(info.tags.get(TAG_START)?)?,
+            parse(info.tags.get(TAG_END)?)?,
+        ))
+    });
+    let (station_id, start, end): (String, NaiveDateTime, NaiveDateTime) =
+        tagged.or_else(|| sidecar_window(&path.with_extension("txt")))?;
+    let end: NaiveDateTime = match info.and_then(|info| info.duration_secs) {
+        Some(secs) => end.min(start + Duration::milliseconds((secs * 1000.0) as i64)),
+        None => end,
+    };
+    Some(RecordedWindow { path: path_str, station_id, start, end })
+}
+
+/// サイドカーファイル（`.txt`）の `Station:` と `Aired:` の行から放送局と放送時間を読み取る
+fn sidecar_window(path: &Path) -> Option<(String, NaiveDateTime, NaiveDateTime)> {
+    let content: String = fs::read_to_string(path).ok()?;
+    let mut station_id: Option<String> = None;
+    let mut aired: Option<(NaiveDateTime, NaiveDateTime)> = None;
+    for line in content.lines() {
+        if let Some(value) = line.strip_prefix("Station: ") {
+            station_id = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("Aired: ") {
+            // `YYYY-MM-DD HH:MM - HH:MM`（終了時刻が開始時刻より前の場合は翌日）
+            let (start, end_time) = value.trim().split_once(" - ")?;
+            let start: NaiveDateTime = NaiveDateTime::parse_from_str(start, "%Y-%m-%d %H:%M").ok()?;
+            let end_time: NaiveTime = NaiveTime::parse_from_str(end_time, "%H:%M").ok()?;
+            let mut end: NaiveDateTime = start.date().and_time(end_time);
+            if end <= start {
+                end += Duration::days(1);
+            }
+            aired = Some((start, end));
+        }
+    }
+    let (start, end) = aired?;
+    Some((station_id?, start, end))
+}
+
+/// 録音ファイルとサイドカーファイルの更新日時とサイズを返す
+fn file_stamp(path: &Path) -> FileStamp {
+    let stamp = |path: &Path| {
+        let metadata: fs::Metadata = fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    };
+    [stamp(path), stamp(&path.with_extension("txt"))]
+}
+
+/// 録音済みファイルの放送局と時間帯を、読み取り済みの場合はそれを使って返す
+fn cached_recorded_window(path: &Path) -> Option<RecordedWindow> {
+    let stamp: FileStamp = file_stamp(path);
+    if let Some((cached_stamp, window)) = RECORDED_WINDOWS.lock().ok().and_then(|windows| windows.get(path).cloned()) {
+        if cached_stamp == stamp {
+            return window;
+        }
+    }
+    let window: Option<RecordedWindow> = recorded_window(path);
+    if let Ok(mut windows) = RECORDED_WINDOWS.lock() {
+        windows.insert(path.to_path_buf(), (stamp, window.clone()));
+    }
+    window
+}
+
+/// 指定した放送局の録音の可能性があるファイルかどうかを、ffprobe を使わずに判定する
+///
+/// 既定のファイル名（`<放送局ID>_<YYYYMMDDHHMMSS>...`）の場合は、先頭の放送局IDが一致するものを対象とする。
+/// それ以外のファイル名（放送局IDを含まないテンプレートなど）は、タグかサイドカーファイルで判定するため対象とする。
+fn may_be_recording_of(path: &Path, station_id: &str) -> bool {
+    match parse_recording_name(path) {
+        (name_station, Some(_)) => name_station == station_id,
+        (_, None) => true,
+    }
+}
+
+/// 出力ディレクトリから、指定した放送局・時間帯（JST）を録音済みのファイルを探す
+///
+/// 要求した時間帯の半分以上が既存の録音と重なる場合に録音済みとみなす。
+/// 放送局と時間帯が読み取れないファイル（タグもサイドカーファイルもないもの）は対象外とする。
+/// 既定のファイル名で別の放送局のものは ffprobe にかけずに除外し、読み取った結果はプロセス内で使い回す。
+pub fn find_recorded(
+    output_dir: &Path,
+    station_id: &str,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> Result<Option<RecordedWindow>, Box<dyn Error>> {
+    if !output_dir.exists() || end <= start {
+        return Ok(None);
+    }
+    let requested_secs: i64 = (end - start).num_seconds();
+    for entry in fs::read_dir(output_dir)? {
+        let path: PathBuf = entry?.path();
+        let is_recording: bool = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| RECORDING_EXTENSIONS.contains(&ext));
+        if !path.is_file() || !is_recording || !may_be_recording_of(&path, station_id) {
+            continue;
+        }
+        let Some(window) = cached_recorded_window(&path) else {
+            continue;
+        };
+        if window.station_id != station_id {
+            continue;
+        }
+        let overlap_secs: i64 = (window.end.min(end) - window.start.max(start)).num_seconds();
+        if overlap_secs as f64 >= requested_secs as f64 * RECORDED_OVERLAP_RATIO {
+            return Ok(Some(window));
+        }
+    }
+    Ok(None)
+}
+
+/// 指定した放送局・時間帯（JST）を録音済みの場合は、スキップする旨をログに出して既存の録音を返す
+///
+/// 出力ディレクトリが読めないなど確認に失敗した場合は、警告を出して `None` を返す（録音を続ける）。
+pub fn skip_if_recorded(
+    output_dir: &Path,
+    station_id: &str,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> Option<RecordedWindow> {
+    match find_recorded(output_dir, station_id, start, end) {
+        Ok(Some(window)) => {
+            info!(
+                "Skipping {} {} - {}: already recorded in {} ({} - {}); use --overwrite to record again",
+                station_id, start, end, window.path, window.start, window.end
+            );
+            Some(window)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            warn!("Failed to check existing recordings in {}: {}", output_dir.display(), e);
+            None
+        }
+    }
+}
+
+/// 録音済みファイルの一覧を指定したキーで並び替える
+pub fn sort_recordings(recordings: &mut [RecordedFile], key: SortKey) {
+    match key {
+        SortKey::Date => recordings.sort_by_key(|r| r.recorded_at),
+        SortKey::Station => recordings.sort_by(|a, b| a.station_id.cmp(&b.station_id)),
+        SortKey::Title => recordings.sort_by(|a, b| a.title.cmp(&b.title)),
+        SortKey::Duration => recordings.sort_by(|a, b| {
+            a.duration_secs
+                .unwrap_or(0.0)
+                .total_cmp(&b.duration_secs.unwrap_or(0.0))
+        }),
+    }
+}
+
+/// 秒数を "HH:MM:SS" 形式にフォーマットする
+pub fn format_duration(duration_secs: Option<f64>) -> String {
+    match duration_secs {
+        Some(secs) => {
+            let total: u64 = secs.round() as u64;
+            format!("{:02}:{:02}:{:02}", total / 3600, total % 3600 / 60, total % 60)
+        }
+        None => "--:--:--".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テストごとの出力ディレクトリを作り直して返す
+    fn fixture_dir(name: &str) -> PathBuf {
+        let dir: PathBuf = std::env::temp_dir().join(format!("radiko_library_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// 中身のない録音ファイルと、放送局・放送時間を記したサイドカーファイルを作る
+    fn write_recording(dir: &Path, file_name: &str, station_id: &str, aired: &str) {
+        let path: PathBuf = dir.join(file_name);
+        fs::write(&path, b"").unwrap();
+        fs::write(path.with_extension("txt"), format!("Title: test\nStation: {}\nAired: {}\n", station_id, aired)).unwrap();
+    }
+
+    fn jst(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y%m%d%H%M%S").unwrap()
+    }
+
+    #[test]
+    fn sidecar_window_reads_station_and_aired() {
+        let dir: PathBuf = fixture_dir("sidecar");
+        let path: PathBuf = dir.join("program.txt");
+        fs::write(&path, "Title: test\nStation: TBS\nAired: 2024-11-20 13:00 - 14:30\n").unwrap();
+        assert_eq!(
+            sidecar_window(&path),
+            Some(("TBS".to_string(), jst("20241120130000"), jst("20241120143000")))
+        );
+
+        // 終了時刻が開始時刻より前の場合は翌日
+        fs::write(&path, "Station: TBS\nAired: 2024-11-20 23:00 - 01:00\n").unwrap();
+        assert_eq!(
+            sidecar_window(&path),
+            Some(("TBS".to_string(), jst("20241120230000"), jst("20241121010000")))
+        );
+
+        fs::write(&path, "Station: TBS\nAired: 2024-11-20 25:00 - 01:00\n").unwrap();
+        assert_eq!(sidecar_window(&path), None);
+        fs::write(&path, "Aired: 2024-11-20 13:00 - 14:30\n").unwrap();
+        assert_eq!(sidecar_window(&path), None);
+        assert_eq!(sidecar_window(&dir.join("missing.txt")), None);
+    }
+
+    #[test]
+    fn find_recorded_requires_half_overlap() {
+        let dir: PathBuf = fixture_dir("overlap");
+        write_recording(&dir, "TBS_20241120130000.m4a", "TBS", "2024-11-20 13:00 - 14:00");
+
+        let found: RecordedWindow = find_recorded(&dir, "TBS", jst("20241120130000"), jst("20241120140000"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.path, dir.join("TBS_20241120130000.m4a").to_string_lossy());
+        assert_eq!((found.start, found.end), (jst("20241120130000"), jst("20241120140000")));
+
+        // 要求した 2 時間のうち 1 時間（ちょうど半分）が重なる
+        assert!(find_recorded(&dir, "TBS", jst("20241120130000"), jst("20241120150000")).unwrap().is_some());
+        // 要求した 2 時間のうち 59 分しか重ならない
+        assert!(find_recorded(&dir, "TBS", jst("20241120130100"), jst("20241120150100")).unwrap().is_none());
+        assert!(find_recorded(&dir, "TBS", jst("20241120140000"), jst("20241120150000")).unwrap().is_none());
+        assert!(find_recorded(&dir, "TBS", jst("20241120140000"), jst("20241120130000")).unwrap().is_none());
+        assert!(find_recorded(&dir.join("missing"), "TBS", jst("20241120130000"), jst("20241120140000"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn find_recorded_matches_the_station_exactly() {
+        let dir: PathBuf = fixture_dir("station");
+        // 放送局IDが別の放送局IDの先頭部分になっている場合（LFR と LFRX など）も区別する
+        write_recording(&dir, "LFRX_20241120130000.m4a", "LFRX", "2024-11-20 13:00 - 14:00");
+        assert!(!may_be_recording_of(&dir.join("LFRX_20241120130000.m4a"), "LFR"));
+        assert!(find_recorded(&dir, "LFR", jst("20241120130000"), jst("20241120140000")).unwrap().is_none());
+        assert!(find_recorded(&dir, "LFRX", jst("20241120130000"), jst("20241120140000")).unwrap().is_some());
+    }
+
+    #[test]
+    fn find_recorded_reads_files_without_the_station_in_the_name() {
+        let dir: PathBuf = fixture_dir("template");
+        // 放送局IDを含まないファイル名テンプレートで保存した録音
+        write_recording(&dir, "Weekend Show 2024-11-20.m4a", "QRR", "2024-11-20 13:00 - 14:00");
+        assert!(may_be_recording_of(&dir.join("Weekend Show 2024-11-20.m4a"), "QRR"));
+        let found: Option<RecordedWindow> =
+            find_recorded(&dir, "QRR", jst("20241120130000"), jst("20241120140000")).unwrap();
+        assert_eq!(found.map(|window| window.station_id), Some("QRR".to_string()));
+        assert!(find_recorded(&dir, "TBS", jst("20241120130000"), jst("20241120140000")).unwrap().is_none());
+    }
+}
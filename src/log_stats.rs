@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, NaiveDateTime, Timelike};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::config::LogTimezone;
+use crate::logger::log_now;
+
+/// ログの集計結果
+#[derive(Debug, Serialize)]
+pub struct LogStats {
+    /// 集計対象の期間の開始日時（ログのタイムゾーン）
+    pub since: NaiveDateTime,
+    /// 成功した録音の数
+    pub succeeded: usize,
+    /// 失敗した録音の数
+    pub failed: usize,
+    /// エラーの分類ごとの件数（多い順）
+    pub error_categories: Vec<ErrorCategory>,
+}
+
+/// エラーの分類とその件数
+#[derive(Debug, Serialize)]
+pub struct ErrorCategory {
+    pub category: String,
+    pub count: usize,
+}
+
+/// ログディレクトリのログファイル（ローテーション済みのファイルを含む）から、
+/// 直近 `days` 日間の録音の成功・失敗の件数とエラーの分類を集計する
+///
+/// 成功は `Successfully recorded` のログ、失敗は `ERROR` レベルのログを 1 件として数える。
+/// ログの日時は `timezone` のタイムゾーンで書かれているものとして扱う。
+pub fn collect_stats(log_dir: &Path, days: u32, timezone: LogTimezone) -> Result<LogStats, Box<dyn Error>> {
+    let now: NaiveDateTime = log_now(timezone);
+    let since: NaiveDateTime = now.with_nanosecond(0).unwrap_or(now) - Duration::days(days as i64);
+    let line_re: Regex =
+        Regex::new(r"^(\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}) (\w+)\s+\[[^\]]*\] \[[^\]]*\] \S+ (.*)$").unwrap();
+
+    let mut succeeded: usize = 0;
+    let mut failed: usize = 0;
+    let mut categories: HashMap<&'static str, usize> = HashMap::new();
+    for path in log_files(log_dir)? {
+        let content: String = fs::read_to_string(&path)?;
+        for line in content.lines() {
+            let Some(caps) = line_re.captures(line) else {
+                continue;
+            };
+            let Ok(at) = NaiveDateTime::parse_from_str(&caps[1], "%Y-%m-%d %H:%M:%S") else {
+                continue;
+            };
+            if at < since {
+                continue;
+            }
+            let message: &str = &caps[3];
+            match &caps[2] {
+                "INFO" if message.starts_with("Successfully recorded ") => succeeded += 1,
+                "ERROR" => {
+                    failed += 1;
+                    *categories.entry(categorize(message)).or_insert(0) += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut error_categories: Vec<ErrorCategory> = categories
+        .into_iter()
+        .map(|(category, count)| ErrorCategory { category: category.to_string(), count })
+        .collect();
+    error_categories.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.category.cmp(&b.category)));
+    Ok(LogStats {
+        since,
+        succeeded,
+        failed,
+        error_categories,
+    })
+}
+
+/// ログディレクトリ内の `.log` ファイルを名前順に返す
+fn log_files(log_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if !log_dir.exists() {
+        return Err(format!("Log directory not found: {}", log_dir.display()).into());
+    }
+    let mut files: Vec<PathBuf> = fs::read_dir(log_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// エラーメッセージを大まかな分類に振り分ける
+fn categorize(message: &str) -> &'static str {
+    let message: String = message.to_lowercase();
+    if message.contains("cancelled") {
+        "cancelled"
+    } else if message.contains("not eligible") {
+        "eligibility"
+    } else if message.contains("auth") {
+        "auth"
+    } else if message.contains("404") || message.contains("not found") || message.contains("no program") {
+        "not found"
+    } else if message.contains("timed out") || message.contains("timeout") {
+        "timeout"
+    } else if message.contains("error sending request") || message.contains("connect") {
+        "network"
+    } else if message.contains("ffmpeg") {
+        "ffmpeg"
+    } else {
+        "other"
+    }
+}
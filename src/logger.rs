@@ -1,22 +1,145 @@
-use chrono::Local;
+use chrono::{Local, NaiveDateTime, Utc};
 use fern::Dispatch;
 use fern::colors::{Color, ColoredLevelConfig};
-use std::fs;
+use std::collections::hash_map::RandomState;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Write};
 use std::path::Path;
 use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use crate::config::{LogConfig, LogTimezone};
+use crate::datetime;
+
+/// サイズの上限を超えたらローテーションするログファイル
+///
+/// `YYYY-MM-DD.log` が上限を超えると `YYYY-MM-DD.1.log` に、
+/// 既存の `YYYY-MM-DD.1.log` は `YYYY-MM-DD.2.log` に、と順に名前を変え、新しいファイルに書き込みます。
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: u32,
+    file: File,
+    written: u64,
+    /// 直前の書き込みが行末で終わっているか（行の途中ではローテーションしない）
+    at_line_start: bool,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: u32) -> io::Result<Self> {
+        let file: File = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written: u64 = file.metadata()?.len();
+        Ok(Self { path, max_bytes, max_files, file, written, at_line_start: true })
+    }
+
+    /// `n` 番目のローテーション済みファイルのパス（例: `2025-01-01.1.log`）
+    fn rotated_path(&self, n: u32) -> PathBuf {
+        let stem: String = self
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.path.with_file_name(format!("{}.{}.log", stem, n))
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        // 最も古いファイルは上書きされて消える
+        for n in (1..self.max_files).rev() {
+            let from: PathBuf = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.at_line_start && self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let n: usize = self.file.write(buf)?;
+        self.written += n as u64;
+        self.at_line_start = buf[..n].ends_with(b"\n");
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// コンソールへのログ出力（と ffmpeg のエラー出力）を止めているか
+static CONSOLE_QUIET: AtomicBool = AtomicBool::new(false);
+
+/// コンソールへのログ出力を止める・再開します（ログファイルへの出力は続けます）。
+///
+/// 進捗を 1 行で表示する場合など、コンソールを他の出力に使う間に止めます。
+pub fn set_console_quiet(quiet: bool) {
+    CONSOLE_QUIET.store(quiet, Ordering::SeqCst);
+}
+
+/// コンソールへのログ出力を止めているかを返します。
+pub fn console_quiet() -> bool {
+    CONSOLE_QUIET.load(Ordering::SeqCst)
+}
+
+/// 実行ごとに異なる短いランダムな ID（16 進数 8 桁）を生成します。
+fn generate_run_id() -> String {
+    // RandomState はプロセスごとにランダムなキーで初期化される
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(process::id());
+    format!("{:08x}", hasher.finish() as u32)
+}
+
+/// 現在のスレッドを識別する文字列（名前がなければスレッドID）を返します。
+fn thread_label() -> String {
+    let current: thread::Thread = thread::current();
+    match current.name() {
+        Some(name) => name.to_string(),
+        None => format!("{:?}", current.id()),
+    }
+}
+
+/// ログの日時に使う、指定したタイムゾーンの現在時刻を返します。
+pub fn log_now(timezone: LogTimezone) -> NaiveDateTime {
+    match timezone {
+        LogTimezone::Local => Local::now().naive_local(),
+        LogTimezone::Utc => Utc::now().naive_utc(),
+        LogTimezone::Jst => datetime::now_jst().naive_local(),
+    }
+}
 
 /// ログをファイルおよびコンソールに出力するロガーを初期化します。
 ///
 /// ログファイルは `./logs/YYYY-MM-DD.log` に保存され、
 /// コンソール出力は色付きでフォーマットされます。
-pub fn setup_logger() -> Result<(), Box<dyn std::error::Error>> {
+/// 複数のプロセスやスレッドのログを区別できるよう、各行にプロセスID・実行ID・スレッドを付与します。
+/// `console_to_stderr` が真の場合、コンソール出力は標準出力ではなく標準エラー出力に書き込みます。
+/// `log_config` でサイズの上限が指定されている場合、上限を超えたログファイルをローテーションします。
+/// ログの日時とログファイル名の日付は `log_config.timezone` のタイムゾーンで表します（録音ファイル名には影響しません）。
+pub fn setup_logger(console_to_stderr: bool, log_config: &LogConfig) -> Result<(), Box<dyn std::error::Error>> {
     // ログディレクトリを作成（存在しない場合）
     let log_dir: &Path = Path::new("logs");
     if !log_dir.exists() {
         fs::create_dir_all(log_dir)?;
     }
+    // 各行に付与するプロセスIDと実行ID
+    let pid: u32 = process::id();
+    let run_id: String = generate_run_id();
+    let console_run_id: String = run_id.clone();
+
     // ログファイルのパス：logs/YYYY-MM-DD.log
-    let log_file: PathBuf  = log_dir.join(format!("{}.log", Local::now().format("%Y-%m-%d")));
+    let timezone: LogTimezone = log_config.timezone;
+    let log_file: PathBuf  = log_dir.join(format!("{}.log", log_now(timezone).format("%Y-%m-%d")));
     
     // コンソール出力用の色設定
     let colors: ColoredLevelConfig = ColoredLevelConfig::new()
@@ -32,36 +155,62 @@ pub fn setup_logger() -> Result<(), Box<dyn std::error::Error>> {
         log::LevelFilter::Info
     };
 
+    // ファイル出力先（サイズの上限があればローテーションする）
+    let file_output: fern::Output = if log_config.max_size_mb > 0 {
+        let writer: Box<dyn Write + Send> = Box::new(RotatingFile::open(
+            log_file,
+            log_config.max_size_mb * 1024 * 1024,
+            log_config.max_files.max(1),
+        )?);
+        writer.into()
+    } else {
+        fern::log_file(log_file)?.into()
+    };
+
+    // コンソール出力先（録音データを標準出力に書き出す場合は標準エラー出力）
+    let console: fern::Output = if console_to_stderr {
+        std::io::stderr().into()
+    } else {
+        std::io::stdout().into()
+    };
+
     // ロガーを設定
     Dispatch::new()
         .level(log_level)
         // ファイル出力
         .chain(
             Dispatch::new()
-                .format(|out, message, record| {
+                .format(move |out, message, record| {
                     out.finish(format_args!(
-                        "{} {:<8} {} {}",
-                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        "{} {:<8} [{}:{}] [{}] {} {}",
+                        log_now(timezone).format("%Y-%m-%d %H:%M:%S"),
                         record.level(),
+                        pid,
+                        run_id,
+                        thread_label(),
                         record.target(),
                         message
                     ))
                 })
-                .chain(fern::log_file(log_file)?)
+                .chain(file_output)
         )
         // コンソール出力
         .chain(
             Dispatch::new()
+                .filter(|_| !console_quiet())
                 .format(move |out, message, record| {
                     out.finish(format_args!(
-                        "{} {} {} {}",
-                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        "{} {} [{}:{}] [{}] {} {}",
+                        log_now(timezone).format("%Y-%m-%d %H:%M:%S"),
                         colors.color(record.level()),
+                        pid,
+                        console_run_id,
+                        thread_label(),
                         record.target(),
                         message
                     ))
                 })
-                .chain(std::io::stdout())
+                .chain(console)
         )
         .apply()?;
     Ok(())
@@ -1,147 +1,2388 @@
-mod auth_handler;
-mod config;
-mod logger;
-mod recorder;
-
-use chrono::{Local, DateTime, NaiveDateTime, TimeZone};
-use clap::{ArgAction, Parser, CommandFactory};
+use chrono::{Duration, DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime};
+use clap::{ArgAction, Parser, CommandFactory, ValueEnum};
+use log::{error, info, warn};
 use regex::Regex;
 use std::error::Error;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::fs;
-use std::path::Path;
+use std::io::{self, IsTerminal, Write};
 use std::process;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+use radiko_recorder::auth_handler::{self, AuthProfile, RadikoAuthHandler};
+use radiko_recorder::batch::{self, BatchJob};
+use radiko_recorder::cache::{self, CacheEntry};
+use radiko_recorder::config::{AreaId, AuthConfig, Config, FfmpegLogMode, FilenameConfig, LogTimezone, MetadataConfig, PreferPremium, StationListVersion, OUTPUT_DIR, RADIKO_AREA_ID};
+use radiko_recorder::duration::{self, MaxDurationAction};
+use radiko_recorder::ffprobe::StreamInfo;
+use radiko_recorder::filename::{self, FilenameFields};
+use radiko_recorder::library::{self, RecordedFile, RecordedWindow, SortKey};
+use radiko_recorder::log_stats::{self, LogStats};
+use radiko_recorder::logger::{self, setup_logger};
+use radiko_recorder::postprocess::{self, NormalizeCodec, NormalizeOptions};
+use radiko_recorder::program::{self, DatedProgram, OnAir, Program};
+use radiko_recorder::recorder::{self, RadikoPlayer, RecordOptions, RecordOutcome, ReencodeOptions, Station};
+use radiko_recorder::schema::{self, SCHEMA_VERSION};
+use radiko_recorder::sidecar::{self, SidecarFormat};
+use radiko_recorder::station_diff::{self, StationDiff};
+use radiko_recorder::{area, cue, datetime, http_client, ical, interactive, profile, scheduler, selftest};
 
-use crate::config::RADIKO_AREA_ID;
-use crate::logger::setup_logger;
-use crate::recorder::RadikoPlayer;
+/// 一覧表示の出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
 
 /// コマンドライン引数を表す構造体
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Radiko Recorder", long_about = None)]
 struct Args {
-    /// エリアID (例: JP13, JP27, etc.)
-    #[arg(short, long, default_value = RADIKO_AREA_ID)]
-    area_id: String,
+    /// 設定ファイルのパス (省略時は radiko_recorder.toml が存在すれば読み込む)
+    #[arg(short, long, env = "RADIKO_CONFIG")]
+    config: Option<String>,
+
+    /// エリアID (例: JP13, JP27, etc.、省略時は JP13)
+    #[arg(short, long, env = "RADIKO_AREA_ID")]
+    area_id: Option<AreaId>,
+
+    /// 録音ファイルの出力ディレクトリ (省略時は output)
+    #[arg(short, long, env = "RADIKO_OUTPUT_DIR")]
+    output_dir: Option<String>,
+
+    /// 分割ダウンロード・追記・音量正規化の作業用ファイルを置くディレクトリ (省略時は出力ファイルと同じディレクトリ)
+    #[arg(long, value_name = "DIR", env = "RADIKO_TEMP_DIR")]
+    temp_dir: Option<String>,
+
+    /// 録音開始時に出力ディレクトリ (--temp-dir を指定した場合はそのディレクトリも) に必要な空き容量 (MB)
+    #[arg(long, value_name = "MB")]
+    min_free_space: Option<u64>,
+
+    /// 要求された録音時間に関係なく適用する録音時間の上限 (整数は分、`6h` などの形式も可)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_duration)]
+    max_duration: Option<i64>,
+
+    /// --max-duration を超える録音時間が要求された場合の扱い (省略時は clamp)
+    #[arg(long, value_enum, value_name = "ACTION")]
+    max_duration_action: Option<MaxDurationAction>,
+
+    /// 同じ放送局・時間帯の録音が出力ディレクトリにある場合も録音し直す (省略時はスキップする)
+    #[arg(long, action = ArgAction::SetTrue)]
+    overwrite: bool,
 
     /// 放送局リストを表示する
     #[arg(short, long, action = ArgAction::SetTrue)]
     station_list: bool,
 
-    /// 放送局ID (録音時は必須)
+    /// 放送局リストの取得に使う API のバージョン (省略時は v3)
+    #[arg(long, value_enum, value_name = "VERSION")]
+    station_list_version: Option<StationListVersion>,
+
+    /// エリアIDと都道府県名の一覧を表示する
+    #[arg(long, action = ArgAction::SetTrue)]
+    list_areas: bool,
+
+    /// --list-areas で、接続元の IP アドレスから判定したエリアのみを表示する
+    #[arg(long, action = ArgAction::SetTrue, requires = "list_areas")]
+    mine: bool,
+
+    /// 認可処理を行い、取得した認可トークンで聴取できるエリアIDの一覧を表示する
+    #[arg(long, action = ArgAction::SetTrue)]
+    probe_area: bool,
+
+    /// 放送局名の一部で放送局を検索し、一致する放送局ID と放送の種類 (AM/FM) を表示する
+    #[arg(long, value_name = "NAME")]
+    find_station: Option<String>,
+
+    /// 保存済みの放送局リスト (JSON) と現在の放送局リストを比較する
+    #[arg(long, value_name = "OLD_JSON")]
+    diff_stations: Option<String>,
+
+    /// エリアの各放送局で現在放送中の番組を表示する
+    #[arg(long, action = ArgAction::SetTrue)]
+    list_programs_now: bool,
+
+    /// --list-programs-now で表示する放送局ID (カンマ区切り、省略時はエリアの全放送局)
+    #[arg(long, value_name = "STATION_IDS", value_delimiter = ',', requires = "list_programs_now")]
+    stations: Vec<String>,
+
+    /// 指定した放送局の番組表を表示する
+    #[arg(long, value_name = "STATION_ID")]
+    schedule: Option<String>,
+
+    /// 番組表の日付 (YYYYMMDD形式、省略時は今日)
+    #[arg(long, value_parser = parse_date)]
+    date: Option<NaiveDate>,
+
+    /// 番組表を取得する日数
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=14))]
+    days: u32,
+
+    /// 番組表を 7 日分取得し、日付ごとにまとめて表示する (JSON では各番組に日付を付ける)
+    #[arg(long, action = ArgAction::SetTrue, requires = "schedule", conflicts_with = "days")]
+    week: bool,
+
+    /// 番組表を iCalendar (.ics) ファイルに書き出す
+    #[arg(long, value_name = "FILE", requires = "schedule")]
+    ical: Option<String>,
+
+    /// 対話形式で放送局を選択して録音する
+    #[arg(short, long, action = ArgAction::SetTrue)]
+    interactive: bool,
+
+    /// 出力ディレクトリ内の録音済みファイルを一覧表示する
+    #[arg(long, action = ArgAction::SetTrue)]
+    list_recorded: bool,
+
+    /// キャッシュ済みの放送局リストの一覧 (経過時間・サイズ) を表示する
+    #[arg(long, action = ArgAction::SetTrue)]
+    cache_info: bool,
+
+    /// キャッシュ済みの放送局リストをすべて削除する
+    #[arg(long, action = ArgAction::SetTrue)]
+    clear_cache: bool,
+
+    /// 録音済みファイルに、番組表から取得した番組情報をメタデータとして書き込む (放送局IDと開始時刻を指定しない場合はファイル名から読み取る)
+    #[arg(long, value_name = "FILE")]
+    retag: Option<String>,
+
+    /// 録音済みファイル一覧の並び順
+    #[arg(long, value_enum, default_value_t = SortKey::Date)]
+    sort: SortKey,
+
+    /// 放送局リスト・番組表・一覧表示の出力形式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// 放送局リスト・番組表で表示する件数の上限
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    limit: Option<u32>,
+
+    /// ログファイルから直近の録音の成功・失敗の件数とエラーの分類を集計して表示する
+    #[arg(long, action = ArgAction::SetTrue)]
+    log_stats: bool,
+
+    /// --log-stats で集計する日数
+    #[arg(long, value_name = "N", default_value_t = 7, value_parser = clap::value_parser!(u32).range(1..), requires = "log_stats")]
+    stats_days: u32,
+
+    /// 中断された録音ファイルに不足している末尾を追記する
+    #[arg(long, value_name = "EXISTING_FILE")]
+    append: Option<String>,
+
+    /// Radiko の認可処理を省略する (認可が必要なストリームは録音できない)
+    #[arg(long, action = ArgAction::SetTrue)]
+    no_auth: bool,
+
+    /// 番組がまだタイムフリーで配信されていない場合に、配信されるまで待つ時間 (分)
+    #[arg(long, value_name = "MINUTES")]
+    retry_window: Option<u64>,
+
+    /// 番組の冒頭が欠けないよう、録音開始時刻より指定秒数前から録音する (終了時刻は変えない)
+    #[arg(long, value_name = "SECONDS")]
+    pre_roll: Option<u64>,
+
+    /// ffmpeg が失敗した場合に録音をやり直す回数
+    #[arg(long, value_name = "N")]
+    ffmpeg_retries: Option<u32>,
+
+    /// 録音ごとに ffmpeg のエラー出力を `<録音ファイル名>.ffmpeg.log` に保存する (on-failure は失敗した場合のみ残す)
+    #[arg(long, value_enum, value_name = "WHEN")]
+    ffmpeg_log: Option<FfmpegLogMode>,
+
+    /// ffmpeg のエンコードのスレッド数 (-threads、再エンコード時のみ効果あり)
+    #[arg(long, value_name = "N")]
+    ffmpeg_threads: Option<u32>,
+
+    /// ffmpeg が HLS のプレイリストの再取得を試みる回数 (-max_reload)
+    #[arg(long, value_name = "N")]
+    hls_max_reload: Option<u32>,
+
+    /// ffmpeg が HLS の次のセグメントを並行して取得する (-http_multiple)
+    #[arg(long, action = ArgAction::SetTrue)]
+    hls_http_multiple: bool,
+
+    /// ffmpeg の入力パケットのキューの長さ (-thread_queue_size)
+    #[arg(long, value_name = "N")]
+    ffmpeg_queue_size: Option<u32>,
+
+    /// 録音をやり直す ffmpeg の終了コード (カンマ区切り、省略時は設定ファイルまたは 1)
+    #[arg(long, value_name = "CODES", value_delimiter = ',')]
+    retry_on_ffmpeg_code: Vec<i32>,
+
+    /// 録音前に、番組が存在し認可トークンでストリームを取得できるか (エリア・会員種別) の確認を省略する
+    #[arg(long, action = ArgAction::SetTrue)]
+    skip_eligibility_check: bool,
+
+    /// 認可の前に、放送局が指定エリアで聴取できるかを放送局リストで確認する
+    #[arg(long, action = ArgAction::SetTrue)]
+    check_area: bool,
+
+    /// 認可キーから指定した位置・長さで部分鍵を作成して表示する (認可処理の調査用、通信は行わない)
+    #[arg(long, num_args = 2, value_names = ["OFFSET", "LENGTH"])]
+    verify_auth_key: Option<Vec<usize>>,
+
+    /// --verify-auth-key で作成した部分鍵と比較する値 (一致しない場合は終了コード 1)
+    #[arg(long, value_name = "KEY", requires = "verify_auth_key")]
+    expected_partial_key: Option<String>,
+
+    /// 組み込みの認可キーの代わりに使う認可キー (`hex:` で始まる場合は 16 進数、`file:` で始まる場合はファイルの内容)
+    #[arg(long, env = "RADIKO_AUTH_KEY", hide_env_values = true)]
+    auth_key: Option<String>,
+
+    /// 認可リクエストで名乗る Radiko のアプリの種類 (省略時は pc_html5)
+    #[arg(long, value_enum, value_name = "PROFILE")]
+    auth_profile: Option<AuthProfile>,
+
+    /// 認可処理を行わずに使う、ブラウザなどで取得済みの認可トークン (--area-id はトークンのエリアに合わせる)
+    #[arg(long, value_name = "TOKEN", env = "RADIKO_AUTH_TOKEN", hide_env_values = true)]
+    resume_token: Option<String>,
+
+    /// Radiko プレミアム会員のメールアドレス
+    #[arg(long, value_name = "MAIL", env = "RADIKO_PREMIUM_MAIL")]
+    premium_mail: Option<String>,
+
+    /// Radiko プレミアム会員のパスワード
+    #[arg(long, value_name = "PASSWORD", env = "RADIKO_PREMIUM_PASSWORD", hide_env_values = true)]
+    premium_password: Option<String>,
+
+    /// プレミアム会員として認可する条件 (auto: 匿名で聴取できない場合のみ、省略時は auto)
+    #[arg(long, value_enum, value_name = "MODE")]
+    prefer_premium: Option<PreferPremium>,
+
+    /// 追加で信頼する CA 証明書 (PEM 形式) のパス
+    #[arg(long, value_name = "PEM")]
+    ca_cert: Option<String>,
+
+    /// TLS 証明書の検証を行わない (テスト用、通信は安全ではなくなる)
+    #[arg(long, action = ArgAction::SetTrue)]
+    insecure: bool,
+
+    /// ログの日時のタイムゾーン (省略時は設定ファイルまたは実行環境のタイムゾーン)
+    #[arg(long, value_enum, value_name = "ZONE")]
+    log_timezone: Option<LogTimezone>,
+
+    /// HTTPのリクエスト・レスポンスのヘッダをログに出力する（認可トークンは伏せる）
+    #[arg(long, action = ArgAction::SetTrue)]
+    trace_http: bool,
+
+    /// 指定した時刻から --until までに放送される番組を、番組ごとのファイルに録音する (YYYYMMDDHHMMSS形式)
+    #[arg(long, value_name = "START", requires = "until", conflicts_with = "append")]
+    since: Option<String>,
+
+    /// --since で録音する時間帯の終了時刻 (YYYYMMDDHHMMSS形式)
+    #[arg(long, value_name = "END", requires = "since")]
+    until: Option<String>,
+
+    /// 出力ディレクトリ内のその放送局の最新の録音の終わりから、現在放送中の番組の前までを番組ごとに録音する
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["since", "append", "program_id", "batch_source", "snap", "start_time"])]
+    since_last: bool,
+
+    /// 番組単位の録音で、録音ファイルの再生時間と番組表の放送時間の差として許容する秒数 (超えると警告する)
+    #[arg(long, value_name = "SECS")]
+    duration_tolerance: Option<u64>,
+
+    /// 番組表の番組IDを指定し、その番組の放送時間ちょうどを録音する (--date で番組表の日付を指定)
+    #[arg(long, value_name = "ID", conflicts_with_all = ["append", "since", "output_to_stdout", "start_time"])]
+    program_id: Option<String>,
+
+    /// 番組表を使い、録音開始時刻をその時刻に放送中の番組の開始時刻に合わせる
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["append", "since", "program_id", "batch_source"])]
+    snap: bool,
+
+    /// --snap に加えて、録音終了時刻をその時刻に放送中の番組の終了時刻に合わせる
+    #[arg(long, action = ArgAction::SetTrue, requires = "snap")]
+    snap_end: bool,
+
+    /// 同じ時間帯を指定した別の放送局からも同時に録音し、再生時間の長い方を残す (もう一方は .alternate を付けて残す)
+    #[arg(
+        long,
+        value_name = "STATION_ID",
+        conflicts_with_all = [
+            "append", "since", "program_id", "batch_source", "segment_minutes", "split_at",
+            "output_to_stdout", "output_fifo", "normalize", "cue", "sidecar"
+        ]
+    )]
+    failover_station: Option<String>,
+
+    /// 録音データをファイルではなく標準出力に ADTS 形式で書き出す (ログは標準エラー出力)
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        conflicts_with_all = ["append", "segment_minutes", "split_at", "segment_downloader", "since", "normalize"]
+    )]
+    output_to_stdout: bool,
+
+    /// 録音データを既存の名前付きパイプ (FIFO) に ADTS 形式で書き出す (Unix のみ)
+    #[arg(
+        long,
+        value_name = "FIFO",
+        conflicts_with_all = [
+            "append", "segment_minutes", "split_at", "segment_downloader", "since", "normalize",
+            "output_to_stdout", "cue", "force_reencode", "program_id", "sidecar"
+        ]
+    )]
+    output_fifo: Option<String>,
+
+    /// 録音に含まれる番組ごとのトラックを記した CUE シート (.cue) を録音ファイルと並べて書き出す
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["append", "segment_minutes", "split_at", "since", "output_to_stdout"])]
+    cue: bool,
+
+    /// 音声をコピーせずに再エンコードして録音する (タイムスタンプが壊れたファイルのシーク不良を防ぐ)
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["append", "output_to_stdout"])]
+    force_reencode: bool,
+
+    /// 再エンコード時のエンコード形式
+    #[arg(long, value_enum, default_value_t = NormalizeCodec::Aac, requires = "force_reencode")]
+    reencode_codec: NormalizeCodec,
+
+    /// 再エンコード時のビットレート
+    #[arg(long, default_value = "128k", requires = "force_reencode")]
+    reencode_bitrate: String,
+
+    /// 録音プロファイル (エンコード形式・ビットレート・チャンネル数の組み合わせ) で再エンコードして録音する
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["append", "output_to_stdout", "output_fifo", "force_reencode"])]
+    profile: Option<String>,
+
+    /// 録音結果 (失敗した場合はエラー) を JSON で標準出力に書き出す (ログは標準エラー出力)
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["append", "since", "output_to_stdout", "output_fifo"])]
+    json: bool,
+
+    /// 番組名・出演者・番組説明を録音ファイルと並べてサイドカーファイルに書き出す
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["append", "output_to_stdout"])]
+    sidecar: Option<SidecarFormat>,
+
+    /// 録音後に音量を正規化する (再エンコードのため処理時間がかかる)
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with = "append")]
+    normalize: bool,
+
+    /// 音量正規化の目標ラウドネス (LUFS)
+    #[arg(long, default_value_t = -16.0, allow_negative_numbers = true, requires = "normalize")]
+    normalize_lufs: f64,
+
+    /// 音量正規化後のエンコード形式
+    #[arg(long, value_enum, default_value_t = NormalizeCodec::Aac, requires = "normalize")]
+    normalize_codec: NormalizeCodec,
+
+    /// 音量正規化後のビットレート
+    #[arg(long, default_value = "128k", requires = "normalize")]
+    normalize_bitrate: String,
+
+    /// 元のファイルを音量正規化後のファイルで置き換える
+    #[arg(long, action = ArgAction::SetTrue, requires = "normalize")]
+    normalize_replace: bool,
+
+    /// バッチファイル (TOML) の各録音を順に実行する
+    #[arg(long, value_name = "FILE", group = "batch_source", conflicts_with_all = ["since", "program_id", "append", "output_to_stdout", "output_fifo", "json"])]
+    batch: Option<String>,
+
+    /// ディレクトリ内の、1 件の録音を 1 ファイルに記述した TOML ファイルの各録音を順に実行する (読み込めないファイルはスキップする)
+    #[arg(long, value_name = "DIR", group = "batch_source", conflicts_with_all = ["since", "program_id", "append", "output_to_stdout", "output_fifo", "json"])]
+    batch_dir: Option<String>,
+
+    /// 録音前に、開始・終了日時、出力ファイル、ストリームURL、分割、メタデータなどの録音計画を表示する
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["append", "output_to_stdout", "output_fifo", "json"])]
+    explain: bool,
+
+    /// --batch・--batch-dir の進捗を 1 行の要約 (完了・失敗・録音中の件数) で表示し、詳細はログファイルのみに書き込む (端末でない場合は無効)
+    #[arg(long, action = ArgAction::SetTrue, requires = "batch_source", conflicts_with = "dry_run")]
+    compact_progress: bool,
+
+    /// --batch・--batch-dir の各録音を検証して結果を表示するのみで、認可・録音は行わない
+    #[arg(long, action = ArgAction::SetTrue, requires = "batch_source")]
+    dry_run: bool,
+
+    /// 設定ファイルの毎週の録音定義に従って常駐録音する
+    #[arg(long, action = ArgAction::SetTrue)]
+    daemon: bool,
+
+    /// 常駐モードで、状態を JSON で返すヘルスチェック用の HTTP サーバを指定したポートで起動する
+    #[arg(long, value_name = "PORT", requires = "daemon")]
+    health_port: Option<u16>,
+
+    /// 録音を指定した分数ごとのファイルに分割する
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i64).range(1..=duration::MAX_DURATION_SECS / 60), conflicts_with = "append")]
+    segment_minutes: Option<i64>,
+
+    /// 録音を指定した位置 (録音開始からの分数、カンマ区切りで昇順) で連番のファイルに分割する
+    #[arg(long, value_name = "M,M,...", value_delimiter = ',', value_parser = clap::value_parser!(i64).range(1..=duration::MAX_DURATION_SECS / 60), conflicts_with_all = ["append", "segment_minutes"])]
+    split_at: Vec<i64>,
+
+    /// ffmpeg の代わりにセグメントを直接ダウンロードして録音する (実験的機能)
+    #[arg(long, action = ArgAction::SetTrue)]
+    segment_downloader: bool,
+
+    /// --segment-downloader のダウンロード速度の上限 (KB/s)
+    #[arg(long, value_name = "KB/s", requires = "segment_downloader", value_parser = clap::value_parser!(u64).range(1..))]
+    rate_limit: Option<u64>,
+
+    /// 短いクリップを録音して動作環境を確認する
+    #[arg(long, action = ArgAction::SetTrue)]
+    selftest: bool,
+
+    /// 録音せずにストリームのコーデック・サンプルレートなどを調べる (開始時刻の省略時は 1 時間前)
+    #[arg(long, action = ArgAction::SetTrue)]
+    probe_only: bool,
+
+    /// 放送局ID (録音時は必須、省略時は環境変数 RADIKO_STATION_ID)
     station_id: Option<String>,
 
     /// 録音開始時刻 (YYYYMMDDHHMMSS形式、録音時は必須)
     start_time: Option<String>,
 
-    /// 録音時間（分）
-    #[arg(default_value_t = 60)]
-    duration_minutes: i32,
+    /// 録音時間 (整数のみは分、1h30m や HH:MM[:SS] 形式も可)
+    #[arg(default_value = "60", value_parser = duration::parse_duration)]
+    duration: i64,
+}
+
+/// 録音後の処理
+#[derive(Default)]
+struct PostProcess {
+    /// 音量を正規化する
+    normalize: Option<NormalizeOptions>,
+    /// 番組ごとのトラックを記した CUE シートを書き出す
+    cue: bool,
+    /// 番組情報をサイドカーファイルに書き出す
+    sidecar: Option<SidecarFormat>,
+}
+
+/// 各コマンドで共通して使う実行時の設定
+///
+/// コマンドライン引数・環境変数・設定ファイル・デフォルト値から解決した値を保持する。
+struct Context {
+    area_id: String,
+    output_dir: String,
+    config: Config,
+    /// 録音前に録音計画を表示する
+    explain: bool,
+    /// この実行で出力した録音ファイル（終了時のサイズの確認に使う）
+    outputs: Mutex<Vec<String>>,
+    /// この実行で録音済みとしてスキップした録音（`--json` の出力に使う）
+    skipped: Mutex<Vec<RecordedWindow>>,
+}
+
+/// 小さすぎる録音ファイルがあった場合の終了コード
+const SMALL_OUTPUT_EXIT_CODE: i32 = 3;
+
+/// 録音結果の出力ファイル（分割録音の場合は各ファイル）を、終了時の確認の対象に加える
+fn track_outputs(ctx: &Context, final_path: &str, outcome: &RecordOutcome) {
+    let mut outputs: MutexGuard<Vec<String>> = ctx.outputs.lock().unwrap_or_else(|e| e.into_inner());
+    if outcome.segments.is_empty() {
+        outputs.push(final_path.to_string());
+    } else {
+        outputs.extend(outcome.segments.iter().cloned());
+    }
+}
+
+/// この実行で出力した録音ファイルのうち、サイズが下限（`[verify] min_output_kb`）未満のものを一覧表示する
+///
+/// 0 バイトや数 KB の録音ファイルは、ほぼ確実に録音の失敗を示す。
+/// 該当するファイルがあった場合は `true` を返す。
+fn report_small_outputs(ctx: &Context) -> bool {
+    let min_bytes: u64 = ctx.config.verify.min_output_kb * 1024;
+    if min_bytes == 0 {
+        return false;
+    }
+    let outputs: MutexGuard<Vec<String>> = ctx.outputs.lock().unwrap_or_else(|e| e.into_inner());
+    let small: Vec<(&String, u64)> = outputs
+        .iter()
+        .map(|path| (path, fs::metadata(path).map(|m| m.len()).unwrap_or(0)))
+        .filter(|(_, size)| *size < min_bytes)
+        .collect();
+    if small.is_empty() {
+        return false;
+    }
+    warn!(
+        "{} of {} output files are smaller than {} KB and are probably empty or failed recordings:",
+        small.len(),
+        outputs.len(),
+        ctx.config.verify.min_output_kb
+    );
+    for (path, size) in &small {
+        warn!("  {} ({} bytes)", path, size);
+    }
+    true
+}
+
+/// 録音モードの結果を出力して終了する
+///
+/// 失敗した場合は終了コード 1、小さすぎる録音ファイルがあった場合は `SMALL_OUTPUT_EXIT_CODE` で終了する。
+fn exit_after_recording(ctx: &Context, result: Result<(), Box<dyn Error>>) {
+    let has_small_outputs: bool = report_small_outputs(ctx);
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+    if has_small_outputs {
+        process::exit(SMALL_OUTPUT_EXIT_CODE);
+    }
 }
 
-/// エリアIDが正しい形式（JP13～JP47）かチェックする
+/// エリアIDが正しい形式（JP1～JP47）かチェックする
 fn is_valid_area_id(area_id: &str) -> bool {
-    let re: Regex = Regex::new(r"^JP([1-9]|[1-3][0-9]|4[0-7])$").unwrap();
-    re.is_match(area_id)
+    area_id.parse::<AreaId>().is_ok()
+}
+
+/// 日付の文字列（YYYYMMDD形式）をパースする
+fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y%m%d").map_err(|_| format!("Invalid date: {} (expected YYYYMMDD)", s))
 }
 
-/// 放送局IDが正しい形式（大文字の英数字のみ）かチェックする
+/// 放送局IDが正しい形式（大文字の英数字、NHK FM の `JOAK-FM` のようにハイフンで区切ったものも可）かチェックする
 fn is_valid_station_id(station_id: &str) -> bool {
-    let re: Regex = Regex::new(r"^[A-Z0-9]+$").unwrap();
+    let re: Regex = Regex::new(r"^[A-Z0-9]+(-[A-Z0-9]+)*$").unwrap();
     re.is_match(station_id)
 }
 
+/// エリアIDと都道府県名の一覧を表示する
+///
+/// `mine` が指定された場合は、接続元の IP アドレスから判定したエリアのみを表示する。
+fn show_areas(mine: bool, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let areas: Vec<(&str, &str)> = if mine {
+        let detected: String = area::detect_area().map_err(|e| {
+            format!(
+                "{}\nRun `radiko_recorder --list-areas` and pick the area ID of your prefecture, \
+                 or open https://radiko.jp/area in a browser and look for the JPxx code.",
+                e
+            )
+        })?;
+        area::AREAS.iter().copied().filter(|(id, _)| *id == detected).collect()
+    } else {
+        area::AREAS.to_vec()
+    };
+    match format {
+        OutputFormat::Json => {
+            let list: Vec<serde_json::Value> = areas
+                .iter()
+                .map(|(id, name)| serde_json::json!({ "schema_version": SCHEMA_VERSION, "id": id, "name": name }))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&list)?);
+        }
+        OutputFormat::Text => {
+            for (id, name) in areas {
+                println!("{}\t{}", id, name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 認可トークンで聴取できるエリアの一覧を表示する
+///
+/// 設定のエリアIDで認可処理を行い、auth2 の応答で示されたエリアIDを表示する。
+fn probe_areas(ctx: &Context, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    if ctx.config.auth.skip {
+        return Err("--probe-area requires authentication; remove --no-auth".into());
+    }
+    let auth: &AuthConfig = &ctx.config.auth;
+    let auth_handler: RadikoAuthHandler =
+        RadikoAuthHandler::new(&ctx.area_id, auth.profile, &auth.headers, auth.key.as_deref())?;
+    let areas: &[String] = auth_handler.authorized_areas();
+    if areas.is_empty() {
+        return Err("The auth response did not contain any area ID".into());
+    }
+    match format {
+        OutputFormat::Json => {
+            let list: Vec<serde_json::Value> = areas
+                .iter()
+                .map(|id| serde_json::json!({ "schema_version": SCHEMA_VERSION, "id": id, "name": area::prefecture_name(id) }))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&list)?);
+        }
+        OutputFormat::Text => {
+            for id in areas {
+                println!("{}\t{}", id, area::prefecture_name(id).unwrap_or("unknown"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 認可キーから部分鍵を作成して表示し、`expected` が指定された場合は一致するかを確認する
+///
+/// 認可処理（auth1 の応答の位置・長さからの部分鍵の作成）の調査用で、通信は行わない。
+fn verify_auth_key(config: &Config, offset: usize, length: usize, expected: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let key: Vec<u8> = RadikoAuthHandler::effective_auth_key(config.auth.profile, config.auth.key.as_deref())?;
+    let partial_key: String = auth_handler::derive_partial_key(&key, offset, length)?;
+    println!("Profile:     {}", config.auth.profile.app_name());
+    println!("Key source:  {}", if config.auth.key.is_some() { "configured" } else { "built-in" });
+    println!("Key length:  {}", key.len());
+    println!("Offset:      {}", offset);
+    println!("Length:      {}", length);
+    println!("Partial key: {}", partial_key);
+    match expected {
+        Some(expected) if expected != partial_key => {
+            Err(format!("Partial key does not match the expected value {}", expected).into())
+        }
+        Some(_) => {
+            println!("Partial key matches the expected value");
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
 /// 放送局リストを表示する
-fn show_station_list(area_id: &str) -> Result<(), Box<dyn Error>> {
-    if !is_valid_area_id(area_id) {
-        return Err(format!("Invalid area ID: {}", area_id).into());
+fn show_station_list(ctx: &Context, format: OutputFormat, limit: Option<u32>) -> Result<(), Box<dyn Error>> {
+    if !is_valid_area_id(&ctx.area_id) {
+        return Err(format!("Invalid area ID: {}", ctx.area_id).into());
     }
 
-    let player: RadikoPlayer = RadikoPlayer::new(area_id);
-    let station_list: Vec<recorder::Station> = player.get_station_list()?;
-    for station in station_list {
-        println!(
-            "Station: id={}, name={}, ascii_name={}, ruby={}",
-            station.id, station.name, station.ascii_name, station.ruby
-        );
+    let player: RadikoPlayer = RadikoPlayer::unauthenticated(&ctx.area_id, &ctx.config);
+    let mut station_list: Vec<Station> = player.get_station_list()?;
+    apply_limit(&mut station_list, limit, "stations");
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&schema::versioned_list(&station_list))?),
+        OutputFormat::Text => {
+            for station in station_list {
+                println!(
+                    "Station: id={}, name={}, ascii_name={}, ruby={}",
+                    station.id, station.name, station.ascii_name, station.ruby
+                );
+            }
+        }
     }
     Ok(())
 }
 
-/// ラジオを録音する処理
-fn record_radio(
-    area_id: &str,
+/// 放送局名の一部で放送局を検索し、一致する放送局を放送の種類とともに表示する
+///
+/// AM 局と同系列の FM 局のように名前の似た放送局が複数一致する場合は、その旨を表示する。
+fn find_station(ctx: &Context, query: &str, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    if !is_valid_area_id(&ctx.area_id) {
+        return Err(format!("Invalid area ID: {}", ctx.area_id).into());
+    }
+
+    let player: RadikoPlayer = RadikoPlayer::unauthenticated(&ctx.area_id, &ctx.config);
+    let station_list: Vec<Station> = player.get_station_list()?;
+    let matches: Vec<&Station> = Station::find_by_name(&station_list, query);
+    if matches.is_empty() {
+        return Err(format!("No stations in {} match: {}", ctx.area_id, query).into());
+    }
+    match format {
+        OutputFormat::Json => {
+            let list: Vec<serde_json::Value> = matches
+                .iter()
+                .map(|s| {
+                    serde_json::json!({ "schema_version": SCHEMA_VERSION, "id": s.id, "name": s.name, "band": s.band_hint() })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&list)?);
+        }
+        OutputFormat::Text => {
+            for station in &matches {
+                println!("{:<12} {:<5} {} ({})", station.id, station.band_hint(), station.name, station.ascii_name);
+            }
+            if matches.len() > 1 {
+                eprintln!("{} stations match \"{}\"; check the band before recording", matches.len(), query);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 保存済みの放送局リストと現在の放送局リストの差分を表示する
+fn show_station_diff(ctx: &Context, old_path: &str, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    if !is_valid_area_id(&ctx.area_id) {
+        return Err(format!("Invalid area ID: {}", ctx.area_id).into());
+    }
+
+    let old: Vec<Station> = station_diff::load_station_list(old_path)?;
+    let new: Vec<Station> = recorder::fetch_station_list(&ctx.area_id)?;
+    let diff: StationDiff = station_diff::diff_stations(&old, &new);
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&schema::versioned(&diff))?),
+        OutputFormat::Text => {
+            if diff.is_empty() {
+                println!("No changes in {}", ctx.area_id);
+            }
+            for station in &diff.added {
+                println!("+ {:<12} {}", station.id, station.name);
+            }
+            for station in &diff.removed {
+                println!("- {:<12} {}", station.id, station.name);
+            }
+            for station in &diff.renamed {
+                println!("~ {:<12} {} -> {}", station.id, station.old_name, station.new_name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// エリアの各放送局で現在放送中の番組を表示する
+///
+/// 番組表を取得できなかった放送局は警告を出力して読み飛ばす。
+fn show_programs_now(ctx: &Context, station_ids: &[String], format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    if !is_valid_area_id(&ctx.area_id) {
+        return Err(format!("Invalid area ID: {}", ctx.area_id).into());
+    }
+
+    let mut stations: Vec<Station> = recorder::fetch_station_list(&ctx.area_id)?;
+    if !station_ids.is_empty() {
+        stations.retain(|s| station_ids.contains(&s.id));
+        for id in station_ids.iter().filter(|id| !stations.iter().any(|s| &s.id == *id)) {
+            warn!("Station {} is not in area {}", id, ctx.area_id);
+        }
+    }
+
+    let now: NaiveDateTime = datetime::now_jst().naive_local();
+    let mut on_air: Vec<OnAir> = Vec::new();
+    for station in stations {
+        match program::find_program_at(&station.id, now) {
+            Ok(Some(program)) => on_air.push(OnAir {
+                remaining_secs: (program.to - now).num_seconds(),
+                station_id: station.id,
+                station_name: station.name,
+                program,
+            }),
+            Ok(None) => warn!("No program on air for {}", station.id),
+            Err(e) => warn!("Failed to fetch schedule for {}: {}", station.id, e),
+        }
+    }
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&schema::versioned_list(&on_air))?),
+        OutputFormat::Text => {
+            for entry in &on_air {
+                println!(
+                    "{:<12} {} - {} ({} left)  {}",
+                    entry.station_id,
+                    entry.program.ft.format("%H:%M"),
+                    entry.program.to.format("%H:%M"),
+                    duration::format_duration(entry.remaining_secs),
+                    entry.program.title
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 番組表を表示する、または iCalendar ファイルに書き出す
+///
+/// `week` が指定された場合は 7 日分を取得し、番組表上の日付ごとにまとめて表示する。
+fn show_schedule(
     station_id: &str,
-    start_time_str: &str,
-    duration_minutes: i64,
+    date: Option<NaiveDate>,
+    days: u32,
+    week: bool,
+    ical_path: Option<&str>,
+    format: OutputFormat,
+    limit: Option<u32>,
 ) -> Result<(), Box<dyn Error>> {
-    if !is_valid_area_id(area_id) {
-        return Err(format!("Invalid area ID: {}", area_id).into());
-    }
     if !is_valid_station_id(station_id) {
         return Err(format!("Invalid station ID: {}", station_id).into());
     }
-    if duration_minutes <= 0 {
-        return Err("Duration minutes must be positive".into());
+
+    // 日付の指定がなければ、現在時刻が属する番組表上の日付を使う
+    let first_date: NaiveDate =
+        date.unwrap_or_else(|| datetime::program_date(datetime::now_jst().naive_local()));
+    let days: u32 = if week { 7 } else { days };
+    let mut dated: Vec<DatedProgram> = Vec::new();
+    for offset in 0..days {
+        let date: NaiveDate = first_date + Duration::days(offset as i64);
+        let programs: Vec<Program> = program::fetch_station_schedule(station_id, date)?;
+        dated.extend(programs.into_iter().map(|program| DatedProgram { date, program }));
     }
+    apply_limit(&mut dated, limit, "programs");
 
-    // 出力ディレクトリ "output" を作成（存在しなければ）
-    let output_dir: &Path = Path::new("output");
-    if !output_dir.exists() {
-        fs::create_dir_all(output_dir)?;
+    if let Some(path) = ical_path {
+        let programs: Vec<Program> = dated.into_iter().map(|dated| dated.program).collect();
+        ical::write_ical(station_id, &programs, path)?;
+        println!("Wrote {} programs to {}", programs.len(), path);
+        return Ok(());
     }
-    // 現在時刻を付与して出力ファイル名を生成
-    let timestamp: String = Local::now().format("%Y%m%d%H%M%S").to_string();
-    let output_file: std::path::PathBuf =
-        output_dir.join(format!("{}_{}.aac", station_id, timestamp));
 
-    // 開始時刻の文字列をパースする
-    let naive_dt: NaiveDateTime = NaiveDateTime::parse_from_str(start_time_str, "%Y%m%d%H%M%S")?;
-    let start_time: DateTime<Local> = Local
-        .from_local_datetime(&naive_dt)
-        .single()
-        .ok_or("Failed to convert start time")?;
+    let line = |program: &Program, date_format: &str| -> String {
+        format!(
+            "{} - {}  {}{}",
+            program.ft.format(date_format),
+            program.to.format("%H:%M"),
+            program.title,
+            if program.pfm.is_empty() { String::new() } else { format!(" ({})", program.pfm) }
+        )
+    };
+    match (format, week) {
+        (OutputFormat::Json, true) => println!("{}", serde_json::to_string_pretty(&schema::versioned_list(&dated))?),
+        (OutputFormat::Json, false) => {
+            let programs: Vec<&Program> = dated.iter().map(|dated| &dated.program).collect();
+            println!("{}", serde_json::to_string_pretty(&schema::versioned_list(&programs))?);
+        }
+        (OutputFormat::Text, true) => {
+            let mut current: Option<NaiveDate> = None;
+            for DatedProgram { date, program } in &dated {
+                if current != Some(*date) {
+                    if current.is_some() {
+                        println!();
+                    }
+                    println!("== {} ==", date.format("%Y-%m-%d (%a)"));
+                    current = Some(*date);
+                }
+                // 深夜番組は翌日の日時になるため、日付も表示して区別する
+                let date_format: &str = if program.ft.date() == *date { "%H:%M" } else { "%m-%d %H:%M" };
+                println!("  {}", line(program, date_format));
+            }
+        }
+        (OutputFormat::Text, false) => {
+            for dated in &dated {
+                println!("{}", line(&dated.program, "%Y-%m-%d %H:%M"));
+            }
+        }
+    }
+    Ok(())
+}
 
-    let player: RadikoPlayer = RadikoPlayer::new(area_id);
-    player.record(
-        station_id,
-        start_time,
-        duration_minutes,
-        output_file.to_str().unwrap(),
-    )?;
+/// 表示する件数を `limit` 件までに切り詰め、省略した件数を標準エラー出力に表示する
+fn apply_limit<T>(items: &mut Vec<T>, limit: Option<u32>, label: &str) {
+    let Some(limit) = limit else {
+        return;
+    };
+    let limit: usize = limit as usize;
+    if items.len() > limit {
+        eprintln!("Showing {} of {} {} ({} omitted)", limit, items.len(), label, items.len() - limit);
+        items.truncate(limit);
+    }
+}
+
+/// 出力ディレクトリ内の録音済みファイルを一覧表示する
+fn list_recorded(output_dir: &str, sort: SortKey, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let mut recordings: Vec<RecordedFile> = library::scan_recordings(Path::new(output_dir))?;
+    library::sort_recordings(&mut recordings, sort);
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&schema::versioned_list(&recordings))?),
+        OutputFormat::Text => {
+            println!("{:<10} {:<19} {:>8}  TITLE", "STATION", "RECORDED", "DURATION");
+            for recording in &recordings {
+                let recorded_at: String = recording
+                    .recorded_at
+                    .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_else(|| "-".to_string());
+                println!(
+                    "{:<10} {:<19} {:>8}  {}",
+                    recording.station_id,
+                    recorded_at,
+                    library::format_duration(recording.duration_secs),
+                    recording.title.as_deref().unwrap_or("-")
+                );
+            }
+        }
+    }
     Ok(())
 }
 
-fn main() {
-    // ロガーを初期化
-    if let Err(e) = setup_logger() {
-        eprintln!("Failed to initialize logger: {}", e);
-        process::exit(1);
+/// キャッシュ済みのファイルの一覧を表示する
+fn show_cache_info(format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let entries: Vec<CacheEntry> = cache::list_entries()?;
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&schema::versioned_list(&entries))?),
+        OutputFormat::Text => {
+            if entries.is_empty() {
+                println!("No cached files");
+                return Ok(());
+            }
+            println!("{:<14} {:<20} {:>10} {:>10}  PATH", "KIND", "KEY", "AGE", "SIZE");
+            for entry in &entries {
+                println!(
+                    "{:<14} {:<20} {:>10} {:>10}  {}{}",
+                    entry.kind,
+                    entry.key,
+                    library::format_duration(Some(entry.age_secs as f64)),
+                    entry.size_bytes,
+                    entry.path,
+                    if entry.expired { " (expired)" } else { "" }
+                );
+            }
+        }
     }
+    Ok(())
+}
 
-    // コマンドライン引数を解析
-    let args: Args = Args::parse();
+/// ログファイルから直近の録音の成功・失敗の件数とエラーの分類を集計して表示する
+fn show_log_stats(days: u32, timezone: LogTimezone, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let stats: LogStats = log_stats::collect_stats(Path::new("logs"), days, timezone)?;
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&schema::versioned(&stats))?),
+        OutputFormat::Text => {
+            println!("Since {}:", stats.since.format("%Y-%m-%d %H:%M"));
+            println!("  succeeded: {}", stats.succeeded);
+            println!("  failed:    {}", stats.failed);
+            if !stats.error_categories.is_empty() {
+                println!("Errors by category:");
+                for error in &stats.error_categories {
+                    println!("  {:<12} {}", error.category, error.count);
+                }
+            }
+        }
+    }
+    Ok(())
+}
 
-    if args.station_list {
-        if let Err(e) = show_station_list(&args.area_id) {
-            eprintln!("Error: {}", e);
-            process::exit(1);
+/// 対話形式で放送局・開始時刻・録音時間を選択して録音する
+///
+/// 放送局の選択後に日付を入力すると、その日の番組表から録音する番組を選択できる。
+/// 番組を選択しなかった場合は、開始時刻と録音時間を入力する。
+fn run_interactive(ctx: &Context, default_duration_secs: i64) -> Result<(), Box<dyn Error>> {
+    if !is_valid_area_id(&ctx.area_id) {
+        return Err(format!("Invalid area ID: {}", ctx.area_id).into());
+    }
+
+    let player: RadikoPlayer = RadikoPlayer::unauthenticated(&ctx.area_id, &ctx.config);
+    let station_list: Vec<Station> = player.get_station_list()?;
+    let station: &Station = interactive::select_station(&station_list)?;
+
+    let picked: Option<Program> = match interactive::input_schedule_date()? {
+        Some(date) => {
+            let programs: Vec<Program> = program::fetch_station_schedule(&station.id, date)?;
+            interactive::select_program(&programs)?.cloned()
         }
-        return;
+        None => None,
+    };
+    if let Some(program) = &picked {
+        let options: RecordOptions = RecordOptions {
+            metadata: program_metadata(&ctx.config.metadata_for(&station.id), program, &station.name),
+            ..RecordOptions::default()
+        };
+        let start_time: String = program.ft.format("%Y%m%d%H%M%S").to_string();
+        let duration_secs: i64 = (program.to - program.ft).num_seconds();
+        info!("Recording {} ({} - {})", program.title, program.ft, program.to);
+        record_radio(ctx, &station.id, &start_time, duration_secs, None, &options, &PostProcess::default())?;
+        return Ok(());
     }
 
-    // 録音モードの場合、必須の引数（station_id, start_time）が与えられているかチェック
-    if args.station_id.is_none() || args.start_time.is_none() {
-        eprintln!("Station ID, start time, and duration minutes are required unless using the --station-list option.");
-        eprintln!("{}", Args::command().render_usage());
-        process::exit(1);
+    let start_time: String = interactive::input_start_time()?;
+    let duration_secs: i64 = interactive::input_duration(default_duration_secs)?;
+
+    record_radio(ctx, &station.id, &start_time, duration_secs, None, &RecordOptions::default(), &PostProcess::default())?;
+    Ok(())
+}
+
+/// `--probe-only` で調べるストリームの長さ（秒）
+const PROBE_DURATION_SECS: i64 = 60;
+
+/// `--probe-only` で開始時刻が省略された場合の遡り時間（分）
+const PROBE_LOOKBACK_MINUTES: i64 = 60;
+
+/// 録音せずにストリームの情報を表示する
+fn probe_station(
+    ctx: &Context,
+    station_id: &str,
+    start_time_str: Option<&str>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    if !is_valid_area_id(&ctx.area_id) {
+        return Err(format!("Invalid area ID: {}", ctx.area_id).into());
+    }
+    if !is_valid_station_id(station_id) {
+        return Err(format!("Invalid station ID: {}", station_id).into());
     }
 
-    let station_id: String = args.station_id.unwrap();
-    let start_time: String = args.start_time.unwrap();
-    let duration_minutes: i64 = args.duration_minutes as i64;
+    // 開始時刻の指定がなければ、直近のタイムフリーから分単位に切り捨てた時刻を使う
+    let start_time: DateTime<FixedOffset> = match start_time_str {
+        Some(s) => datetime::to_jst(datetime::parse_start_time(s)?),
+        None => {
+            let start: DateTime<FixedOffset> =
+                datetime::now_jst() - Duration::minutes(PROBE_LOOKBACK_MINUTES);
+            start - Duration::seconds(start.timestamp() % 60)
+        }
+    };
 
-    if let Err(e) = record_radio(&args.area_id, &station_id, &start_time, duration_minutes) {
-        eprintln!("Error: {}", e);
-        process::exit(1);
+    if ctx.config.auth.check_area {
+        recorder::check_station_area(&ctx.area_id, station_id)?;
+    }
+    let player: RadikoPlayer = RadikoPlayer::new(&ctx.area_id, &ctx.config)?;
+    let info: StreamInfo = player.probe(station_id, start_time, PROBE_DURATION_SECS)?;
+
+    let or_unknown = |v: Option<String>| v.unwrap_or_else(|| "unknown".to_string());
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&schema::versioned(&info))?),
+        OutputFormat::Text => {
+            println!("Station:     {}", station_id);
+            println!("Format:      {}", or_unknown(info.format_name));
+            println!("Codec:       {}", or_unknown(info.codec));
+            println!("Sample rate: {}", or_unknown(info.sample_rate.map(|r| format!("{} Hz", r))));
+            println!("Channels:    {}", or_unknown(info.channels.map(|c| c.to_string())));
+            println!("Bit rate:    {}", or_unknown(info.bit_rate.map(|b| format!("{} kbps", b / 1000))));
+        }
+    }
+    Ok(())
+}
+
+/// 指定した時間帯に放送される番組を、番組ごとのファイルに録音する
+///
+/// 時間帯の境界をまたぐ番組は、時間帯に含まれる部分のみを録音する。
+fn record_range(
+    ctx: &Context,
+    station_id: &str,
+    since_str: &str,
+    until_str: &str,
+    options: &RecordOptions,
+    post: &PostProcess,
+) -> Result<(), Box<dyn Error>> {
+    let area_id: &str = &ctx.area_id;
+    let config: &Config = &ctx.config;
+    if !is_valid_area_id(area_id) {
+        return Err(format!("Invalid area ID: {}", area_id).into());
+    }
+    if !is_valid_station_id(station_id) {
+        return Err(format!("Invalid station ID: {}", station_id).into());
+    }
+    let since: NaiveDateTime = datetime::parse_start_time(since_str)?;
+    let until: NaiveDateTime = datetime::parse_start_time(until_str)?;
+    if until <= since {
+        return Err("--until must be later than --since".into());
+    }
+
+    let programs: Vec<Program> = program::fetch_programs_between(station_id, since, until)?;
+    if programs.is_empty() {
+        return Err(format!("No programs found for {} between {} and {}", station_id, since, until).into());
+    }
+    info!("Recording {} programs of {} between {} and {}", programs.len(), station_id, since, until);
+
+    if config.auth.check_area {
+        recorder::check_station_area(area_id, station_id)?;
+    }
+
+    // 番組名を含むファイル名にする
+    let mut filename_config: FilenameConfig = config.filename.clone();
+    if !filename_config.template.contains("{title}") {
+        filename_config.template.push_str("_{title}");
+    }
+
+    let mut station_options: RecordOptions = options.clone();
+    apply_station_profile(ctx, station_id, &mut station_options)?;
+
+    let player: RadikoPlayer = RadikoPlayer::new(area_id, config)?;
+    let station_name: String = station_name(ctx, station_id);
+    let mut failed: usize = 0;
+    for program in &programs {
+        let mut options: RecordOptions = station_options.clone();
+        options.metadata = program_metadata(&config.metadata_for(station_id), program, &station_name);
+        let start: NaiveDateTime = program.ft.max(since);
+        let end: NaiveDateTime = program.to.min(until);
+        let start_time: DateTime<FixedOffset> = datetime::to_jst(start);
+        if !config.overwrite && library::skip_if_recorded(Path::new(&ctx.output_dir), station_id, start, end).is_some() {
+            continue;
+        }
+        let result: Result<(), Box<dyn Error>> = recorder::output_file_path(
+            Path::new(&ctx.output_dir),
+            station_id,
+            start_time,
+            Some(&program.title),
+            &filename_config,
+        )
+        .and_then(|output_file| {
+            let output_path: &str = output_file.to_str().ok_or("Invalid output file path")?;
+            let duration_secs: i64 = (end - start).num_seconds();
+            if ctx.explain {
+                explain_plan(&player, station_id, start_time, duration_secs, output_path, &options, post);
+            }
+            let outcome: RecordOutcome =
+                player.record(station_id, start_time, duration_secs, output_path, &options, None)?;
+            // pre-roll と --max-duration を反映した録音時間と比べる
+            verify_duration(&outcome, program, outcome.requested_duration, config.verify.duration_tolerance_secs);
+            let final_path: String = finish_recording(&outcome, post.normalize.as_ref())?;
+            track_outputs(ctx, &final_path, &outcome);
+            match post.sidecar {
+                Some(format) => write_sidecar(&final_path, program, format),
+                None => Ok(()),
+            }
+        });
+        if let Err(e) = result {
+            error!("Failed to record {} ({}): {}", program.title, program.ft, e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} of {} programs failed to record", failed, programs.len()).into());
+    }
+    Ok(())
+}
+
+/// `--since-last` で録音する時間帯（前回の録音の終わりから、現在放送中の番組の開始まで）を求める
+///
+/// 前回の録音は、出力ディレクトリ内のその放送局の録音ファイルのうち、ファイル名の日時が最も新しいものとし、
+/// その日時に ffprobe で計測した再生時間を足した時刻を開始とする。
+/// タイムフリーで遡れる範囲より前の場合は、範囲の先頭に切り詰めて警告する。
+fn since_last_window(ctx: &Context, station_id: &str) -> Result<(NaiveDateTime, NaiveDateTime), Box<dyn Error>> {
+    let last: RecordedFile = library::scan_recordings(Path::new(&ctx.output_dir))?
+        .into_iter()
+        .filter(|r| r.station_id == station_id && r.recorded_at.is_some())
+        .max_by_key(|r| r.recorded_at)
+        .ok_or_else(|| {
+            format!(
+                "No previous recording of {} in {}; record it once or use --since/--until",
+                station_id, ctx.output_dir
+            )
+        })?;
+    let last_start: NaiveDateTime = last.recorded_at.ok_or("Missing recording time")?;
+    let mut since: NaiveDateTime = last_start + Duration::seconds(last.duration_secs.unwrap_or(0.0).round() as i64);
+    info!("Last recording of {}: {} (ends at {})", station_id, last.path, since);
+
+    let now: NaiveDateTime = datetime::now_jst().naive_local();
+    let oldest: NaiveDateTime = now - Duration::days(datetime::TIMESHIFT_DAYS);
+    if since < oldest {
+        warn!(
+            "The last recording of {} ended at {}, beyond the timeshift window of {} days; recording from {} instead",
+            station_id,
+            since,
+            datetime::TIMESHIFT_DAYS,
+            oldest
+        );
+        since = oldest;
+    }
+    // 放送中の番組は途中までしか録音できないため、その番組の開始までとする
+    let until: NaiveDateTime = match program::find_program_at(station_id, now)? {
+        Some(on_air) => on_air.ft,
+        None => now,
+    };
+    if until <= since {
+        return Err(format!("Nothing new to record for {} since {}", station_id, since).into());
+    }
+    Ok((since, until))
+}
+
+/// バッチファイルの各録音を順に実行する
+///
+/// 一部の録音に失敗しても残りの録音を続け、最後に失敗した件数をエラーとして返す。
+fn run_batch(
+    ctx: &mut Context,
+    jobs: &[BatchJob],
+    options: &RecordOptions,
+    post: &PostProcess,
+    compact_progress: bool,
+) -> Result<(), Box<dyn Error>> {
+    // 進捗の行を上書きするため、端末でない場合（ファイルへのリダイレクトなど）は通常の表示にする
+    let compact: bool = compact_progress && io::stderr().is_terminal();
+    if compact_progress && !compact {
+        info!("--compact-progress is disabled because stderr is not a terminal");
+    }
+    if compact {
+        logger::set_console_quiet(true);
+    }
+    let show_progress = |done: usize, failed: usize, current: Option<&BatchJob>| {
+        if !compact {
+            return;
+        }
+        let current: String = current.map_or_else(String::new, |job| format!(" ({} {})", job.station, job.start));
+        eprint!(
+            "\r\x1b[K{}/{} done, {} failed, {} in progress{}",
+            done,
+            jobs.len(),
+            failed,
+            usize::from(!current.is_empty()),
+            current
+        );
+        let _ = io::stderr().flush();
+    };
+
+    let default_area_id: String = ctx.area_id.clone();
+    let mut done: usize = 0;
+    let mut failed: usize = 0;
+    for (i, job) in jobs.iter().enumerate() {
+        show_progress(done, failed, Some(job));
+        info!("Batch job {}/{}: {} {}", i + 1, jobs.len(), job.station, job.start);
+        ctx.area_id = job.area_id.clone().unwrap_or_else(|| default_area_id.clone());
+        let result: Result<Option<RecordOutcome>, Box<dyn Error>> = duration::parse_duration(&job.duration.to_string())
+            .map_err(|e| e.into())
+            .and_then(|duration_secs| record_radio(ctx, &job.station, &job.start, duration_secs, None, options, post));
+        match result {
+            Ok(_) => done += 1,
+            Err(e) => {
+                error!("Batch job {} ({} {}) failed: {}", i + 1, job.station, job.start, e);
+                failed += 1;
+            }
+        }
+    }
+    ctx.area_id = default_area_id;
+    show_progress(done, failed, None);
+    if compact {
+        eprintln!();
+        logger::set_console_quiet(false);
+    }
+
+    if failed > 0 {
+        return Err(format!("{} of {} batch jobs failed", failed, jobs.len()).into());
+    }
+    Ok(())
+}
+
+/// バッチファイルの各録音を検証し、1 件ずつ結果を表示する
+///
+/// 認可や録音は行わず、放送局ID・エリアID・開始日時・録音時間の形式と、出力ファイルが
+/// 既存のファイルや他の録音と重ならないかを確認する。ファイル名テンプレートに
+/// `{recorded_at}` が含まれる場合は実行時刻で名前が決まるため、重なりは確認しない。
+fn validate_batch(ctx: &Context, jobs: &[BatchJob]) -> Result<(), Box<dyn Error>> {
+    let check_collisions: bool = !ctx.config.filename.template.contains("{recorded_at}");
+    let mut outputs: HashMap<PathBuf, usize> = HashMap::new();
+    let mut invalid: usize = 0;
+    for (i, job) in jobs.iter().enumerate() {
+        let result: Result<PathBuf, String> = validate_batch_job(ctx, job).and_then(|path| {
+            if !check_collisions {
+                return Ok(path);
+            }
+            if let Some(other) = outputs.get(&path) {
+                return Err(format!("output {} collides with job {}", path.display(), other + 1));
+            }
+            if path.exists() {
+                return Err(format!("output {} already exists", path.display()));
+            }
+            outputs.insert(path.clone(), i);
+            Ok(path)
+        });
+        match result {
+            Ok(path) => println!("job {}: OK     {} {} -> {}", i + 1, job.station, job.start, path.display()),
+            Err(e) => {
+                println!("job {}: ERROR  {} {}: {}", i + 1, job.station, job.start, e);
+                invalid += 1;
+            }
+        }
+    }
+
+    if invalid > 0 {
+        return Err(format!("{} of {} batch jobs are invalid", invalid, jobs.len()).into());
+    }
+    println!("All {} batch jobs are valid", jobs.len());
+    Ok(())
+}
+
+/// バッチファイルの 1 件分を検証し、出力ファイルのパスを返す
+///
+/// 番組表は取得しないため、`{title}` は `untitled` として展開する。
+fn validate_batch_job(ctx: &Context, job: &BatchJob) -> Result<PathBuf, String> {
+    let area_id: &str = job.area_id.as_deref().unwrap_or(&ctx.area_id);
+    if !is_valid_area_id(area_id) {
+        return Err(format!("invalid area ID: {}", area_id));
+    }
+    if !is_valid_station_id(&job.station) {
+        return Err(format!("invalid station ID: {}", job.station));
+    }
+    let start: NaiveDateTime = datetime::parse_start_time(&job.start)?;
+    let duration_secs: i64 = duration::parse_duration(&job.duration.to_string())?;
+    duration::validate_duration(duration_secs)?;
+    duration::capped_duration(duration_secs + ctx.config.stream.pre_roll_secs as i64)?;
+
+    let fields: FilenameFields = FilenameFields {
+        station_id: &job.station,
+        start_time: datetime::to_jst(start),
+        recorded_at: Local::now(),
+        title: None,
+    };
+    let filename_config: &FilenameConfig = &ctx.config.filename;
+    let stem: String = filename::expand_template(
+        &filename_config.template,
+        &fields,
+        &filename_config.replacement,
+        filename_config.max_bytes,
+    );
+    Ok(Path::new(&ctx.output_dir).join(format!("{}.aac", stem)))
+}
+
+/// 番組表の番組IDで指定した番組を、放送時間ちょうどに録音する
+///
+/// 番組名・出演者・放送局IDを出力ファイルのメタデータとして埋め込む。
+fn record_program(
+    ctx: &Context,
+    station_id: &str,
+    date: Option<NaiveDate>,
+    program_id: &str,
+    options: &RecordOptions,
+    post: &PostProcess,
+) -> Result<Option<RecordOutcome>, Box<dyn Error>> {
+    if !is_valid_station_id(station_id) {
+        return Err(format!("Invalid station ID: {}", station_id).into());
+    }
+    let date: NaiveDate = date.unwrap_or_else(|| datetime::program_date(datetime::now_jst().naive_local()));
+    let program: Program = program::find_program_by_id(station_id, date, program_id)?;
+    info!("Recording {} ({} - {})", program.title, program.ft, program.to);
+
+    let mut options: RecordOptions = options.clone();
+    options.metadata = program_metadata(&ctx.config.metadata_for(station_id), &program, &station_name(ctx, station_id));
+
+    let start_time: String = program.ft.format("%Y%m%d%H%M%S").to_string();
+    let duration_secs: i64 = (program.to - program.ft).num_seconds();
+    let outcome: Option<RecordOutcome> = record_radio(ctx, station_id, &start_time, duration_secs, None, &options, post)?;
+    if let Some(outcome) = &outcome {
+        // pre-roll の分は録音ファイルが番組表の放送時間より長く、--max-duration で切り詰めた場合は短くなる
+        verify_duration(outcome, &program, outcome.requested_duration, ctx.config.verify.duration_tolerance_secs);
+    }
+    Ok(outcome)
+}
+
+/// 録音ファイルの再生時間を番組表の放送時間（`ft`〜`to`、時間帯で切り詰めた場合はその長さ）と比べ、
+/// 差が許容範囲を超える場合は警告する
+///
+/// 録音が途中で途切れた場合や、余分な部分が含まれた場合を検出するため。
+fn verify_duration(outcome: &RecordOutcome, program: &Program, expected_secs: i64, tolerance_secs: u64) {
+    let Some(measured) = outcome.measured_duration else {
+        warn!("Could not verify the duration of {}: duration unknown", outcome.output_path);
+        return;
+    };
+    let difference: f64 = measured - expected_secs as f64;
+    if difference.abs() > tolerance_secs as f64 {
+        warn!(
+            "Duration of {} ({:.1}s) differs from the schedule of {} ({}s) by {:+.1}s",
+            outcome.output_path, measured, program.title, expected_secs, difference
+        );
+    } else {
+        info!(
+            "Verified duration of {} against the schedule ({:.1}s / {}s)",
+            outcome.output_path, measured, expected_secs
+        );
+    }
+}
+
+/// 録音開始時刻を、番組表でその時刻に放送中の番組の開始時刻に合わせる
+///
+/// `snap_end` が真の場合は、録音終了時刻もその直前に放送中の番組の終了時刻に合わせる。
+/// 合わせた録音開始時刻（YYYYMMDDHHMMSS形式）と録音時間（秒）を返す。
+/// 番組表が取得できない場合や、該当する番組がない場合はエラーとする。
+fn snap_to_programs(
+    station_id: &str,
+    start_time_str: &str,
+    duration_secs: i64,
+    snap_end: bool,
+) -> Result<(String, i64), Box<dyn Error>> {
+    let start: NaiveDateTime = datetime::parse_start_time(start_time_str)?;
+    let end: NaiveDateTime = start + Duration::seconds(duration_secs);
+    let program: Program = program::find_program_at(station_id, start)
+        .map_err(|e| format!("Cannot snap the start time: failed to fetch the schedule of {}: {}", station_id, e))?
+        .ok_or_else(|| format!("Cannot snap the start time: no program of {} found at {}", station_id, start))?;
+    let snapped_start: NaiveDateTime = program.ft;
+    let snapped_end: NaiveDateTime = if snap_end {
+        let last: NaiveDateTime = end - Duration::seconds(1);
+        program::find_program_at(station_id, last)
+            .map_err(|e| format!("Cannot snap the end time: failed to fetch the schedule of {}: {}", station_id, e))?
+            .ok_or_else(|| format!("Cannot snap the end time: no program of {} found at {}", station_id, last))?
+            .to
+    } else {
+        end
+    };
+    info!("Snapped {} - {} to {} - {} ({})", start, end, snapped_start, snapped_end, program.title);
+    Ok((
+        snapped_start.format("%Y%m%d%H%M%S").to_string(),
+        (snapped_end - snapped_start).num_seconds(),
+    ))
+}
+
+/// 番組情報と設定から、録音ファイルに埋め込むメタデータを作る
+///
+/// タイトルは番組名、アーティストは出演者、アルバムは放送局名とし、
+/// 設定で指定されたタグはその値で上書きする（空文字列の場合は埋め込まない）。
+fn program_metadata(config: &MetadataConfig, program: &Program, station_name: &str) -> Vec<(String, String)> {
+    let tags: [(&str, &Option<String>, &str); 4] = [
+        ("title", &config.title, &program.title),
+        ("artist", &config.artist, &program.pfm),
+        ("album", &config.album, station_name),
+        ("genre", &config.genre, ""),
+    ];
+    let mut metadata: Vec<(String, String)> = tags
+        .iter()
+        .map(|(key, custom, default)| (key.to_string(), custom.as_deref().unwrap_or(default).to_string()))
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+    metadata.extend(date_metadata(config, program.ft));
+    metadata
+}
+
+/// 録音済みファイルに、番組表から取得した番組情報をメタデータとして書き込む
+///
+/// 放送局IDと開始日時が指定されない場合は、ファイル名（`<放送局ID>_<YYYYMMDDHHMMSS>...`）から読み取る。
+/// 番組表を取得できない場合（公開期間外の日付など）や番組が見つからない場合は、ファイルを変更せずにエラーを返す。
+fn retag(ctx: &Context, file: &str, station_id: Option<&str>, start_time_str: Option<&str>) -> Result<(), Box<dyn Error>> {
+    if !Path::new(file).is_file() {
+        return Err(format!("File not found: {}", file).into());
+    }
+    let (parsed_station, parsed_start): (String, Option<NaiveDateTime>) =
+        library::parse_recording_name(Path::new(file));
+    let station_id: String = station_id.map(str::to_string).unwrap_or(parsed_station);
+    let start: NaiveDateTime = match start_time_str {
+        Some(s) => datetime::parse_start_time(s)?,
+        None => parsed_start.ok_or_else(|| {
+            format!(
+                "Could not read the start time from the file name {}; pass the station ID and start time after --retag",
+                file
+            )
+        })?,
+    };
+    if !is_valid_station_id(&station_id) {
+        return Err(format!("Invalid station ID: {}", station_id).into());
+    }
+
+    let program: Program = program::find_program_at(&station_id, start)
+        .map_err(|e| {
+            format!(
+                "The program schedule of {} on {} is not available (only recent dates are published): {}",
+                station_id,
+                datetime::program_date(start),
+                e
+            )
+        })?
+        .ok_or_else(|| format!("No program found on {} at {}", station_id, start))?;
+    let metadata: Vec<(String, String)> =
+        program_metadata(&ctx.config.metadata_for(&station_id), &program, &station_name(ctx, &station_id));
+    postprocess::write_metadata(file, &metadata)?;
+    info!("Retagged {} as \"{}\" ({} tags)", file, program.title, metadata.len());
+    Ok(())
+}
+
+/// 放送局ごとの設定（`[stations.<放送局ID>]`）の録音プロファイルを録音のオプションに適用する
+///
+/// コマンドラインで再エンコード（`--profile`, `--force-reencode`）が指定された場合はそちらを優先する。
+fn apply_station_profile(ctx: &Context, station_id: &str, options: &mut RecordOptions) -> Result<(), Box<dyn Error>> {
+    if options.reencode.is_some() {
+        return Ok(());
+    }
+    if let Some(name) = ctx.config.profile_for(station_id) {
+        info!("Using profile {} configured for {}", name, station_id);
+        options.reencode = Some(profile::resolve_profile(name, &ctx.config.profiles)?);
+    }
+    Ok(())
+}
+
+/// 録音開始日時（JST）から、録音ファイルに埋め込む放送日のタグ（`date` と `year`）を作る
+///
+/// 放送日は 5:00 を境とする番組表上の日付とし、深夜番組も前日の放送として並ぶようにする。
+/// 設定で指定された場合はその値を使い、空文字列の場合は埋め込まない。
+fn date_metadata(config: &MetadataConfig, start: NaiveDateTime) -> Vec<(String, String)> {
+    let date: String = config
+        .date
+        .clone()
+        .unwrap_or_else(|| datetime::program_date(start).format("%Y-%m-%d").to_string());
+    if date.is_empty() {
+        return Vec::new();
+    }
+    let year: String = date.chars().take(4).collect();
+    vec![("date".to_string(), date), ("year".to_string(), year)]
+}
+
+/// エリアの放送局リストから放送局名を取得する（取得できない場合は放送局ID）
+fn station_name(ctx: &Context, station_id: &str) -> String {
+    match recorder::fetch_station_list(&ctx.area_id) {
+        Ok(stations) => stations
+            .into_iter()
+            .find(|s| s.id == station_id)
+            .map(|s| s.name)
+            .unwrap_or_else(|| station_id.to_string()),
+        Err(e) => {
+            warn!("Failed to fetch the station name of {}: {}", station_id, e);
+            station_id.to_string()
+        }
+    }
+}
+
+/// ラジオを録音し、標準出力または名前付きパイプ（FIFO）に書き出す
+fn record_to_stream(
+    ctx: &Context,
+    station_id: &str,
+    start_time_str: &str,
+    duration_secs: i64,
+    fifo_path: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    if !is_valid_area_id(&ctx.area_id) {
+        return Err(format!("Invalid area ID: {}", ctx.area_id).into());
+    }
+    if !is_valid_station_id(station_id) {
+        return Err(format!("Invalid station ID: {}", station_id).into());
+    }
+    duration::validate_duration(duration_secs)?;
+    // 通信を始める前に、プリロールを含めた録音時間で上限を確認する（切り詰める場合の適用と警告は録音時に行う）
+    duration::capped_duration(duration_secs + ctx.config.stream.pre_roll_secs as i64)?;
+
+    let naive_dt: NaiveDateTime = datetime::parse_start_time(start_time_str)?;
+    let start_time: DateTime<FixedOffset> = datetime::to_jst(naive_dt);
+    if ctx.config.auth.check_area {
+        recorder::check_station_area(&ctx.area_id, station_id)?;
+    }
+
+    let player: RadikoPlayer = RadikoPlayer::new(&ctx.area_id, &ctx.config)?;
+    match fifo_path {
+        Some(path) => player.record_to_fifo(station_id, start_time, duration_secs, path),
+        None => player.record_to_stdout(station_id, start_time, duration_secs),
+    }
+}
+
+/// ラジオを録音する処理
+///
+/// 録音結果を返す（既存ファイルへの追記の場合は `None`）。
+fn record_radio(
+    ctx: &Context,
+    station_id: &str,
+    start_time_str: &str,
+    duration_secs: i64,
+    append_to: Option<&str>,
+    options: &RecordOptions,
+    post: &PostProcess,
+) -> Result<Option<RecordOutcome>, Box<dyn Error>> {
+    let area_id: &str = &ctx.area_id;
+    let config: &Config = &ctx.config;
+    if !is_valid_area_id(area_id) {
+        return Err(format!("Invalid area ID: {}", area_id).into());
+    }
+    if !is_valid_station_id(station_id) {
+        return Err(format!("Invalid station ID: {}", station_id).into());
+    }
+    duration::validate_duration(duration_secs)?;
+    // 通信を始める前に、プリロールを含めた録音時間で上限と分割位置を確認する
+    // （切り詰める場合の適用と警告は録音時に行う）
+    options.validate_split_within_cap(duration_secs, config.stream.pre_roll_secs as i64)?;
+
+    // 開始時刻の文字列をパースする（24時以降の深夜表記は翌日として扱う）
+    let naive_dt: NaiveDateTime = datetime::parse_start_time(start_time_str)?;
+    let start_time: DateTime<FixedOffset> = datetime::to_jst(naive_dt);
+
+    // 指定された場合は、認可前に放送局が指定エリアで聴取可能かを確認する
+    if config.auth.check_area {
+        recorder::check_station_area(area_id, station_id)?;
+    }
+
+    // 追記モードの場合は既存ファイルに不足分のみを録音する
+    if let Some(existing_path) = append_to {
+        if !Path::new(existing_path).is_file() {
+            return Err(format!("File not found: {}", existing_path).into());
+        }
+        let player: RadikoPlayer = RadikoPlayer::new(area_id, config)?;
+        player.append(station_id, start_time, duration_secs, existing_path)?;
+        return Ok(None);
+    }
+
+    if !config.overwrite {
+        let end: NaiveDateTime = naive_dt + Duration::seconds(duration_secs);
+        if let Some(window) = library::skip_if_recorded(Path::new(&ctx.output_dir), station_id, naive_dt, end) {
+            ctx.skipped.lock().unwrap_or_else(|e| e.into_inner()).push(window);
+            return Ok(None);
+        }
+    }
+
+    // 番組単位の録音以外でも、放送日のタグを埋め込む
+    let mut options: RecordOptions = options.clone();
+    apply_station_profile(ctx, station_id, &mut options)?;
+    if !options.metadata.iter().any(|(key, _)| key == "date") {
+        options.metadata.extend(date_metadata(&config.metadata_for(station_id), naive_dt));
+    }
+    let options: &RecordOptions = &options;
+
+    let output_file: std::path::PathBuf = recorder::output_file_path(
+        Path::new(&ctx.output_dir),
+        station_id,
+        start_time,
+        None,
+        &config.filename,
+    )?;
+
+    let player: RadikoPlayer = RadikoPlayer::new(area_id, config)?;
+    if ctx.explain {
+        explain_plan(&player, station_id, start_time, duration_secs, output_file.to_str().unwrap(), options, post);
+    }
+    let outcome: RecordOutcome = player.record(
+        station_id,
+        start_time,
+        duration_secs,
+        output_file.to_str().unwrap(),
+        options,
+        None,
+    )?;
+    let final_path: String = finish_recording(&outcome, post.normalize.as_ref())?;
+    track_outputs(ctx, &final_path, &outcome);
+
+    // 録音に含まれる番組ごとのトラックを CUE シートに書き出す
+    if post.cue {
+        let start: NaiveDateTime = start_time.naive_local();
+        let end: NaiveDateTime = start + Duration::seconds(duration_secs);
+        let programs: Vec<Program> = program::fetch_programs_between(station_id, start, end)?;
+        let cue_path: String = cue::write_cue(&final_path, station_id, &programs, start)?;
+        info!("Wrote {} tracks to {}", programs.len(), cue_path);
+    }
+
+    // 録音開始時に放送中の番組の情報をサイドカーファイルに書き出す
+    if let Some(format) = post.sidecar {
+        match program::find_program_at(station_id, start_time.naive_local())? {
+            Some(program) => write_sidecar(&final_path, &program, format)?,
+            None => warn!("No program found at {} for the sidecar file", start_time),
+        }
+    }
+    Ok(Some(outcome))
+}
+
+/// 同じ時間帯を 2 つの放送局から同時に録音し、ffprobe で計測した再生時間の長い方を残す
+///
+/// 再生時間が同じ場合は `station_ids` の先頭の放送局の録音を残す。
+/// 残さなかった録音は削除せず、`<ファイル名>.alternate.<拡張子>` に名前を変えて残す。
+/// 失敗した側の書きかけの録音は保存先に残さない。両方の録音に失敗した場合のみエラーを返す。
+fn record_with_failover(
+    ctx: &Context,
+    station_ids: [&str; 2],
+    start_time_str: &str,
+    duration_secs: i64,
+    options: &RecordOptions,
+    post: &PostProcess,
+) -> Result<Option<RecordOutcome>, Box<dyn Error>> {
+    if station_ids[0] == station_ids[1] {
+        return Err("--failover-station must differ from the station ID".into());
+    }
+    // ファイル名テンプレートに放送局IDが含まれない場合、2 つの録音が同じファイルに書き込まれてしまう
+    let start_time: DateTime<FixedOffset> = datetime::to_jst(datetime::parse_start_time(start_time_str)?);
+    let paths: Vec<PathBuf> = station_ids
+        .iter()
+        .map(|station_id| {
+            recorder::output_file_path(Path::new(&ctx.output_dir), station_id, start_time, None, &ctx.config.filename)
+        })
+        .collect::<Result<Vec<PathBuf>, Box<dyn Error>>>()?;
+    if paths[0] == paths[1] {
+        return Err(format!(
+            "Both stations would be recorded to {}; include {{station_id}} in the filename template",
+            paths[0].display()
+        )
+        .into());
+    }
+    info!("Recording {} and {} simultaneously", station_ids[0], station_ids[1]);
+    let options: &RecordOptions = &RecordOptions {
+        discard_partial: true,
+        ..options.clone()
+    };
+    // Box<dyn Error> はスレッド間で受け渡せないため、エラーは文字列にする
+    let results: Vec<Result<Option<RecordOutcome>, String>> = thread::scope(|scope| {
+        let handles: Vec<thread::ScopedJoinHandle<Result<Option<RecordOutcome>, String>>> = station_ids
+            .iter()
+            .map(|&station_id| {
+                scope.spawn(move || {
+                    record_radio(ctx, station_id, start_time_str, duration_secs, None, options, post)
+                        .map_err(|e| e.to_string())
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|_| Err("Recording thread panicked".to_string())))
+            .collect()
+    });
+
+    let mut outcomes: Vec<(&str, RecordOutcome)> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+    for (station_id, result) in station_ids.iter().zip(results) {
+        match result {
+            Ok(Some(outcome)) => outcomes.push((station_id, outcome)),
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Recording of {} failed: {}", station_id, e);
+                errors.push(format!("{}: {}", station_id, e));
+            }
+        }
+    }
+
+    // 同じ長さの場合は先に指定した放送局を優先する（max_by は最後の最大値を返すため逆順にする）
+    let best: usize = match outcomes
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by(|(_, a), (_, b)| {
+            a.1.measured_duration
+                .unwrap_or(0.0)
+                .total_cmp(&b.1.measured_duration.unwrap_or(0.0))
+        }) {
+        Some((i, _)) => i,
+        None => return Err(format!("All recordings failed ({})", errors.join("; ")).into()),
+    };
+    let (kept_station, kept): (&str, RecordOutcome) = outcomes.swap_remove(best);
+    for (station_id, outcome) in outcomes {
+        let archived: String = postprocess::sibling_path(&outcome.output_path, "alternate");
+        postprocess::move_file(Path::new(&outcome.output_path), Path::new(&archived))?;
+        ctx.outputs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|path| *path != outcome.output_path);
+        info!(
+            "Archived the recording of {} ({}) as {}",
+            station_id,
+            library::format_duration(outcome.measured_duration),
+            archived
+        );
+    }
+    info!(
+        "Keeping the recording of {} ({}): {}",
+        kept_station,
+        library::format_duration(kept.measured_duration),
+        kept.output_path
+    );
+    Ok(Some(kept))
+}
+
+/// 録音計画（録音する時間帯・出力ファイル・ストリームURL・分割・エンコード・メタデータ・録音後の処理）を表示する
+fn explain_plan(
+    player: &RadikoPlayer,
+    station_id: &str,
+    start_time: DateTime<FixedOffset>,
+    duration_secs: i64,
+    output_path: &str,
+    options: &RecordOptions,
+    post: &PostProcess,
+) {
+    let output_path: String = options.output_path(output_path);
+    let end_time: DateTime<FixedOffset> = start_time + Duration::seconds(duration_secs);
+    println!("Recording plan:");
+    println!("  Station:   {}", station_id);
+    println!("  Start:     {}", start_time.format("%Y-%m-%d %H:%M:%S %:z"));
+    println!("  End:       {}", end_time.format("%Y-%m-%d %H:%M:%S %:z"));
+    println!("  Duration:  {}s", duration_secs);
+    println!("  Output:    {}", output_path);
+    println!("  Stream:    {}", player.playlist_url(station_id, start_time, duration_secs));
+    println!(
+        "  Download:  {}",
+        if options.segment_downloader { "segment downloader" } else { "ffmpeg (HLS)" }
+    );
+    match &options.reencode {
+        Some(reencode) => println!(
+            "  Encoding:  {} {}{}",
+            reencode.codec.encoder(),
+            reencode.bitrate,
+            reencode.channels.map_or_else(String::new, |channels| format!(" {}ch", channels))
+        ),
+        None => println!("  Encoding:  copy"),
+    }
+    if options.is_split() {
+        let pattern: String = recorder::segment_pattern(&output_path);
+        println!("  Segments:");
+        let mut offset: i64 = 0;
+        for (index, end) in options.split_points(duration_secs).into_iter().enumerate() {
+            println!(
+                "    {} - {}  {}",
+                (start_time + Duration::seconds(offset)).format("%H:%M:%S"),
+                (start_time + Duration::seconds(end)).format("%H:%M:%S"),
+                pattern.replace("%03d", &format!("{:03}", index))
+            );
+            offset = end;
+        }
+    }
+    if options.metadata.is_empty() {
+        println!("  Metadata:  (none)");
+    } else {
+        println!("  Metadata:");
+        for (key, value) in &options.metadata {
+            println!("    {}={}", key, value);
+        }
+    }
+    let mut steps: Vec<String> = Vec::new();
+    if let Some(normalize) = &post.normalize {
+        steps.push(format!(
+            "normalize to {} LUFS ({} {}{})",
+            normalize.target_lufs,
+            normalize.codec.encoder(),
+            normalize.bitrate,
+            if normalize.replace { ", replace original" } else { "" }
+        ));
+    }
+    if post.cue {
+        steps.push("write CUE sheet".to_string());
+    }
+    if let Some(format) = post.sidecar {
+        steps.push(format!("write {:?} sidecar", format).to_lowercase());
+    }
+    if steps.is_empty() {
+        println!("  After:     (nothing)");
+    } else {
+        println!("  After:     {}", steps.join(", "));
+    }
+}
+
+/// 番組情報のサイドカーファイルを書き出す
+fn write_sidecar(audio_path: &str, program: &Program, format: SidecarFormat) -> Result<(), Box<dyn Error>> {
+    let path: String = sidecar::write_sidecar(audio_path, program, format)?;
+    info!("Wrote program information to {}", path);
+    Ok(())
+}
+
+/// 録音結果をログに出力し、指定されていれば音量を正規化する
+///
+/// 正規化で元のファイルを置き換えた場合は置き換え後のパスを、それ以外は録音ファイルのパスを返す。
+fn finish_recording(outcome: &RecordOutcome, normalize: Option<&NormalizeOptions>) -> Result<String, Box<dyn Error>> {
+    info!(
+        "Recorded {}: requested={}s, measured={}, size={} bytes",
+        outcome.output_path,
+        outcome.requested_duration,
+        outcome
+            .measured_duration
+            .map_or_else(|| "unknown".to_string(), |d| format!("{:.1}s", d)),
+        outcome.file_size_bytes
+    );
+    for segment in &outcome.segments {
+        info!("Segment: {}", segment);
+    }
+
+    // 分割録音の場合は各ファイルを、それ以外は録音ファイルを正規化する
+    let mut final_path: String = outcome.output_path.clone();
+    if let Some(normalize) = normalize {
+        if outcome.segments.is_empty() {
+            let normalized: String = postprocess::normalize_loudness(&outcome.output_path, normalize)?;
+            if normalize.replace {
+                final_path = normalized;
+            }
+        }
+        for segment in &outcome.segments {
+            postprocess::normalize_loudness(segment, normalize)?;
+        }
+    }
+    Ok(final_path)
+}
+
+/// 放送局IDが省略された場合に、環境変数 `RADIKO_STATION_ID` の値で補う
+///
+/// 位置引数は先頭から順に割り当てられるため、放送局IDを省略すると開始時刻が放送局IDに、
+/// 録音時間が開始時刻に入る。放送局IDの位置に開始時刻がある場合は、1 つずつ後ろにずらしてから補う。
+/// コマンドラインで放送局IDを指定した場合は環境変数を使わない。
+fn apply_station_env(args: &mut Args, env_station: Option<String>) -> Result<(), String> {
+    let Some(env_station) = env_station.filter(|station| !station.trim().is_empty()) else {
+        return Ok(());
+    };
+    match args.station_id.as_deref() {
+        None => args.station_id = Some(env_station),
+        Some(first) if datetime::parse_start_time(first).is_ok() => {
+            if let Some(duration) = args.start_time.take() {
+                args.duration = duration::parse_duration(&duration)?;
+            }
+            args.start_time = args.station_id.take();
+            args.station_id = Some(env_station);
+        }
+        Some(_) => {}
+    }
+    Ok(())
+}
+
+fn main() {
+    // コマンドライン引数を解析
+    let mut args: Args = Args::parse();
+    if let Err(e) = apply_station_env(&mut args, std::env::var("RADIKO_STATION_ID").ok()) {
+        exit_with_error(e, args.json);
+    }
+
+    // 設定ファイルを読み込む
+    let mut config: Config = match Config::load(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            exit_with_error(e, args.json);
+        }
+    };
+
+    // ロガーを初期化（録音データを標準出力に書き出す場合、ログは標準エラー出力へ）
+    if let Some(timezone) = args.log_timezone {
+        config.log.timezone = timezone;
+    }
+    if let Err(e) = setup_logger(args.output_to_stdout || args.json, &config.log) {
+        exit_with_error(format!("Failed to initialize logger: {}", e), args.json);
+    }
+    if args.no_auth {
+        config.auth.skip = true;
+    }
+    if args.skip_eligibility_check {
+        config.auth.skip_eligibility_check = true;
+    }
+    if args.check_area {
+        config.auth.check_area = true;
+    }
+    if let Some(minutes) = args.retry_window {
+        config.stream.retry_window_minutes = minutes;
+    }
+    if let Some(pre_roll) = args.pre_roll {
+        config.stream.pre_roll_secs = pre_roll;
+    }
+    if let Some(ffmpeg_log) = args.ffmpeg_log {
+        config.stream.ffmpeg_log = ffmpeg_log;
+    }
+    if let Some(tolerance) = args.duration_tolerance {
+        config.verify.duration_tolerance_secs = tolerance;
+    }
+    if let Some(retries) = args.ffmpeg_retries {
+        config.stream.ffmpeg_retries = retries;
+    }
+    if args.ffmpeg_threads.is_some() {
+        config.stream.ffmpeg.threads = args.ffmpeg_threads;
+    }
+    if args.hls_max_reload.is_some() {
+        config.stream.ffmpeg.max_reload = args.hls_max_reload;
+    }
+    if args.hls_http_multiple {
+        config.stream.ffmpeg.http_multiple = Some(true);
+    }
+    if args.ffmpeg_queue_size.is_some() {
+        config.stream.ffmpeg.thread_queue_size = args.ffmpeg_queue_size;
+    }
+    if !args.retry_on_ffmpeg_code.is_empty() {
+        config.stream.retry_exit_codes = Some(args.retry_on_ffmpeg_code.clone());
+    }
+    if args.auth_key.is_some() {
+        config.auth.key = args.auth_key.clone();
+    }
+    if let Some(profile) = args.auth_profile {
+        config.auth.profile = profile;
+    }
+    if args.premium_mail.is_some() {
+        config.auth.premium_mail = args.premium_mail.clone();
+    }
+    if args.premium_password.is_some() {
+        config.auth.premium_password = args.premium_password.clone();
+    }
+    if let Some(prefer_premium) = args.prefer_premium {
+        config.auth.prefer_premium = prefer_premium;
+    }
+    if args.resume_token.is_some() {
+        config.auth.token = args.resume_token.clone();
+    }
+    if config.auth.token.as_deref().is_some_and(|token| token.trim().is_empty()) {
+        exit_with_error("The supplied auth token is empty", args.json);
+    }
+    http_client::set_trace(args.trace_http);
+    if args.ca_cert.is_some() {
+        config.http.ca_cert = args.ca_cert.clone();
+    }
+    if args.insecure {
+        config.http.insecure = true;
+    }
+    if let Err(e) = http_client::configure_tls(config.http.ca_cert.as_deref(), config.http.insecure) {
+        exit_with_error(e, args.json);
+    }
+    if let Some(capacity) = config.http.retry_budget {
+        http_client::configure_retry_budget(capacity, config.http.retry_refill_per_minute);
+    }
+    recorder::configure_area_fetch(config.http.area_concurrency, config.http.area_request_delay_ms);
+    if let Some(version) = args.station_list_version {
+        config.endpoints.station_list_version = version;
+    }
+    recorder::configure_endpoints(&config.endpoints);
+    cache::configure_cache(
+        Path::new(&config.cache.dir),
+        config.cache.station_list_ttl_hours,
+        config.cache.schedule_ttl_hours,
+    );
+    if let Some(temp_dir) = args.temp_dir.as_ref().or(config.temp_dir.as_ref()) {
+        if let Err(e) = postprocess::configure_temp_dir(Path::new(temp_dir)) {
+            exit_with_error(e, args.json);
+        }
+    }
+    if let Some(min_free_space) = args.min_free_space {
+        config.min_free_space_mb = min_free_space;
+    }
+    postprocess::configure_min_free_space(config.min_free_space_mb);
+    if args.overwrite {
+        config.overwrite = true;
+    }
+    if let Some(action) = args.max_duration_action {
+        config.max_duration_action = action;
+    }
+    let max_duration: Option<i64> = match (args.max_duration, &config.max_duration) {
+        (Some(secs), _) => Some(secs),
+        (None, Some(value)) => match duration::parse_duration(&value.to_string()) {
+            Ok(secs) => Some(secs),
+            Err(e) => {
+                exit_with_error(format!("invalid max_duration in config: {}", e), args.json);
+            }
+        },
+        (None, None) => None,
+    };
+    if let Some(secs) = max_duration {
+        duration::configure_max_duration(secs, config.max_duration_action);
+    }
+
+    // コマンドライン引数・環境変数 > 設定ファイル > デフォルト値の順に解決する
+    let area_id: String = args
+        .area_id
+        .as_ref()
+        .map(AreaId::to_string)
+        .or_else(|| config.area_id.clone())
+        .unwrap_or_else(|| RADIKO_AREA_ID.to_string());
+    let output_dir: String = args
+        .output_dir
+        .clone()
+        .or_else(|| config.output_dir.clone())
+        .unwrap_or_else(|| OUTPUT_DIR.to_string());
+    let mut ctx: Context = Context {
+        area_id,
+        output_dir,
+        config,
+        explain: args.explain,
+        outputs: Mutex::new(Vec::new()),
+        skipped: Mutex::new(Vec::new()),
+    };
+
+    if args.list_areas {
+        if let Err(e) = show_areas(args.mine, args.format) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if args.station_list {
+        if let Err(e) = show_station_list(&ctx, args.format, args.limit) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if let Some(values) = &args.verify_auth_key {
+        if let Err(e) = verify_auth_key(&ctx.config, values[0], values[1], args.expected_partial_key.as_deref()) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if args.probe_area {
+        if let Err(e) = probe_areas(&ctx, args.format) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if let Some(query) = &args.find_station {
+        if let Err(e) = find_station(&ctx, query, args.format) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if let Some(old_path) = &args.diff_stations {
+        if let Err(e) = show_station_diff(&ctx, old_path, args.format) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if args.list_programs_now {
+        if let Err(e) = show_programs_now(&ctx, &args.stations, args.format) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if let Some(station_id) = &args.schedule {
+        if let Err(e) = show_schedule(
+            station_id,
+            args.date,
+            args.days,
+            args.week,
+            args.ical.as_deref(),
+            args.format,
+            args.limit,
+        ) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if args.daemon {
+        if let Err(e) = scheduler::run_daemon(&ctx.area_id, &ctx.output_dir, &ctx.config, args.health_port) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if args.log_stats {
+        if let Err(e) = show_log_stats(args.stats_days, ctx.config.log.timezone, args.format) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if args.cache_info {
+        if let Err(e) = show_cache_info(args.format) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if args.clear_cache {
+        match cache::clear() {
+            Ok(count) => println!("Removed {} cached files", count),
+            Err(e) => {
+                exit_with_error(e, args.json);
+            }
+        }
+        return;
+    }
+
+    if args.list_recorded {
+        if let Err(e) = list_recorded(&ctx.output_dir, args.sort, args.format) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if let Some(file) = &args.retag {
+        if let Err(e) = retag(&ctx, file, args.station_id.as_deref(), args.start_time.as_deref()) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if args.probe_only {
+        let Some(station_id) = args.station_id.as_deref() else {
+            exit_with_error("Station ID is required for --probe-only.", args.json);
+        };
+        if let Err(e) = probe_station(&ctx, station_id, args.start_time.as_deref(), args.format) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if args.selftest {
+        if !is_valid_area_id(&ctx.area_id) {
+            exit_with_error(format!("Invalid area ID: {}", ctx.area_id), args.json);
+        }
+        if let Err(e) = selftest::run_selftest(&ctx.area_id, args.station_id.as_deref(), &ctx.config) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+
+    if args.interactive {
+        let result: Result<(), Box<dyn Error>> = run_interactive(&ctx, args.duration);
+        exit_after_recording(&ctx, result);
+        return;
+    }
+
+    let reencode: Option<ReencodeOptions> = match &args.profile {
+        Some(name) => match profile::resolve_profile(name, &ctx.config.profiles) {
+            Ok(reencode) => Some(reencode),
+            Err(e) => {
+                exit_with_error(e, args.json);
+            }
+        },
+        None => args.force_reencode.then(|| ReencodeOptions {
+            codec: args.reencode_codec,
+            bitrate: args.reencode_bitrate.clone(),
+            channels: None,
+        }),
+    };
+    let options: RecordOptions = RecordOptions {
+        segment_secs: args.segment_minutes.map(|m| m * 60),
+        split_at_secs: args.split_at.iter().map(|m| m * 60).collect(),
+        segment_downloader: args.segment_downloader,
+        rate_limit_kbps: args.rate_limit,
+        reencode,
+        metadata: Vec::new(),
+        cancel: None,
+        sink: None,
+        discard_partial: false,
+    };
+    let post: PostProcess = PostProcess {
+        normalize: args.normalize.then(|| NormalizeOptions {
+            target_lufs: args.normalize_lufs,
+            codec: args.normalize_codec,
+            bitrate: args.normalize_bitrate.clone(),
+            replace: args.normalize_replace,
+        }),
+        cue: args.cue,
+        sidecar: args.sidecar,
+    };
+    if options.reencode.is_some() && post.normalize.is_some() {
+        warn!("--normalize encodes the recording again after it was transcoded while recording; the audio is encoded twice");
+    }
+
+    if args.batch.is_some() || args.batch_dir.is_some() {
+        let jobs: Result<Vec<BatchJob>, Box<dyn Error>> = match (&args.batch, &args.batch_dir) {
+            (Some(batch_path), _) => batch::load_batch(batch_path),
+            (None, Some(batch_dir)) => batch::load_batch_dir(batch_dir),
+            (None, None) => unreachable!(),
+        };
+        let result: Result<(), Box<dyn Error>> = jobs.and_then(|jobs| {
+            if args.dry_run {
+                validate_batch(&ctx, &jobs)
+            } else {
+                run_batch(&mut ctx, &jobs, &options, &post, args.compact_progress)
+            }
+        });
+        exit_after_recording(&ctx, result);
+        return;
+    }
+
+    if args.since_last {
+        let Some(station_id) = args.station_id.as_deref() else {
+            exit_with_error("Station ID is required for --since-last.", args.json);
+        };
+        let result: Result<(), Box<dyn Error>> = since_last_window(&ctx, station_id).and_then(|(since, until)| {
+            let since: String = since.format("%Y%m%d%H%M%S").to_string();
+            let until: String = until.format("%Y%m%d%H%M%S").to_string();
+            record_range(&ctx, station_id, &since, &until, &options, &post)
+        });
+        exit_after_recording(&ctx, result);
+        return;
+    }
+
+    if let (Some(since), Some(until)) = (&args.since, &args.until) {
+        let Some(station_id) = args.station_id.as_deref() else {
+            exit_with_error("Station ID is required for --since/--until.", args.json);
+        };
+        let result: Result<(), Box<dyn Error>> = record_range(&ctx, station_id, since, until, &options, &post);
+        exit_after_recording(&ctx, result);
+        return;
+    }
+
+    if let Some(program_id) = &args.program_id {
+        let Some(station_id) = args.station_id.as_deref() else {
+            exit_with_error("Station ID is required for --program-id.", args.json);
+        };
+        report_result(&ctx, record_program(&ctx, station_id, args.date, program_id, &options, &post), args.json);
+        return;
+    }
+
+    // 録音モードの場合、必須の引数（station_id, start_time）が与えられているかチェック
+    if args.station_id.is_none() || args.start_time.is_none() {
+        let message: &str = "Station ID and start time are required unless using the --list-areas, --station-list, --find-station, --diff-stations, --schedule, --interactive, --list-recorded, --log-stats, --batch, --daemon or --selftest option.";
+        if args.json {
+            exit_with_error(message, true);
+        }
+        eprintln!("{}", message);
+        eprintln!("{}", Args::command().render_usage());
+        process::exit(1);
+    }
+
+    let station_id: String = args.station_id.unwrap();
+    let mut start_time: String = args.start_time.unwrap();
+    let mut duration_secs: i64 = args.duration;
+    if args.snap {
+        match snap_to_programs(&station_id, &start_time, duration_secs, args.snap_end) {
+            Ok((snapped_start, snapped_duration)) => {
+                start_time = snapped_start;
+                duration_secs = snapped_duration;
+            }
+            Err(e) => {
+                exit_with_error(e, args.json);
+            }
+        }
+    }
+    if args.output_to_stdout || args.output_fifo.is_some() {
+        let fifo_path: Option<&str> = args.output_fifo.as_deref();
+        if let Err(e) = record_to_stream(&ctx, &station_id, &start_time, duration_secs, fifo_path) {
+            exit_with_error(e, args.json);
+        }
+        return;
+    }
+    if let Some(failover_station) = args.failover_station.as_deref() {
+        let result: Result<Option<RecordOutcome>, Box<dyn Error>> = record_with_failover(
+            &ctx,
+            [&station_id, failover_station],
+            &start_time,
+            duration_secs,
+            &options,
+            &post,
+        );
+        report_result(&ctx, result, args.json);
+        return;
+    }
+    let result: Result<Option<RecordOutcome>, Box<dyn Error>> = record_radio(
+        &ctx,
+        &station_id,
+        &start_time,
+        duration_secs,
+        args.append.as_deref(),
+        &options,
+        &post,
+    );
+    report_result(&ctx, result, args.json);
+}
+
+/// 録音の成否を出力し、失敗した場合は終了コード 1 で終了する
+///
+/// `json` が指定された場合は、録音結果・録音済みでスキップした旨・エラーのいずれかを JSON で標準出力にも書き出す。
+/// 小さすぎる録音ファイルがあった場合は、一覧を表示して `SMALL_OUTPUT_EXIT_CODE` で終了する。
+fn report_result(ctx: &Context, result: Result<Option<RecordOutcome>, Box<dyn Error>>, json: bool) {
+    let has_small_outputs: bool = report_small_outputs(ctx);
+    match result {
+        Ok(Some(outcome)) if json => match serde_json::to_string_pretty(&schema::versioned(&outcome)) {
+            Ok(text) => println!("{}", text),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        },
+        Ok(None) if json => {
+            let skipped: MutexGuard<Vec<RecordedWindow>> = ctx.skipped.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(existing) = skipped.last() {
+                let skip: serde_json::Value = serde_json::json!({
+                    "skipped": true,
+                    "reason": "already recorded",
+                    "existing": existing,
+                });
+                match serde_json::to_string_pretty(&schema::versioned(&skip)) {
+                    Ok(text) => println!("{}", text),
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        process::exit(1);
+                    }
+                }
+            }
+        }
+        Ok(_) => {}
+        Err(e) => {
+            // ログの集計 (--log-stats) で失敗として数えられるよう、ログにも残す
+            error!("Recording failed: {}", e);
+            exit_with_error(e, json);
+        }
+    }
+    if has_small_outputs {
+        process::exit(SMALL_OUTPUT_EXIT_CODE);
+    }
+}
+
+/// エラーを出力して終了コード 1 で終了する
+///
+/// `json` が指定された場合は、録音の失敗と同じ形式のエラーを JSON で標準出力にも書き出す。
+fn exit_with_error(message: impl std::fmt::Display, json: bool) -> ! {
+    let message: String = message.to_string();
+    if json {
+        println!("{}", serde_json::json!({ "schema_version": SCHEMA_VERSION, "error": message }));
+    }
+    eprintln!("Error: {}", message);
+    process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(argv: &[&str]) -> Args {
+        Args::try_parse_from(std::iter::once("radiko_recorder").chain(argv.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn station_env_fills_the_shifted_positionals() {
+        // 放送局IDを省略すると、開始時刻と録音時間が 1 つずつ前の位置引数に入る
+        let mut args: Args = parse(&["20241120130000", "90"]);
+        assert_eq!(args.station_id.as_deref(), Some("20241120130000"));
+        apply_station_env(&mut args, Some("TBS".to_string())).unwrap();
+        assert_eq!(args.station_id.as_deref(), Some("TBS"));
+        assert_eq!(args.start_time.as_deref(), Some("20241120130000"));
+        assert_eq!(args.duration, 90 * 60);
+
+        // 録音時間も省略した場合はデフォルトのまま
+        let mut args: Args = parse(&["20241120130000"]);
+        apply_station_env(&mut args, Some("TBS".to_string())).unwrap();
+        assert_eq!((args.station_id.as_deref(), args.start_time.as_deref()), (Some("TBS"), Some("20241120130000")));
+        assert_eq!(args.duration, 60 * 60);
+
+        // 録音時間が不正な場合はエラー
+        let mut args: Args = parse(&["20241120130000", "abc"]);
+        assert!(apply_station_env(&mut args, Some("TBS".to_string())).is_err());
+    }
+
+    #[test]
+    fn station_on_the_command_line_wins_over_env() {
+        let mut args: Args = parse(&["QRR", "20241120130000", "30"]);
+        apply_station_env(&mut args, Some("TBS".to_string())).unwrap();
+        assert_eq!(args.station_id.as_deref(), Some("QRR"));
+        assert_eq!(args.start_time.as_deref(), Some("20241120130000"));
+        assert_eq!(args.duration, 30 * 60);
+
+        // 位置引数がない場合（放送局一覧など）は環境変数の放送局IDを使う
+        let mut args: Args = parse(&["--station-list"]);
+        apply_station_env(&mut args, Some("TBS".to_string())).unwrap();
+        assert_eq!(args.station_id.as_deref(), Some("TBS"));
+
+        // 環境変数が空の場合は何もしない
+        let mut args: Args = parse(&["20241120130000"]);
+        apply_station_env(&mut args, Some(" ".to_string())).unwrap();
+        assert_eq!(args.station_id.as_deref(), Some("20241120130000"));
     }
 }
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitStatus, Output};
+use std::sync::OnceLock;
+
+use log::{debug, info, warn};
+
+use crate::ffprobe;
+
+/// 複数の音声ファイルを再エンコードせずに連結する
+///
+/// ffmpeg の concat demuxer を使用するため、入力ファイルは同じ形式である必要がある。
+///
+/// # 引数
+/// - `inputs`: 連結するファイルパス（連結順）
+/// - `output_path`: 出力先ファイルパス
+pub fn concat_files(inputs: &[&str], output_path: &str) -> Result<(), Box<dyn Error>> {
+    // concat demuxer 用のファイルリストを作業用ディレクトリに作成
+    let list_path: PathBuf = PathBuf::from(format!("{}.txt", temp_path(output_path, "concat")));
+    let list: String = inputs
+        .iter()
+        .map(|input| {
+            let absolute: PathBuf = fs::canonicalize(input)?;
+            // シングルクォートは concat リストの書式に従ってエスケープする
+            Ok(format!("file '{}'\n", absolute.to_string_lossy().replace('\'', "'\\''")))
+        })
+        .collect::<Result<String, std::io::Error>>()?;
+    fs::write(&list_path, list)?;
+    debug!("concat list {}: {:?}", list_path.display(), inputs);
+
+    let result: Result<ExitStatus, std::io::Error> = Command::new("ffmpeg")
+        .args(["-f", "concat", "-safe", "0", "-i"])
+        .arg(&list_path)
+        .args(["-c", "copy", "-y", output_path])
+        .status();
+    let _ = fs::remove_file(&list_path);
+
+    let status: ExitStatus = result?;
+    if !status.success() {
+        return Err(format!("ffmpeg concat exited with status: {:?}", status).into());
+    }
+    info!("Concatenated {} files into {}", inputs.len(), output_path);
+    Ok(())
+}
+
+/// 再エンコード時のエンコード形式（音量の正規化と録音時の再エンコードで共通）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum NormalizeCodec {
+    Aac,
+    Mp3,
+    Opus,
+}
+
+impl NormalizeCodec {
+    /// ffmpeg のエンコーダ名
+    pub fn encoder(self) -> &'static str {
+        match self {
+            NormalizeCodec::Aac => "aac",
+            NormalizeCodec::Mp3 => "libmp3lame",
+            NormalizeCodec::Opus => "libopus",
+        }
+    }
+
+    /// 出力ファイルの拡張子
+    pub fn extension(self) -> &'static str {
+        match self {
+            NormalizeCodec::Aac => "aac",
+            NormalizeCodec::Mp3 => "mp3",
+            NormalizeCodec::Opus => "opus",
+        }
+    }
+}
+
+/// ラウドネス正規化のオプション
+#[derive(Debug, Clone)]
+pub struct NormalizeOptions {
+    /// 目標の統合ラウドネス（LUFS）
+    pub target_lufs: f64,
+    /// 出力のエンコード形式
+    pub codec: NormalizeCodec,
+    /// 出力のビットレート（例: `128k`）
+    pub bitrate: String,
+    /// 元のファイルを正規化後のファイルで置き換える
+    pub replace: bool,
+}
+
+/// ffmpeg の loudnorm フィルタで音量を正規化したファイルを作成し、そのパスを返す
+///
+/// 再エンコードを伴うため、録音時間に応じた処理時間がかかる。
+/// `replace` が指定されていない場合は `<元のファイル名>.normalized.<拡張子>` に出力する。
+/// 指定されている場合は作業用ディレクトリに出力してから元のファイルを置き換える。
+///
+/// # 引数
+/// - `input_path`: 正規化するファイルパス
+/// - `options`: 正規化のオプション
+pub fn normalize_loudness(input_path: &str, options: &NormalizeOptions) -> Result<String, Box<dyn Error>> {
+    let work_path: String = if options.replace {
+        temp_path(input_path, "normalized")
+    } else {
+        sibling_path(input_path, "normalized")
+    };
+    let normalized_path: PathBuf = Path::new(&work_path).with_extension(options.codec.extension());
+    warn!(
+        "Normalizing {} to {} LUFS; re-encoding may take a while",
+        input_path, options.target_lufs
+    );
+
+    let status: ExitStatus = Command::new("ffmpeg")
+        .args(["-i", input_path])
+        .args(["-af", &format!("loudnorm=I={}:TP=-1.5:LRA=11", options.target_lufs)])
+        .args(["-c:a", options.codec.encoder(), "-b:a", &options.bitrate, "-y"])
+        .arg(&normalized_path)
+        .status()?;
+    if !status.success() {
+        let _ = fs::remove_file(&normalized_path);
+        return Err(format!("ffmpeg loudnorm exited with status: {:?}", status).into());
+    }
+
+    if !options.replace {
+        info!("Normalized {} into {}", input_path, normalized_path.display());
+        return Ok(normalized_path.to_string_lossy().to_string());
+    }
+
+    // 置き換える場合は、拡張子のみエンコード形式に合わせた元のファイル名にする
+    let replaced_path: PathBuf = Path::new(input_path).with_extension(options.codec.extension());
+    move_file(&normalized_path, &replaced_path)?;
+    if replaced_path != Path::new(input_path) {
+        fs::remove_file(input_path)?;
+    }
+    info!("Normalized {} in place as {}", input_path, replaced_path.display());
+    Ok(replaced_path.to_string_lossy().to_string())
+}
+
+/// 既存の音声ファイルに、再エンコードせずにメタデータを書き込む
+///
+/// 作業用ファイルに書き出してから元のファイルを置き換える。ffmpeg が失敗した場合や、
+/// 書き込んだタグを ffprobe で 1 件も読み戻せない場合は、元のファイルは変更せずにエラーを返す。
+/// `.aac`（ADTS 形式）にはタグの領域がないため、ID3v2 タグとして書き込む。
+///
+/// # 引数
+/// - `path`: メタデータを書き込むファイルパス
+/// - `metadata`: 書き込むメタデータ（`title`, `artist` などのキーと値）
+pub fn write_metadata(path: &str, metadata: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+    let work_path: String = temp_path(path, "retag");
+    let mut command: Command = Command::new("ffmpeg");
+    command.args(["-i", path, "-map", "0", "-c", "copy"]);
+    for (key, value) in metadata {
+        command.args(["-metadata", &format!("{}={}", key, value)]);
+    }
+    if path.ends_with(".aac") {
+        command.args(["-write_id3v2", "1"]);
+    }
+    let status: ExitStatus = command.args(["-y", &work_path]).status()?;
+    if !status.success() {
+        let _ = fs::remove_file(&work_path);
+        return Err(format!("ffmpeg exited with status while writing metadata: {:?}", status).into());
+    }
+    match verify_metadata(&work_path, metadata) {
+        Ok(missing) if !missing.is_empty() => {
+            warn!("Tags not found in {} after writing them: {}", path, missing.join(", "))
+        }
+        Ok(_) => {}
+        Err(e) => {
+            let _ = fs::remove_file(&work_path);
+            return Err(format!("Failed to write metadata into {}: {}", path, e).into());
+        }
+    }
+    move_file(Path::new(&work_path), Path::new(path))?;
+    debug!("wrote {} metadata tags into {}", metadata.len(), path);
+    Ok(())
+}
+
+/// 書き込んだタグを ffprobe で読み戻し、見つからなかったタグ名を返す
+///
+/// 1 件も読み戻せない場合はエラーとする。形式によってはタグ名が変わる（ID3v2 の `year` など）ため、
+/// 一部のタグが見つからない場合は呼び出し側で警告にとどめる。
+fn verify_metadata<'a>(path: &str, metadata: &'a [(String, String)]) -> Result<Vec<&'a str>, Box<dyn Error>> {
+    if metadata.is_empty() {
+        return Ok(Vec::new());
+    }
+    let tags: HashMap<String, String> = ffprobe::probe(path)?.tags;
+    let missing: Vec<&str> = metadata
+        .iter()
+        .map(|(key, _)| key.as_str())
+        .filter(|key| !tags.contains_key(&key.to_lowercase()))
+        .collect();
+    if missing.len() == metadata.len() {
+        return Err("none of the tags could be read back with ffprobe".into());
+    }
+    Ok(missing)
+}
+
+/// `configure_temp_dir` で設定された作業用ディレクトリ（未設定の場合は出力ファイルと同じディレクトリ）
+static TEMP_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// `configure_min_free_space` で設定された、録音開始時に必要な空き容量（バイト、未設定の場合は確認しない）
+static MIN_FREE_SPACE: OnceLock<u64> = OnceLock::new();
+
+/// ディスクがいっぱいで書き込めなかったことを示す ffmpeg のエラー出力
+const DISK_FULL_MESSAGES: [&str; 2] = ["No space left on device", "Disk quota exceeded"];
+
+/// 空き容量の確認に使う、録音 1 秒あたりのデータ量の目安（バイト、48kbps の AAC に余裕を持たせた値）
+const ESTIMATED_BYTES_PER_SEC: u64 = 8_000;
+
+/// 作業用ファイル（分割ダウンロード・追記・正規化の中間ファイル）を置くディレクトリを設定する
+///
+/// ディレクトリが存在しなければ作成し、書き込めることを確認する。
+pub fn configure_temp_dir(dir: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create temporary directory {}: {}", dir.display(), e))?;
+    let probe: PathBuf = dir.join(format!(".radiko_recorder_probe_{}", process::id()));
+    fs::write(&probe, b"").map_err(|e| format!("Temporary directory {} is not writable: {}", dir.display(), e))?;
+    let _ = fs::remove_file(&probe);
+    info!("Using {} for intermediate files", dir.display());
+    let _ = TEMP_DIR.set(dir.to_path_buf());
+    Ok(())
+}
+
+/// 作業用ディレクトリに、出力ファイル名に接尾辞を付けた作業用ファイルパスを作成する
+///
+/// 作業用ディレクトリが設定されていない場合は `sibling_path` と同じく出力ファイルと同じディレクトリとする。
+pub fn temp_path(path: &str, suffix: &str) -> String {
+    let sibling: String = sibling_path(path, suffix);
+    match TEMP_DIR.get() {
+        Some(dir) => dir
+            .join(Path::new(&sibling).file_name().unwrap_or_default())
+            .to_string_lossy()
+            .to_string(),
+        None => sibling,
+    }
+}
+
+/// 作業用ディレクトリに、指定した録音時間分の作業用ファイルを置く空き容量があるかを確認する
+///
+/// 空き容量を調べられない環境（`df` コマンドがない場合など）では確認を省略する。
+pub fn ensure_temp_space(path: &str, duration_secs: i64) -> Result<(), Box<dyn Error>> {
+    let work_path: String = temp_path(path, "tmp");
+    let dir: &Path = parent_dir(&work_path);
+    let required: u64 = duration_secs.max(0) as u64 * ESTIMATED_BYTES_PER_SEC;
+    match available_space(dir) {
+        Some(available) if available < required => Err(format!(
+            "Not enough space for intermediate files in {}: {} bytes available, about {} bytes needed",
+            dir.display(),
+            available,
+            required
+        )
+        .into()),
+        Some(_) => Ok(()),
+        None => {
+            debug!("Could not determine free space of {}", dir.display());
+            Ok(())
+        }
+    }
+}
+
+/// 録音開始時に必要な空き容量を設定する（0 の場合は確認しない）
+pub fn configure_min_free_space(min_free_space_mb: u64) {
+    if min_free_space_mb > 0 {
+        let _ = MIN_FREE_SPACE.set(min_free_space_mb * 1024 * 1024);
+    }
+}
+
+/// 録音ファイルの出力先と一時ファイルのディレクトリに、`configure_min_free_space` で設定した空き容量があるかを確認する
+///
+/// 一時ファイルは録音後に出力先へ移動するため、両方のディレクトリを確認する（同じ場合は 1 度だけ）。
+/// 設定されていない場合や、空き容量を調べられない環境では確認を省略する。
+pub fn ensure_min_free_space(output_path: &str, temp_path: &str) -> Result<(), Box<dyn Error>> {
+    let Some(&required) = MIN_FREE_SPACE.get() else {
+        return Ok(());
+    };
+    let output_dir: &Path = parent_dir(output_path);
+    let temp_dir: &Path = parent_dir(temp_path);
+    ensure_free_space_in(output_dir, required)?;
+    if temp_dir != output_dir {
+        ensure_free_space_in(temp_dir, required)?;
+    }
+    Ok(())
+}
+
+/// ディレクトリのあるファイルシステムに `required` バイト以上の空き容量があるかを確認する
+fn ensure_free_space_in(dir: &Path, required: u64) -> Result<(), Box<dyn Error>> {
+    match available_space(dir) {
+        Some(available) if available < required => Err(format!(
+            "Not enough free space in {}: {} MB available, at least {} MB required (--min-free-space)",
+            dir.display(),
+            available / 1024 / 1024,
+            required / 1024 / 1024
+        )
+        .into()),
+        Some(available) => {
+            debug!("{} bytes free in {}", available, dir.display());
+            Ok(())
+        }
+        None => {
+            debug!("Could not determine free space of {}", dir.display());
+            Ok(())
+        }
+    }
+}
+
+/// ffmpeg のエラー出力が、ディスクがいっぱいで書き込めなかったことを示すか
+pub fn is_disk_full(stderr: &str) -> bool {
+    DISK_FULL_MESSAGES.iter().any(|message| stderr.contains(message))
+}
+
+/// ファイルのあるディレクトリ（相対パスのファイル名のみの場合はカレントディレクトリ）
+fn parent_dir(path: &str) -> &Path {
+    match Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// `df` コマンドで、ディレクトリのあるファイルシステムの空き容量（バイト）を調べる
+fn available_space(dir: &Path) -> Option<u64> {
+    let output: Output = Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text: String = String::from_utf8_lossy(&output.stdout).to_string();
+    let available_kb: u64 = text.lines().nth(1)?.split_whitespace().nth(3)?.parse().ok()?;
+    Some(available_kb * 1024)
+}
+
+/// ファイルを移動する（別のファイルシステムへの移動はコピーしてから元のファイルを削除する）
+pub fn move_file(from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// 出力ファイルと同じディレクトリに、接尾辞付きの作業用ファイルパスを作成する
+///
+/// 例: `output/TBS_20250101.aac` と `part` から `output/TBS_20250101.part.aac` を返す。
+pub fn sibling_path(path: &str, suffix: &str) -> String {
+    let path: &Path = Path::new(path);
+    let stem: String = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file_name: String = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, suffix),
+    };
+    path.with_file_name(file_name).to_string_lossy().to_string()
+}
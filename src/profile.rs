@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use clap::ValueEnum;
+
+use crate::config::ProfileConfig;
+use crate::postprocess::NormalizeCodec;
+use crate::recorder::ReencodeOptions;
+
+/// 組み込みの録音プロファイル（名前・エンコード形式・ビットレート・チャンネル数）
+pub const BUILTIN_PROFILES: [(&str, NormalizeCodec, &str, Option<u32>); 3] = [
+    ("archive", NormalizeCodec::Opus, "48k", Some(1)),
+    ("voice", NormalizeCodec::Opus, "32k", Some(1)),
+    ("mp3", NormalizeCodec::Mp3, "128k", None),
+];
+
+/// プロファイル名から再エンコードのオプションを求める
+///
+/// 設定ファイルの `[profiles.<名前>]` で定義したプロファイルは、同名の組み込みのプロファイルより優先する。
+pub fn resolve_profile(
+    name: &str,
+    custom: &HashMap<String, ProfileConfig>,
+) -> Result<ReencodeOptions, Box<dyn Error>> {
+    if let Some(profile) = custom.get(name) {
+        let codec: NormalizeCodec = NormalizeCodec::from_str(&profile.codec, true)
+            .map_err(|_| format!("Invalid codec for profile {}: {}", name, profile.codec))?;
+        if profile.channels == Some(0) {
+            return Err(format!("Invalid channel count for profile {}: 0", name).into());
+        }
+        return Ok(ReencodeOptions {
+            codec,
+            bitrate: profile.bitrate.clone(),
+            channels: profile.channels,
+        });
+    }
+    BUILTIN_PROFILES
+        .iter()
+        .find(|(builtin, ..)| *builtin == name)
+        .map(|(_, codec, bitrate, channels)| ReencodeOptions {
+            codec: *codec,
+            bitrate: bitrate.to_string(),
+            channels: *channels,
+        })
+        .ok_or_else(|| {
+            let mut names: Vec<&str> = BUILTIN_PROFILES.iter().map(|(builtin, ..)| *builtin).collect();
+            names.extend(custom.keys().map(String::as_str));
+            names.sort();
+            format!("Unknown profile: {} (available: {})", name, names.join(", ")).into()
+        })
+}
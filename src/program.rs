@@ -0,0 +1,447 @@
+use std::error::Error;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use log::debug;
+use quick_xml::de::from_str;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::cache;
+use crate::datetime;
+use crate::http_client::{build_client, send_with_retry};
+
+/// 番組情報
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Program {
+    #[serde(rename(deserialize = "@id"))]
+    pub id: String,
+    /// 放送局ID（番組表 XML では親要素の属性のため、取得後に設定する）
+    #[serde(skip_deserializing, default)]
+    pub station_id: String,
+    /// 放送開始日時（JST）
+    #[serde(rename(deserialize = "@ft"), deserialize_with = "deserialize_datetime")]
+    pub ft: NaiveDateTime,
+    /// 放送終了日時（JST）
+    #[serde(rename(deserialize = "@to"), deserialize_with = "deserialize_datetime")]
+    pub to: NaiveDateTime,
+    pub title: String,
+    /// 出演者
+    #[serde(default)]
+    pub pfm: String,
+    /// 番組説明（HTML）
+    #[serde(default)]
+    pub desc: String,
+    /// 番組の補足情報（HTML）
+    #[serde(default)]
+    pub info: String,
+    /// 番組画像の URL
+    #[serde(default)]
+    pub img: String,
+    /// 番組ページの URL
+    #[serde(default)]
+    pub url: String,
+}
+
+/// 番組表上の日付を付けた番組（複数日の番組表をまとめて出力する場合に使う）
+#[derive(Debug, Clone, Serialize)]
+pub struct DatedProgram {
+    /// 番組表上の日付（5:00 始まり）
+    pub date: NaiveDate,
+    #[serde(flatten)]
+    pub program: Program,
+}
+
+/// 放送局で現在放送中の番組
+#[derive(Debug, Clone, Serialize)]
+pub struct OnAir {
+    pub station_id: String,
+    pub station_name: String,
+    pub program: Program,
+    /// 放送終了までの残り時間（秒）
+    pub remaining_secs: i64,
+}
+
+/// 番組表 XML のルート要素
+#[derive(Debug, Deserialize)]
+struct ScheduleXml {
+    stations: ScheduleStations,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScheduleStations {
+    #[serde(rename = "station", default)]
+    stations: Vec<ScheduleStation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScheduleStation {
+    #[serde(rename = "@id")]
+    id: String,
+    progs: ScheduleProgs,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScheduleProgs {
+    #[serde(rename = "prog", default)]
+    programs: Vec<Program>,
+}
+
+/// "YYYYMMDDHHMMSS" 形式の日時をデシリアライズする
+fn deserialize_datetime<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: String = String::deserialize(deserializer)?;
+    NaiveDateTime::parse_from_str(&s, "%Y%m%d%H%M%S").map_err(serde::de::Error::custom)
+}
+
+/// 指定したエリアまたは放送局・日付の番組表を取得する
+///
+/// `JP13` のようなエリアIDを指定した場合はエリア内の全放送局の番組を、
+/// それ以外は放送局IDとしてその放送局の番組を返す。
+///
+/// # 引数
+/// - `area_or_station`: エリアIDまたは放送局ID
+/// - `date`: 番組表の日付（5:00 始まりの番組表上の日付）
+pub fn fetch_schedule(area_or_station: &str, date: NaiveDate) -> Result<Vec<Program>, Box<dyn Error>> {
+    if let Some(content) = cache::load_schedule(area_or_station, date) {
+        return parse_schedule(&content);
+    }
+    let url: String = if is_area_id(area_or_station) {
+        format!(
+            "https://radiko.jp/v3/program/date/{}/{}.xml",
+            date.format("%Y%m%d"),
+            area_or_station
+        )
+    } else {
+        format!(
+            "https://radiko.jp/v3/program/station/date/{}/{}.xml",
+            date.format("%Y%m%d"),
+            area_or_station
+        )
+    };
+    debug!("fetching schedule: {}", url);
+    let resp: reqwest::blocking::Response = send_with_retry(build_client()?.get(&url))?;
+    if !resp.status().is_success() {
+        return Err(format!("Failed to fetch schedule from {}: {}", url, resp.status()).into());
+    }
+    let content: String = resp.text()?;
+    let programs: Vec<Program> = parse_schedule(&content)?;
+    cache::store_schedule(area_or_station, date, &content);
+    Ok(programs)
+}
+
+/// 番組表 XML を解析し、各番組に放送局IDを設定して返す
+pub fn parse_schedule(content: &str) -> Result<Vec<Program>, Box<dyn Error>> {
+    let schedule: ScheduleXml = from_str(content)?;
+    Ok(schedule
+        .stations
+        .stations
+        .into_iter()
+        .flat_map(|station| {
+            let station_id: String = station.id;
+            station.progs.programs.into_iter().map(move |mut program| {
+                program.station_id = station_id.clone();
+                program
+            })
+        })
+        .collect())
+}
+
+/// 指定した放送局・日付の番組表を取得する
+///
+/// # 引数
+/// - `station_id`: 放送局ID
+/// - `date`: 番組表の日付（5:00 始まりの番組表上の日付）
+pub fn fetch_station_schedule(station_id: &str, date: NaiveDate) -> Result<Vec<Program>, Box<dyn Error>> {
+    fetch_schedule(station_id, date)
+}
+
+/// エリアID（`JP` と数字）の形式かどうか
+fn is_area_id(id: &str) -> bool {
+    id.strip_prefix("JP")
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// 指定した時間帯（JST）と重なる番組を、番組表から開始日時の順に取得する
+///
+/// 時間帯にかかる番組表の日付をすべて取得する。
+pub fn fetch_programs_between(
+    station_id: &str,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+) -> Result<Vec<Program>, Box<dyn Error>> {
+    let mut programs: Vec<Program> = Vec::new();
+    let mut date: NaiveDate = datetime::program_date(since);
+    while date <= datetime::program_date(until - Duration::seconds(1)) {
+        programs.extend(fetch_station_schedule(station_id, date)?);
+        date += Duration::days(1);
+    }
+    let mut programs: Vec<Program> = stitch_programs(programs);
+    programs.retain(|p| p.ft < until && p.to > since);
+    Ok(programs)
+}
+
+/// 番組表の日付の境界（5:00）かどうか
+fn is_program_day_start(dt: NaiveDateTime) -> bool {
+    dt.time() == NaiveTime::from_hms_opt(datetime::PROGRAM_DAY_START_HOUR, 0, 0).unwrap()
+}
+
+/// 複数の日付の番組表から取得した番組を開始日時の順に並べ、番組表の境界（5:00）をまたぐ番組をまとめる
+///
+/// 境界をまたぐ番組は、両方の日付の番組表に同じ番組として載る場合と、5:00 で 2 つに分かれて載る場合がある。
+/// 前者は 1 つにまとめ、後者は 5:00 で接する同じ番組名の番組を 1 つの番組としてつなげる。
+pub fn stitch_programs(mut programs: Vec<Program>) -> Vec<Program> {
+    programs.sort_by_key(|p| (p.ft, p.to));
+    let mut stitched: Vec<Program> = Vec::with_capacity(programs.len());
+    for program in programs {
+        if let Some(last) = stitched.last_mut() {
+            if last.id == program.id && last.ft == program.ft {
+                last.to = last.to.max(program.to);
+                continue;
+            }
+            if last.to == program.ft && last.title == program.title && is_program_day_start(program.ft) {
+                debug!("stitching {} across the program day boundary at {}", program.title, program.ft);
+                last.to = program.to;
+                continue;
+            }
+        }
+        stitched.push(program);
+    }
+    stitched
+}
+
+/// 指定した放送局・日付の番組表から、番組IDが一致する番組を取得する
+///
+/// 番組表に見つからない場合はエラーを返す。
+pub fn find_program_by_id(station_id: &str, date: NaiveDate, program_id: &str) -> Result<Program, Box<dyn Error>> {
+    fetch_station_schedule(station_id, date)?
+        .into_iter()
+        .find(|p| p.id == program_id)
+        .ok_or_else(|| {
+            format!(
+                "Program {} not found in the schedule of {} on {}",
+                program_id,
+                station_id,
+                date.format("%Y-%m-%d")
+            )
+            .into()
+        })
+}
+
+/// 番組表から指定日時（JST）に放送中の番組を探す
+///
+/// # 引数
+/// - `station_id`: 放送局ID
+/// - `at`: 日時（JST）
+///
+/// 見つかった番組が 5:00 ちょうどに始まる場合（または見つからない場合）は、前日の番組表から始まって
+/// 境界をまたぐ番組の可能性があるため、前日の番組表も取得してつなげてから探す。
+pub fn find_program_at(station_id: &str, at: NaiveDateTime) -> Result<Option<Program>, Box<dyn Error>> {
+    let date: NaiveDate = datetime::program_date(at);
+    let programs: Vec<Program> = fetch_station_schedule(station_id, date)?;
+    let found: Option<Program> = programs.iter().find(|p| p.ft <= at && at < p.to).cloned();
+    if found.as_ref().is_some_and(|p| !is_program_day_start(p.ft)) {
+        return Ok(found);
+    }
+    let mut programs: Vec<Program> = programs;
+    match fetch_station_schedule(station_id, date - Duration::days(1)) {
+        Ok(previous) => programs.extend(previous),
+        Err(e) => {
+            debug!("could not fetch the previous schedule of {}: {}", station_id, e);
+            return Ok(found);
+        }
+    }
+    Ok(stitch_programs(programs).into_iter().find(|p| p.ft <= at && at < p.to))
+}
+
+/// 番組説明の HTML をプレーンテキストに変換する
+pub fn html_to_text(html: &str) -> String {
+    let br: Regex = Regex::new(r"(?i)<br\s*/?>|</p>").unwrap();
+    let tag: Regex = Regex::new(r"<[^>]*>").unwrap();
+    let text: String = br.replace_all(html, "\n").to_string();
+    let text: String = tag.replace_all(&text, "").to_string();
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// エリア単位の番組表（`/v3/program/date/<日付>/<エリアID>.xml`）
+    const AREA_SCHEDULE_XML: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<radiko>
+  <ttl>1800</ttl>
+  <srvtime>1732500000</srvtime>
+  <stations>
+    <station id="TBS">
+      <name>TBSラジオ</name>
+      <progs>
+        <date>20241125</date>
+        <prog id="100" master_id="" ft="20241125220000" to="20241125230000" ftl="2200" tol="2300" dur="3600">
+          <title>Show A</title>
+          <url>https://www.tbsradio.jp/a/</url>
+          <failed_record>0</failed_record>
+          <ts_in_ng>0</ts_in_ng>
+          <desc></desc>
+          <info>&lt;p&gt;Guest: someone&lt;/p&gt;</info>
+          <pfm>Host A</pfm>
+          <img>https://radiko.jp/res/program/DEFAULT_IMAGE/TBS/a.png</img>
+          <genre><personality id="C008"><name>Talk</name></personality></genre>
+          <metas><meta name="twitter" value="#showa"/></metas>
+        </prog>
+      </progs>
+    </station>
+    <station id="QRR">
+      <name>文化放送</name>
+      <progs>
+        <date>20241125</date>
+        <prog id="200" master_id="" ft="20241125220000" to="20241126000000" ftl="2200" tol="2400" dur="7200">
+          <title>Show B</title>
+          <url></url>
+          <desc>&lt;p&gt;Music&lt;/p&gt;</desc>
+          <info></info>
+          <pfm></pfm>
+          <img>https://radiko.jp/res/program/DEFAULT_IMAGE/QRR/b.png</img>
+        </prog>
+      </progs>
+    </station>
+  </stations>
+</radiko>
+"##;
+
+    /// 放送局単位の番組表（`/v3/program/station/date/<日付>/<放送局ID>.xml`）
+    const STATION_SCHEDULE_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<radiko>
+  <ttl>1800</ttl>
+  <srvtime>1732500000</srvtime>
+  <stations>
+    <station id="TBS">
+      <name>TBSラジオ</name>
+      <progs>
+        <date>20241125</date>
+        <prog id="100" master_id="" ft="20241125220000" to="20241125230000" ftl="2200" tol="2300" dur="3600">
+          <title>Show A</title>
+          <url>https://www.tbsradio.jp/a/</url>
+          <info>&lt;p&gt;Guest: someone&lt;/p&gt;</info>
+          <img>https://radiko.jp/res/program/DEFAULT_IMAGE/TBS/a.png</img>
+        </prog>
+        <prog id="101" master_id="" ft="20241125230000" to="20241126000000" ftl="2300" tol="2400" dur="3600">
+          <title>Show C</title>
+        </prog>
+      </progs>
+    </station>
+  </stations>
+</radiko>
+"#;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, mo, d).unwrap().and_hms_opt(h, mi, 0).unwrap()
+    }
+
+    fn program(id: &str, title: &str, ft: NaiveDateTime, to: NaiveDateTime) -> Program {
+        Program {
+            id: id.to_string(),
+            station_id: "TBS".to_string(),
+            ft,
+            to,
+            title: title.to_string(),
+            pfm: String::new(),
+            desc: String::new(),
+            info: String::new(),
+            img: String::new(),
+            url: String::new(),
+        }
+    }
+
+    #[test]
+    fn parses_area_schedule() {
+        let programs: Vec<Program> = parse_schedule(AREA_SCHEDULE_XML).unwrap();
+        assert_eq!(programs.len(), 2);
+
+        let a: &Program = &programs[0];
+        assert_eq!(a.station_id, "TBS");
+        assert_eq!(a.id, "100");
+        assert_eq!(a.title, "Show A");
+        assert_eq!(a.pfm, "Host A");
+        assert_eq!(a.info, "<p>Guest: someone</p>");
+        assert_eq!(a.img, "https://radiko.jp/res/program/DEFAULT_IMAGE/TBS/a.png");
+        assert_eq!(a.url, "https://www.tbsradio.jp/a/");
+        assert_eq!((a.ft, a.to), (at(2024, 11, 25, 22, 0), at(2024, 11, 25, 23, 0)));
+
+        let b: &Program = &programs[1];
+        assert_eq!(b.station_id, "QRR");
+        assert_eq!(b.desc, "<p>Music</p>");
+        assert_eq!(b.info, "");
+        assert_eq!(b.url, "");
+        assert_eq!(b.img, "https://radiko.jp/res/program/DEFAULT_IMAGE/QRR/b.png");
+        assert_eq!(b.to, at(2024, 11, 26, 0, 0));
+    }
+
+    #[test]
+    fn parses_station_schedule() {
+        let programs: Vec<Program> = parse_schedule(STATION_SCHEDULE_XML).unwrap();
+        let ids: Vec<&str> = programs.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, ["100", "101"]);
+        assert!(programs.iter().all(|p| p.station_id == "TBS"));
+        assert_eq!(programs[0].info, "<p>Guest: someone</p>");
+        assert_eq!(programs[0].img, "https://radiko.jp/res/program/DEFAULT_IMAGE/TBS/a.png");
+        assert_eq!(programs[0].url, "https://www.tbsradio.jp/a/");
+        // 省略された要素は空文字列になる
+        assert_eq!((programs[1].info.as_str(), programs[1].img.as_str(), programs[1].url.as_str()), ("", "", ""));
+    }
+
+    #[test]
+    fn stitch_merges_program_listed_on_both_days() {
+        // 前日と当日の両方の番組表に同じ番組IDで載る場合（当日側は 5:00 以降の部分のみ）
+        let programs: Vec<Program> = stitch_programs(vec![
+            program("200", "Late Show", at(2024, 11, 26, 4, 30), at(2024, 11, 26, 5, 0)),
+            program("201", "Morning", at(2024, 11, 26, 5, 30), at(2024, 11, 26, 6, 0)),
+            program("200", "Late Show", at(2024, 11, 26, 4, 30), at(2024, 11, 26, 5, 30)),
+        ]);
+        let spans: Vec<(&str, NaiveDateTime, NaiveDateTime)> =
+            programs.iter().map(|p| (p.id.as_str(), p.ft, p.to)).collect();
+        assert_eq!(
+            spans,
+            [
+                ("200", at(2024, 11, 26, 4, 30), at(2024, 11, 26, 5, 30)),
+                ("201", at(2024, 11, 26, 5, 30), at(2024, 11, 26, 6, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn stitch_joins_halves_split_at_program_day_start() {
+        // 04:30-05:30 の番組が 5:00 で 2 つに分かれて載る場合
+        let programs: Vec<Program> = stitch_programs(vec![
+            program("301", "Late Show", at(2024, 11, 26, 5, 0), at(2024, 11, 26, 5, 30)),
+            program("300", "Late Show", at(2024, 11, 26, 4, 30), at(2024, 11, 26, 5, 0)),
+        ]);
+        assert_eq!(programs.len(), 1);
+        assert_eq!(programs[0].id, "300");
+        assert_eq!((programs[0].ft, programs[0].to), (at(2024, 11, 26, 4, 30), at(2024, 11, 26, 5, 30)));
+    }
+
+    #[test]
+    fn stitch_keeps_same_title_apart_outside_program_day_start() {
+        // 5:00 以外で接する同じ番組名の番組（帯番組の前半・後半など）はつなげない
+        let programs: Vec<Program> = stitch_programs(vec![
+            program("400", "News", at(2024, 11, 26, 6, 0), at(2024, 11, 26, 6, 30)),
+            program("401", "News", at(2024, 11, 26, 6, 30), at(2024, 11, 26, 7, 0)),
+            program("402", "Other", at(2024, 11, 26, 4, 30), at(2024, 11, 26, 5, 0)),
+            program("403", "Late Show", at(2024, 11, 26, 5, 0), at(2024, 11, 26, 5, 30)),
+        ]);
+        let ids: Vec<&str> = programs.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, ["402", "403", "400", "401"]);
+    }
+}
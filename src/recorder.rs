@@ -1,132 +1,1836 @@
-use chrono::{DateTime, Duration, Local};
-use log::{debug, info};
+use chrono::{DateTime, Duration, FixedOffset, Local};
+use log::{debug, info, warn};
 use quick_xml::de::from_str;
-use reqwest;
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::process::Command;
+use regex::Regex;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
 use std::error::Error;
+use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Instant;
 
-use crate::auth_handler::RadikoAuthHandler;
+use crate::auth_handler::{self, RadikoAuthHandler};
+use crate::cache;
+use crate::config::{all_area_ids, AuthConfig, Config, EndpointConfig, FfmpegLogMode, FfmpegTuning, FilenameConfig, PreferPremium, StationListVersion};
+use crate::datetime;
+use crate::duration;
+use crate::ffmpeg_retry::{self, FfmpegRetryPolicy};
+use crate::ffprobe::{self, ProbeInfo, StreamInfo};
+use crate::filename::{self, FilenameFields};
+use crate::http_client::{self, build_client, send_with_retry};
+use crate::library;
+use crate::postprocess::{self, NormalizeCodec};
+use crate::program;
+use crate::segment_downloader;
+use crate::sink::{LocalSink, OutputSink};
 
 /// 放送局情報
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Station {
     pub id: String,
     pub name: String,
     pub ascii_name: String,
+    /// 読み仮名（v2 の放送局リストには含まれないため空になる）
+    #[serde(default)]
     pub ruby: String,
 }
 
-/// XMLのルート要素として放送局リストを受け取るための構造体
+impl Station {
+    /// 放送局名の一部（大文字・小文字、全角・半角、ひらがな・カタカナを区別しない）で放送局を検索する
+    ///
+    /// 放送局ID・放送局名・英字名・読み仮名（`ruby`）のいずれかに一致した放送局を、リストの順に返す。
+    /// 例えば `ぶんかほうそう` でも `ブンカ` でも、読み仮名が `ぶんかほうそう` の放送局に一致する。
+    pub fn find_by_name<'a>(stations: &'a [Station], query: &str) -> Vec<&'a Station> {
+        let query: String = normalize_name(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+        stations
+            .iter()
+            .filter(|s| {
+                [&s.id, &s.name, &s.ascii_name, &s.ruby]
+                    .iter()
+                    .any(|field| normalize_name(field).contains(&query))
+            })
+            .collect()
+    }
+
+    /// 放送局名から推測した放送の種類（`FM`・`AM`・`other`）
+    ///
+    /// AM 局と同系列の FM 局など、名前の似た放送局を取り違えないための目安であり、
+    /// 名前に `FM`・`AM` を含むか、`ラジオ`・`放送` で終わるかで判定する。
+    pub fn band_hint(&self) -> &'static str {
+        let name: String = normalize_name(&format!("{} {}", self.name, self.ascii_name));
+        if name.contains("FM") {
+            "FM"
+        } else if name.contains("AM") || self.name.ends_with("ラジオ") || self.name.ends_with("放送") {
+            "AM"
+        } else {
+            "other"
+        }
+    }
+}
+
+/// 放送局名の比較用に、全角英数字を半角に、カタカナをひらがなにして大文字にそろえる
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '！'..='～' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect::<String>()
+        .to_uppercase()
+}
+
+
+/// タイムフリーのストリームURLのデフォルトテンプレート
+///
+/// `{station_id}`, `{ft}`, `{to}` はそれぞれ放送局ID、開始日時、終了日時に置換される。
+pub const DEFAULT_STREAM_URL_TEMPLATE: &str =
+    "https://radiko.jp/v2/api/ts/playlist.m3u8?station_id={station_id}&l=15&ft={ft}&to={to}";
+
+/// 出力ディレクトリを作成し、設定のテンプレートに従った出力ファイルパスを返す
+///
+/// デフォルトのファイル名は `<放送局ID>_<YYYYMMDDHHMMSS>.aac`（録音を実行した日時）となる。
+/// テンプレートに `{title}` が含まれ、`title` が指定されていない場合は、
+/// 番組表から開始日時に放送中の番組名を取得する。
+pub fn output_file_path(
+    output_dir: &Path,
+    station_id: &str,
+    start_time: DateTime<FixedOffset>,
+    title: Option<&str>,
+    config: &FilenameConfig,
+) -> Result<PathBuf, Box<dyn Error>> {
+    // 出力ディレクトリを作成（存在しなければ）
+    if !output_dir.exists() {
+        fs::create_dir_all(output_dir)?;
+    }
+
+    let template: &str = &config.template;
+    let title: Option<String> = if let Some(title) = title {
+        Some(title.to_string())
+    } else if template.contains("{title}") {
+        match program::find_program_at(station_id, start_time.naive_local()) {
+            Ok(program) => program.map(|p| p.title),
+            Err(e) => {
+                warn!("Failed to look up the program title for {}: {}", station_id, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let fields: FilenameFields = FilenameFields {
+        station_id,
+        start_time,
+        recorded_at: Local::now(),
+        title: title.as_deref(),
+    };
+    let stem: String = filename::expand_template(template, &fields, &config.replacement, config.max_bytes);
+    if stem.is_empty() {
+        return Err(format!("File name template produced an empty name: {}", template).into());
+    }
+    let path: PathBuf = output_dir.join(format!("{}.aac", stem));
+    info!("Output file: {}", path.display());
+    Ok(path)
+}
+
+
+/// 分割録音の出力ファイル名のパターン（ffmpeg の segment muxer 形式）を返す
+///
+/// 例: `output/TBS_20250101.aac` から `output/TBS_20250101_%03d.aac` を返す。
+pub fn segment_pattern(output_path: &str) -> String {
+    let path: &Path = Path::new(output_path);
+    let stem: String = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file_name: String = match path.extension() {
+        Some(ext) => format!("{}_%03d.{}", stem, ext.to_string_lossy()),
+        None => format!("{}_%03d", stem),
+    };
+    path.with_file_name(file_name).to_string_lossy().to_string()
+}
+
+/// 分割録音で出力されたファイルのパスを連番順に返す
+pub fn segment_files(output_path: &str) -> Vec<String> {
+    let pattern: String = segment_pattern(output_path);
+    (0..)
+        .map(|i: u32| pattern.replace("%03d", &format!("{:03}", i)))
+        .take_while(|p| Path::new(p).exists())
+        .collect()
+}
+
+/// 録音の進行状況を通知するイベント
+///
+/// 複数の録音を並行して監視する場合は、録音ごとに `Sender` を渡すことで
+/// 受信側で集約できる。
+#[derive(Debug, Clone)]
+pub enum RecordEvent {
+    /// 録音を開始した
+    Started { station_id: String, output_path: String },
+    /// 録音の進捗率（0.0〜100.0）
+    Progress { pct: f64 },
+    /// 録音が正常に完了した
+    Finished { output_path: String },
+    /// 録音に失敗した
+    Failed { error: String },
+}
+
+/// `configure_endpoints` で設定された放送局リストの API のバージョン（未設定の場合は v3）
+static STATION_LIST_VERSION: OnceLock<StationListVersion> = OnceLock::new();
+
+/// Radiko API のエンドポイントの設定を反映する
+pub fn configure_endpoints(config: &EndpointConfig) {
+    let _ = STATION_LIST_VERSION.set(config.station_list_version);
+}
+
+/// 実行中に取得済みの放送局リスト（キャッシュのキーごと）
+///
+/// 放送局名の取得やエリアの確認などで同じエリアのリストを何度も取得しないよう、プロセス内で使い回す。
+static FETCHED_STATION_LISTS: Mutex<BTreeMap<String, Vec<Station>>> = Mutex::new(BTreeMap::new());
+
+/// 指定エリアの放送局リストを取得する
+///
+/// 放送局リストの取得には認可は不要。API のバージョンは `configure_endpoints` の設定に従う。
+/// 一度取得したエリアのリストは実行中は使い回す。
+/// キャッシュが有効な場合（`cache::configure_cache`）は、有効期間内のキャッシュを使い、取得した結果をキャッシュに保存する。
+pub fn fetch_station_list(area_id: &str) -> Result<Vec<Station>, Box<dyn Error>> {
+    let version: StationListVersion = STATION_LIST_VERSION.get().copied().unwrap_or_default();
+    // バージョンごとに内容が異なるため、v3 以外はキャッシュのキーを分ける
+    let cache_key: String = match version {
+        StationListVersion::V2 => format!("{}_v2", area_id),
+        StationListVersion::V3 => area_id.to_string(),
+    };
+    if let Some(stations) = FETCHED_STATION_LISTS.lock().ok().and_then(|lists| lists.get(&cache_key).cloned()) {
+        return Ok(stations);
+    }
+    let stations: Vec<Station> = load_station_list(area_id, &cache_key, version)?;
+    if let Ok(mut lists) = FETCHED_STATION_LISTS.lock() {
+        lists.insert(cache_key, stations.clone());
+    }
+    Ok(stations)
+}
+
+/// 放送局リストをキャッシュから読み込むか、Radiko から取得する
+fn load_station_list(
+    area_id: &str,
+    cache_key: &str,
+    version: StationListVersion,
+) -> Result<Vec<Station>, Box<dyn Error>> {
+    if let Some(content) = cache::load_station_list(cache_key) {
+        match parse_station_list(&content) {
+            Ok(stations) => return Ok(stations),
+            Err(e) => warn!("Ignoring the unreadable cached station list of {}: {}", area_id, e),
+        }
+    }
+    let url: String = match version {
+        StationListVersion::V2 => format!("https://radiko.jp/v2/station/list/{}.xml", area_id),
+        StationListVersion::V3 => format!("https://radiko.jp/v3/station/list/{}.xml", area_id),
+    };
+    debug!("fetching station list: {}", url);
+    let resp: reqwest::blocking::Response = send_with_retry(build_client()?.get(&url))?;
+    // エラーページをキャッシュしないよう、成功した応答のみ解析する
+    if !resp.status().is_success() {
+        return Err(format!("Failed to fetch station list from {}: {}", url, resp.status()).into());
+    }
+    // gzip 圧縮されたレスポンスは text() の時点で展開済み
+    let content: String = resp.text()?;
+
+    // 放送局が 1 件もない場合は解析エラーとなるため、空のリストはキャッシュしない
+    let stations: Vec<Station> = parse_station_list(&content)?;
+    cache::store_station_list(cache_key, &content);
+    Ok(stations)
+}
+
+/// 放送局リストの XML を `<station>` 要素ごとに解析する
+///
+/// 一部の要素が不正でも残りの放送局を返せるよう、要素単位で解析し、
+/// 解析できなかった要素は件数と内容を警告としてログに出力する。
+/// `<station>` 要素が 1 件もない場合（エラーページなど）や、放送局が 1 件も解析できなかった場合はエラーとする。
+pub fn parse_station_list(content: &str) -> Result<Vec<Station>, Box<dyn Error>> {
+    parse_station_elements::<Station>(content)
+}
+
+/// `<station>` 要素を 1 件ずつ指定した型として解析する（`parse_station_list` を参照）
+fn parse_station_elements<T: DeserializeOwned>(content: &str) -> Result<Vec<T>, Box<dyn Error>> {
+    let element: Regex = Regex::new(r"(?s)<station[\s>].*?</station>").unwrap();
+    let mut stations: Vec<T> = Vec::new();
+    let mut skipped: usize = 0;
+    for m in element.find_iter(content) {
+        match from_str::<T>(m.as_str()) {
+            Ok(station) => stations.push(station),
+            Err(e) => {
+                skipped += 1;
+                warn!("Skipped malformed station entry ({}): {}", e, m.as_str());
+            }
+        }
+    }
+    if skipped == 0 && stations.is_empty() {
+        return Err("No <station> elements found in the station list".into());
+    }
+    if skipped > 0 {
+        warn!("Skipped {} malformed station entries out of {}", skipped, skipped + stations.len());
+        if stations.is_empty() {
+            return Err(format!("Failed to parse all {} station entries", skipped).into());
+        }
+    }
+    Ok(stations)
+}
+
+/// 全エリアの放送局リストを取得する際の並列度
+#[derive(Debug, Clone, Copy)]
+struct AreaFetchSettings {
+    concurrency: usize,
+    delay: std::time::Duration,
+}
+
+/// エリアIDとそのエリアの放送局リスト
+pub type AreaStations = (String, Vec<Station>);
+
+/// 取得順を表す番号と、1 エリア分の取得結果
+type AreaFetchResult = (usize, Result<Vec<Station>, String>);
+
+/// `configure_area_fetch` で設定された並列度（未設定の場合は 4 並列・間隔なし）
+static AREA_FETCH: OnceLock<AreaFetchSettings> = OnceLock::new();
+
+/// 全エリアの放送局リストを取得する際の並列度を設定する
+///
+/// # 引数
+/// - `concurrency`: 同時に行うリクエスト数（0 の場合は 1 とする）
+/// - `delay_ms`: 各スレッドでリクエストを行う間隔（ミリ秒）
+pub fn configure_area_fetch(concurrency: usize, delay_ms: u64) {
+    let _ = AREA_FETCH.set(AreaFetchSettings {
+        concurrency: concurrency.max(1),
+        delay: std::time::Duration::from_millis(delay_ms),
+    });
+}
+
+/// 全エリアの放送局リストを、設定された並列度で取得する
+///
+/// 各エリアの取得が完了するたびに進捗をログに出力する。
+/// 結果はエリアIDの順（JP1〜JP47）に並べて返し、1 つでも失敗した場合はエラーを返す。
+pub fn fetch_all_station_lists() -> Result<Vec<AreaStations>, Box<dyn Error>> {
+    let settings: AreaFetchSettings = AREA_FETCH.get().copied().unwrap_or(AreaFetchSettings {
+        concurrency: 4,
+        delay: std::time::Duration::ZERO,
+    });
+    let areas: Vec<String> = all_area_ids().collect();
+    let next: AtomicUsize = AtomicUsize::new(0);
+    let completed: AtomicUsize = AtomicUsize::new(0);
+    let results: Mutex<Vec<AreaFetchResult>> = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..settings.concurrency.min(areas.len()) {
+            scope.spawn(|| {
+                let mut first: bool = true;
+                loop {
+                    let index: usize = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(area_id) = areas.get(index) else {
+                        break;
+                    };
+                    if !first && !settings.delay.is_zero() {
+                        thread::sleep(settings.delay);
+                    }
+                    first = false;
+                    // Box<dyn Error> はスレッド間で受け渡せないため、文字列にして持ち帰る
+                    let result: Result<Vec<Station>, String> =
+                        fetch_station_list(area_id).map_err(|e| format!("{}: {}", area_id, e));
+                    let done: usize = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                    info!("Fetched station list of {} ({}/{})", area_id, done, areas.len());
+                    if let Ok(mut guard) = results.lock() {
+                        guard.push((index, result));
+                    }
+                }
+            });
+        }
+    });
+
+    let mut results: Vec<AreaFetchResult> =
+        results.into_inner().map_err(|_| "Station list results lock poisoned")?;
+    results.sort_by_key(|(index, _)| *index);
+    results
+        .into_iter()
+        .map(|(index, result)| Ok((areas[index].clone(), result?)))
+        .collect()
+}
+
+/// 全国の放送局を地方ごとにまとめたリストのURL（各放送局の本来のエリアIDを含む）
+const STATION_REGION_LIST_URL: &str = "https://radiko.jp/v3/station/region/full.xml";
+
+/// 全国の放送局リストの 1 件分
 #[derive(Debug, Deserialize)]
-struct StationList {
-    #[serde(rename = "station")]
-    stations: Vec<Station>,
+struct RegionStation {
+    id: String,
+    area_id: String,
+}
+
+/// 指定した放送局を含むエリアIDの一覧を返す
+///
+/// 全国の放送局リストを 1 回のリクエストで取得し、放送局の本来のエリアを返す。
+/// 取得できない場合は、全エリアの放送局リストを設定された並列度で取得して探す。
+pub fn find_station_areas(station_id: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    match fetch_region_stations() {
+        Ok(stations) => {
+            return Ok(stations
+                .into_iter()
+                .filter(|s| s.id == station_id)
+                .map(|s| s.area_id)
+                .collect())
+        }
+        Err(e) => warn!("Failed to fetch the nationwide station list; searching each area: {}", e),
+    }
+    Ok(fetch_all_station_lists()?
+        .into_iter()
+        .filter(|(_, stations)| stations.iter().any(|s| s.id == station_id))
+        .map(|(area_id, _)| area_id)
+        .collect())
+}
+
+/// 全国の放送局リストを取得する
+fn fetch_region_stations() -> Result<Vec<RegionStation>, Box<dyn Error>> {
+    debug!("fetching station list: {}", STATION_REGION_LIST_URL);
+    let resp: reqwest::blocking::Response = send_with_retry(build_client()?.get(STATION_REGION_LIST_URL))?;
+    if !resp.status().is_success() {
+        return Err(format!("Failed to fetch the nationwide station list (HTTP {})", resp.status()).into());
+    }
+    parse_station_elements::<RegionStation>(&resp.text()?)
+}
+
+/// 放送局が指定エリアで聴取可能かを確認する
+///
+/// 聴取できない場合は、その放送局を聴取できるエリアの一覧をエラーメッセージに含める。
+/// エリア外の放送局の録音（エリアフリー）には Radiko プレミアム会員の認可が必要となる
+/// （`AuthConfig::prefer_premium` を参照）。
+pub fn check_station_area(area_id: &str, station_id: &str) -> Result<(), Box<dyn Error>> {
+    match station_area_mismatch(area_id, station_id)? {
+        Some(message) => Err(message.into()),
+        None => Ok(()),
+    }
+}
+
+/// 放送局が指定エリアで聴取できない場合に、その理由と聴取できるエリアを示すメッセージを返す
+fn station_area_mismatch(area_id: &str, station_id: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let stations: Vec<Station> = fetch_station_list(area_id)?;
+    if stations.iter().any(|s| s.id == station_id) {
+        return Ok(None);
+    }
+
+    warn!("{} is not in area {}; searching other areas", station_id, area_id);
+    let areas: Vec<String> = find_station_areas(station_id)?;
+    if areas.is_empty() {
+        return Ok(Some(format!("Station {} was not found in any area", station_id)));
+    }
+    Ok(Some(format!(
+        "Station {} is not available in area {}; it is available in {} (use --area-id {})",
+        station_id,
+        area_id,
+        areas.join(", "),
+        areas[0]
+    )))
+}
+
+/// ffmpeg の `-headers` に渡す文字列を作成する（ヘッダがない場合は `None`）
+///
+/// 各ヘッダは `Name: value` の形式で、CRLF で区切る。認可トークンのヘッダを先頭に置き、
+/// 追加のヘッダは名前順に続ける。ヘッダ名・値は HTTP のヘッダとして正しいかを検証し、
+/// 改行などの制御文字を含む値はエラーとする。
+pub fn ffmpeg_headers(
+    auth_token: Option<&str>,
+    extra_headers: &HashMap<String, String>,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let mut lines: Vec<String> = Vec::new();
+    if let Some(auth_token) = auth_token {
+        lines.push(format!("X-RADIKO-AUTHTOKEN: {}", auth_token));
+    }
+    let mut extra: Vec<(&String, &String)> = extra_headers.iter().collect();
+    extra.sort();
+    for (name, value) in extra {
+        HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid stream header name: {}", name))?;
+        HeaderValue::from_str(value).map_err(|_| format!("Invalid stream header value for {}: {:?}", name, value))?;
+        if name.eq_ignore_ascii_case("X-Radiko-AuthToken") {
+            warn!("Ignoring stream header {}; the auth token is set automatically", name);
+            continue;
+        }
+        lines.push(format!("{}: {}", name, value.trim()));
+    }
+    if lines.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(lines.iter().map(|line| format!("{}\r\n", line)).collect()))
+}
+
+/// 標準出力へ書き出す場合の ffmpeg の出力先
+const STDOUT_OUTPUT: &str = "pipe:1";
+
+/// 指定したパスが名前付きパイプ（FIFO）かどうかを返す
+///
+/// FIFO は Unix でのみ扱えるため、それ以外の環境では常に `false` を返す。
+pub fn is_fifo(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        fs::metadata(path).is_ok_and(|m| m.file_type().is_fifo())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// 録音の中止を要求するためのフラグ（`true` にすると録音を中止する）
+pub type CancelFlag = Arc<AtomicBool>;
+
+/// ffmpeg が失敗してから録音をやり直すまでの待ち時間（秒）
+const FFMPEG_RETRY_DELAY_SECS: u64 = 10;
+
+/// 中止を要求してから ffmpeg の終了を待つ時間（経過後は強制終了する）
+const CANCEL_GRACE_SECS: u64 = 10;
+
+/// 録音のオプション
+#[derive(Debug, Clone, Default)]
+pub struct RecordOptions {
+    /// 指定した場合、録音を指定秒数ごとのファイルに分割する
+    ///
+    /// 分割したファイルは `<出力ファイル名>_000.<拡張子>` のように連番で出力される。
+    pub segment_secs: Option<i64>,
+    /// 指定した場合、録音を指定した位置（録音開始からの秒数、昇順）で分割する
+    ///
+    /// 出力ファイル名は `segment_secs` と同じく連番になる。`segment_secs` とは併用できない。
+    pub split_at_secs: Vec<i64>,
+    /// ffmpeg の HLS 取得の代わりに、セグメントを直接ダウンロードしてから ffmpeg で変換する（実験的機能）
+    pub segment_downloader: bool,
+    /// セグメントを直接ダウンロードする場合のダウンロード速度の上限（KB/s、`None` の場合は制限しない）
+    pub rate_limit_kbps: Option<u64>,
+    /// 指定した場合、音声をコピーせずに再エンコードする
+    ///
+    /// タイムスタンプが不連続なストリームでも、シーク可能なファイルを作成できる。
+    pub reencode: Option<ReencodeOptions>,
+    /// 出力ファイルに埋め込むメタデータ（`title`, `artist` などのキーと値）
+    pub metadata: Vec<(String, String)>,
+    /// 指定した場合、フラグが立った時点で ffmpeg に `q` を送り、録音ファイルを正常に閉じて中止する
+    pub cancel: Option<CancelFlag>,
+    /// 録音ファイルの保存先（`None` の場合はローカルのファイルシステム）
+    pub sink: Option<Arc<dyn OutputSink>>,
+    /// 録音に失敗した場合、書きかけの録音を保存先に残さずに削除する
+    pub discard_partial: bool,
+}
+
+impl RecordOptions {
+    /// 録音を複数のファイルに分割するかどうか
+    pub fn is_split(&self) -> bool {
+        self.segment_secs.is_some() || !self.split_at_secs.is_empty()
+    }
+
+    /// 分割後の各ファイルの終了位置（録音開始からの秒数）を返す（分割しない場合は録音時間のみ）
+    pub fn split_points(&self, duration_secs: i64) -> Vec<i64> {
+        let mut points: Vec<i64> = match self.segment_secs {
+            Some(segment_secs) => (1..).map(|i| i * segment_secs).take_while(|&p| p < duration_secs).collect(),
+            None => self.split_at_secs.clone(),
+        };
+        points.push(duration_secs);
+        points
+    }
+
+    /// 分割の指定が、運用上の上限（`--max-duration`）を適用した後の録音時間に対して正しいかを確認する
+    ///
+    /// 上限はプリロールを含めた録音時間に適用するため、切り詰めた場合は録音開始からの録音時間も短くなる。
+    /// 切り詰めた後の録音時間を超える分割位置は、録音を始める前にエラーにする（ログは出さない）。
+    ///
+    /// # 引数
+    /// - `duration_secs`: 要求した録音時間（秒、プリロールを含まない）
+    /// - `pre_roll_secs`: 録音開始時刻より前から録音する秒数
+    pub fn validate_split_within_cap(&self, duration_secs: i64, pre_roll_secs: i64) -> Result<(), Box<dyn Error>> {
+        let recorded_secs: i64 = duration::capped_duration(duration_secs + pre_roll_secs)? - pre_roll_secs;
+        if recorded_secs <= 0 {
+            return Err(format!(
+                "--max-duration {} leaves nothing to record after the {}s pre-roll",
+                duration::format_duration(recorded_secs + pre_roll_secs),
+                pre_roll_secs
+            )
+            .into());
+        }
+        if recorded_secs < duration_secs {
+            if let Some(&offset) = self.split_at_secs.iter().find(|&&offset| offset >= recorded_secs) {
+                return Err(format!(
+                    "Split offset {} is past the end of the recording, which --max-duration clamps to {} (including {}s pre-roll)",
+                    duration::format_duration(offset),
+                    duration::format_duration(recorded_secs + pre_roll_secs),
+                    pre_roll_secs
+                )
+                .into());
+            }
+        }
+        self.validate_split(recorded_secs)
+    }
+
+    /// 分割の指定が録音時間に対して正しいかを確認する
+    ///
+    /// 分割位置は 0 より大きく録音時間より小さい値を昇順（重複なし）で指定する必要がある。
+    pub fn validate_split(&self, duration_secs: i64) -> Result<(), Box<dyn Error>> {
+        if self.segment_secs.is_some() && !self.split_at_secs.is_empty() {
+            return Err("Split offsets cannot be combined with fixed-length segments".into());
+        }
+        let mut previous: i64 = 0;
+        for &offset in &self.split_at_secs {
+            if offset <= previous {
+                return Err(format!("Split offsets must be positive and increasing: {}s", offset).into());
+            }
+            if offset >= duration_secs {
+                return Err(format!(
+                    "Split offset {}s is not within the recording duration ({}s)",
+                    offset, duration_secs
+                )
+                .into());
+            }
+            previous = offset;
+        }
+        Ok(())
+    }
+
+    /// 実際に出力するファイルパスを返す
+    ///
+    /// 再エンコードする場合は、拡張子をエンコード形式に合わせる。
+    pub fn output_path(&self, path: &str) -> String {
+        match &self.reencode {
+            Some(reencode) => Path::new(path)
+                .with_extension(reencode.codec.extension())
+                .to_string_lossy()
+                .to_string(),
+            None => path.to_string(),
+        }
+    }
+}
+
+/// 録音時の再エンコードのオプション
+#[derive(Debug, Clone)]
+pub struct ReencodeOptions {
+    /// 出力のエンコード形式
+    pub codec: NormalizeCodec,
+    /// 出力のビットレート（例: `128k`）
+    pub bitrate: String,
+    /// 出力のチャンネル数（`None` の場合は元のストリームと同じ）
+    pub channels: Option<u32>,
+}
+
+/// 録音結果
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordOutcome {
+    /// 出力先ファイルパス
+    pub output_path: String,
+    /// 要求した録音時間（秒）
+    pub requested_duration: i64,
+    /// ffprobe で計測した出力ファイルの再生時間（秒、計測できなかった場合は `None`）
+    pub measured_duration: Option<f64>,
+    /// 出力ファイルのサイズ（バイト）
+    pub file_size_bytes: u64,
+    /// 録音に使用したストリームURL
+    pub endpoint_used: String,
+    /// 録音のリトライ回数
+    pub retries: u32,
+    /// 分割して録音した場合の各ファイルのパス
+    pub segments: Vec<String>,
+}
+
+impl RecordOutcome {
+    /// 出力ファイルのサイズと再生時間を計測して録音結果を作成する
+    ///
+    /// 分割して録音した場合は、各ファイルの合計値を計測値とする。
+    fn measure(
+        output_path: &str,
+        options: &RecordOptions,
+        requested_duration: i64,
+        endpoint_used: String,
+        retries: u32,
+    ) -> Self {
+        let segments: Vec<String> = if options.is_split() {
+            segment_files(output_path)
+        } else {
+            Vec::new()
+        };
+        let files: Vec<String> = if segments.is_empty() {
+            vec![output_path.to_string()]
+        } else {
+            segments.clone()
+        };
+
+        let file_size_bytes: u64 = files
+            .iter()
+            .map(|f| fs::metadata(f).map(|m| m.len()).unwrap_or(0))
+            .sum();
+        let measured_duration: Option<f64> = files
+            .iter()
+            .map(|f| match ffprobe::probe(f) {
+                Ok(info) => info.duration_secs,
+                Err(e) => {
+                    warn!("Failed to measure duration of {}: {}", f, e);
+                    None
+                }
+            })
+            .sum();
+        Self {
+            output_path: output_path.to_string(),
+            requested_duration,
+            measured_duration,
+            file_size_bytes,
+            endpoint_used,
+            retries,
+            segments,
+        }
+    }
 }
 
 /// Radikoプレイヤー
 pub struct RadikoPlayer {
     area_id: String,
     headers: HashMap<String, String>,
+    /// 放送局IDごとのストリームURLテンプレート
+    url_templates: HashMap<String, String>,
+    /// ffmpeg がストリームを取得する際に追加するヘッダ
+    stream_headers: HashMap<String, String>,
+    /// 録音前の聴取可否の確認を省略する
+    skip_eligibility_check: bool,
+    /// 番組の配信を待ち続ける時間（秒、0 の場合は待たない）
+    retry_window_secs: u64,
+    /// 配信を待つ間にプレイリストを確認する間隔（秒）
+    retry_interval_secs: u64,
+    /// 録音開始時刻より前から録音する秒数
+    pre_roll_secs: i64,
+    /// 録音ごとの ffmpeg のエラー出力のログファイルを残す条件
+    ffmpeg_log: FfmpegLogMode,
+    /// ffmpeg が失敗した場合に録音をやり直すかの方針
+    ffmpeg_retry: FfmpegRetryPolicy,
+    /// ffmpeg のスレッド数・HLS 取得のオプション
+    ffmpeg_tuning: FfmpegTuning,
+    /// 匿名の認可で聴取できなかった場合にプレミアム会員として認可し直すための設定（`--prefer-premium auto`）
+    premium_fallback: Option<AuthConfig>,
+    /// プレミアム会員として認可し直した後のヘッダ
+    premium_headers: OnceLock<HashMap<String, String>>,
 }
 
 impl RadikoPlayer {
     /// コンストラクタ  
     /// 
+    /// 認可に失敗した場合は `auth_handler::AuthError` を含むエラーを返す。
+    /// 
+    /// # 引数
+    /// - `area_id`: RadikoのエリアID
+    /// - `config`: 設定ファイルの内容
+    pub fn new(area_id: &str, config: &Config) -> Result<Self, Box<dyn Error>> {
+        let headers: HashMap<String, String> = Self::make_headers(area_id, config)?;
+        let auth: &AuthConfig = &config.auth;
+        let premium_fallback: Option<AuthConfig> = (auth.prefer_premium == PreferPremium::Auto
+            && auth.token.is_none()
+            && !auth.skip
+            && auth.premium_credentials().is_some())
+        .then(|| auth.clone());
+        Ok(Self {
+            area_id: area_id.to_string(),
+            headers,
+            url_templates: config.stream.url_templates.clone(),
+            stream_headers: config.stream.headers.clone(),
+            skip_eligibility_check: config.auth.skip_eligibility_check,
+            retry_window_secs: config.stream.retry_window_minutes * 60,
+            retry_interval_secs: config.stream.retry_interval_secs.max(1),
+            pre_roll_secs: config.stream.pre_roll_secs as i64,
+            ffmpeg_log: config.stream.ffmpeg_log,
+            ffmpeg_retry: FfmpegRetryPolicy::from_config(&config.stream),
+            ffmpeg_tuning: config.stream.ffmpeg.clone(),
+            premium_fallback,
+            premium_headers: OnceLock::new(),
+        })
+    }
+
+    /// 認可処理を行わないコンストラクタ  
+    /// 
+    /// 放送局リストの取得など、認可が不要な処理のみに使う。
+    /// 認可が必要なストリームの録音には `new` または `from_existing_token` を使うこと。
+    /// 
+    /// # 引数
+    /// - `area_id`: RadikoのエリアID
+    /// - `config`: 設定ファイルの内容
+    pub fn unauthenticated(area_id: &str, config: &Config) -> Self {
+        let mut headers: HashMap<String, String> = HashMap::new();
+        headers.insert("Connection".to_string(), "keep-alive".to_string());
+        Self {
+            area_id: area_id.to_string(),
+            headers,
+            url_templates: config.stream.url_templates.clone(),
+            stream_headers: config.stream.headers.clone(),
+            skip_eligibility_check: config.auth.skip_eligibility_check,
+            retry_window_secs: config.stream.retry_window_minutes * 60,
+            retry_interval_secs: config.stream.retry_interval_secs.max(1),
+            pre_roll_secs: config.stream.pre_roll_secs as i64,
+            ffmpeg_log: config.stream.ffmpeg_log,
+            ffmpeg_retry: FfmpegRetryPolicy::from_config(&config.stream),
+            ffmpeg_tuning: config.stream.ffmpeg.clone(),
+            premium_fallback: None,
+            premium_headers: OnceLock::new(),
+        }
+    }
+
+    /// 取得済みの認可トークンを使うコンストラクタ  
+    /// 
+    /// 認可処理（`RadikoAuthHandler::new`）を行わないため、一度取得したトークンを
+    /// 複数の `RadikoPlayer` で使い回すことができる。
+    /// 
     /// # 引数
     /// - `area_id`: RadikoのエリアID
-    pub fn new(area_id: &str) -> Self {
-        let headers: HashMap<String, String> = Self::make_headers(area_id);
+    /// - `auth_token`: 認可済みのトークン
+    /// - `config`: 設定ファイルの内容
+    pub fn from_existing_token(area_id: &str, auth_token: &str, config: &Config) -> Self {
+        let mut headers: HashMap<String, String> = HashMap::new();
+        headers.insert("X-Radiko-AuthToken".to_string(), auth_token.to_string());
+        headers.insert("Connection".to_string(), "keep-alive".to_string());
         Self {
             area_id: area_id.to_string(),
             headers,
+            url_templates: config.stream.url_templates.clone(),
+            stream_headers: config.stream.headers.clone(),
+            skip_eligibility_check: config.auth.skip_eligibility_check,
+            retry_window_secs: config.stream.retry_window_minutes * 60,
+            retry_interval_secs: config.stream.retry_interval_secs.max(1),
+            pre_roll_secs: config.stream.pre_roll_secs as i64,
+            ffmpeg_log: config.stream.ffmpeg_log,
+            ffmpeg_retry: FfmpegRetryPolicy::from_config(&config.stream),
+            ffmpeg_tuning: config.stream.ffmpeg.clone(),
+            premium_fallback: None,
+            premium_headers: OnceLock::new(),
         }
     }
 
+    /// 認可トークンを返す（認可処理を省略した場合は `None`）
+    ///
+    /// プレミアム会員として認可し直した場合は、そのトークンを返す。
+    pub fn auth_token(&self) -> Option<&str> {
+        self.premium_headers
+            .get()
+            .unwrap_or(&self.headers)
+            .get("X-Radiko-AuthToken")
+            .map(String::as_str)
+    }
+
     /// 指定した放送局のストリームを録音してファイルに保存する  
     /// 
+    /// 録音は作業用の一時ファイルに書き込み、完了後に保存先（`options.sink`）に引き渡す。
+    /// 録音に失敗した場合も、途中までの録音ファイルは保存先に引き渡す。
+    /// 
     /// # 引数
     /// - `station_id`: 放送局ID
-    /// - `start_time`: 録音開始日時（Localタイムゾーン）
-    /// - `duration_minutes`: 録音時間（分）
-    /// - `output_path`: 出力先ファイルパス
+    /// - `start_time`: 録音開始日時（日本標準時）
+    /// - `duration_secs`: 録音時間（秒）
+    /// - `output_path`: 出力先ファイルパス（再エンコードする場合は拡張子をエンコード形式に合わせる）
+    /// - `options`: 録音のオプション
+    /// - `events`: 進行状況を通知する送信側チャネル（不要な場合は `None`）
+    /// 
+    /// # 戻り値
+    /// 録音結果（出力ファイルの実測値を含む）
     pub fn record(
         &self,
         station_id: &str,
-        start_time: DateTime<Local>,
-        duration_minutes: i64,
+        start_time: DateTime<FixedOffset>,
+        duration_secs: i64,
+        output_path: &str,
+        options: &RecordOptions,
+        events: Option<&Sender<RecordEvent>>,
+    ) -> Result<RecordOutcome, Box<dyn Error>> {
+        self.record_window(station_id, start_time, duration_secs, self.pre_roll_secs, output_path, options, events)
+    }
+
+    /// `pre_roll_secs` 秒早めて録音し、ファイルに保存する（`record` の本体）
+    ///
+    /// 既存ファイルへの追記では、プリロールした部分が既存の録音と重ならないよう 0 を指定する。
+    #[allow(clippy::too_many_arguments)]
+    fn record_window(
+        &self,
+        station_id: &str,
+        start_time: DateTime<FixedOffset>,
+        duration_secs: i64,
+        pre_roll_secs: i64,
         output_path: &str,
+        options: &RecordOptions,
+        events: Option<&Sender<RecordEvent>>,
+    ) -> Result<RecordOutcome, Box<dyn Error>> {
+        duration::validate_duration(duration_secs)?;
+        options.validate_split_within_cap(duration_secs, pre_roll_secs)?;
+        let (start_time, duration_secs): (DateTime<FixedOffset>, i64) =
+            self.recording_window(start_time, duration_secs, pre_roll_secs)?;
+        let output_path: &str = &options.output_path(output_path);
+        let temp_path: String = postprocess::temp_path(output_path, "recording");
+        let is_stream: bool = output_path == STDOUT_OUTPUT || is_fifo(Path::new(output_path));
+        if !is_stream {
+            postprocess::ensure_min_free_space(output_path, &temp_path)?;
+        }
+        // ffmpeg のエラー出力は、一時ファイルではなく保存先の録音ファイルと並べて残す
+        let ffmpeg_log: Option<PathBuf> = (self.ffmpeg_log != FfmpegLogMode::Off && !is_stream)
+            .then(|| Path::new(output_path).with_extension("ffmpeg.log"));
+        // 受信側が破棄されていても録音は継続する
+        let notify = |event: RecordEvent| {
+            if let Some(tx) = events {
+                let _ = tx.send(event);
+            }
+        };
+
+        notify(RecordEvent::Started {
+            station_id: station_id.to_string(),
+            output_path: output_path.to_string(),
+        });
+        let result: Result<RecordOutcome, Box<dyn Error>> = self
+            .run_ffmpeg(
+                station_id,
+                start_time,
+                duration_secs,
+                pre_roll_secs,
+                &temp_path,
+                options,
+                events,
+                ffmpeg_log.as_deref(),
+            )
+            .and_then(|(endpoint_used, retries)| {
+                // 一時ファイルのうちに計測してから保存先に引き渡す
+                let mut outcome: RecordOutcome =
+                    RecordOutcome::measure(&temp_path, options, duration_secs, endpoint_used, retries);
+                let (final_path, segments): (String, Vec<String>) =
+                    Self::finalize_output(&temp_path, output_path, options)?;
+                outcome.output_path = final_path;
+                outcome.segments = segments;
+                Ok(outcome)
+            });
+        match result {
+            Ok(outcome) => {
+                notify(RecordEvent::Finished {
+                    output_path: outcome.output_path.clone(),
+                });
+                Ok(outcome)
+            }
+            Err(e) => {
+                if options.discard_partial {
+                    Self::discard_temp_output(&temp_path, options);
+                } else if let Err(finalize_error) = Self::finalize_output(&temp_path, output_path, options) {
+                    warn!("Failed to save the partial recording {}: {}", temp_path, finalize_error);
+                }
+                notify(RecordEvent::Failed {
+                    error: e.to_string(),
+                });
+                Err(e)
+            }
+        }
+    }
+
+    /// 実際に録音する時間帯（プリロールを含めた開始日時と録音時間）を返す
+    ///
+    /// 番組の冒頭が欠けないよう、終了時刻は変えずに開始時刻を `pre_roll_secs` 秒早め、
+    /// プリロールを含めた録音時間に運用上の上限（`--max-duration`）を適用する。
+    /// 上限を適用するのはここのみで、すべての録音（標準出力・FIFO への書き出しを含む）がここを通る。
+    fn recording_window(
+        &self,
+        start_time: DateTime<FixedOffset>,
+        duration_secs: i64,
+        pre_roll_secs: i64,
+    ) -> Result<(DateTime<FixedOffset>, i64), Box<dyn Error>> {
+        let (start_time, duration_secs): (DateTime<FixedOffset>, i64) = if pre_roll_secs > 0 {
+            info!("Starting {} seconds early (pre-roll)", pre_roll_secs);
+            (start_time - Duration::seconds(pre_roll_secs), duration_secs + pre_roll_secs)
+        } else {
+            (start_time, duration_secs)
+        };
+        let duration_secs: i64 = duration::apply_max_duration(duration_secs)?;
+        duration::validate_duration(duration_secs)?;
+        Ok((start_time, duration_secs))
+    }
+
+    /// 録音済みの一時ファイル（分割録音の場合は各ファイル）を保存先に引き渡す
+    ///
+    /// 保存先の録音ファイルのパスと、分割録音の場合は各ファイルの保存先のパスを返す。
+    /// 一時ファイルが存在しない場合は何もしない。
+    fn finalize_output(
+        temp_path: &str,
+        output_path: &str,
+        options: &RecordOptions,
+    ) -> Result<(String, Vec<String>), Box<dyn Error>> {
+        let local: Arc<dyn OutputSink> = Arc::new(LocalSink);
+        let sink: &Arc<dyn OutputSink> = options.sink.as_ref().unwrap_or(&local);
+        if !options.is_split() {
+            if !Path::new(temp_path).exists() {
+                return Ok((output_path.to_string(), Vec::new()));
+            }
+            let final_path: String = sink.finalize(Path::new(temp_path), output_path, &options.metadata)?;
+            return Ok((final_path, Vec::new()));
+        }
+
+        let pattern: String = segment_pattern(output_path);
+        let mut segments: Vec<String> = Vec::new();
+        for (i, segment) in segment_files(temp_path).iter().enumerate() {
+            let destination: String = pattern.replace("%03d", &format!("{:03}", i));
+            segments.push(sink.finalize(Path::new(segment), &destination, &options.metadata)?);
+        }
+        Ok((output_path.to_string(), segments))
+    }
+
+    /// 録音に失敗した一時ファイル（分割録音の場合は各ファイル）を削除する
+    fn discard_temp_output(temp_path: &str, options: &RecordOptions) {
+        let files: Vec<String> = if options.is_split() {
+            segment_files(temp_path)
+        } else {
+            vec![temp_path.to_string()]
+        };
+        for file in files.iter().filter(|file| Path::new(file).exists()) {
+            match fs::remove_file(file) {
+                Ok(()) => info!("Discarded the partial recording {}", file),
+                Err(e) => warn!("Failed to remove the partial recording {}: {}", file, e),
+            }
+        }
+    }
+
+    /// 指定した放送局のストリームを録音し、ADTS 形式で標準出力に書き出す  
+    /// 
+    /// ffmpeg の標準出力をそのまま引き継ぐため、ログは標準エラー出力に出すこと。
+    /// 
+    /// # 引数
+    /// - `station_id`: 放送局ID
+    /// - `start_time`: 録音開始日時（日本標準時）
+    /// - `duration_secs`: 録音時間（秒）
+    pub fn record_to_stdout(
+        &self,
+        station_id: &str,
+        start_time: DateTime<FixedOffset>,
+        duration_secs: i64,
     ) -> Result<(), Box<dyn Error>> {
+        duration::validate_duration(duration_secs)?;
+        let (start_time, duration_secs): (DateTime<FixedOffset>, i64) =
+            self.recording_window(start_time, duration_secs, self.pre_roll_secs)?;
+        self.run_ffmpeg(
+            station_id,
+            start_time,
+            duration_secs,
+            self.pre_roll_secs,
+            STDOUT_OUTPUT,
+            &RecordOptions::default(),
+            None,
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// 指定した放送局のストリームを録音し、既存の名前付きパイプ（FIFO）に ADTS 形式で書き出す  
+    /// 
+    /// 読み出し側のプロセスがリアルタイムに処理できるよう、出力先をそのまま ffmpeg に渡す。
+    /// 一時ファイルや再生時間の計測など、通常のファイルを前提とする処理は行わない。
+    /// 
+    /// # 引数
+    /// - `station_id`: 放送局ID
+    /// - `start_time`: 録音開始日時（日本標準時）
+    /// - `duration_secs`: 録音時間（秒）
+    /// - `fifo_path`: 書き出し先の FIFO のパス
+    pub fn record_to_fifo(
+        &self,
+        station_id: &str,
+        start_time: DateTime<FixedOffset>,
+        duration_secs: i64,
+        fifo_path: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        duration::validate_duration(duration_secs)?;
+        if !cfg!(unix) {
+            return Err("FIFO output is only supported on Unix".into());
+        }
+        if !is_fifo(Path::new(fifo_path)) {
+            return Err(format!("{} is not an existing FIFO (create it with mkfifo)", fifo_path).into());
+        }
+        let (start_time, duration_secs): (DateTime<FixedOffset>, i64) =
+            self.recording_window(start_time, duration_secs, self.pre_roll_secs)?;
+        self.run_ffmpeg(
+            station_id,
+            start_time,
+            duration_secs,
+            self.pre_roll_secs,
+            fifo_path,
+            &RecordOptions::default(),
+            None,
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// 途中で中断された録音ファイルに、不足している末尾部分を追記する  
+    /// 
+    /// 既存ファイルの再生時間を ffprobe で取得し、元の録音範囲のうち残りの部分のみを
+    /// 録音して既存ファイルの末尾に連結する。
+    /// 
+    /// # 引数
+    /// - `station_id`: 放送局ID
+    /// - `start_time`: 元の録音開始日時（日本標準時）
+    /// - `duration_secs`: 元の録音時間（秒）
+    /// - `existing_path`: 中断された録音ファイルのパス
+    pub fn append(
+        &self,
+        station_id: &str,
+        start_time: DateTime<FixedOffset>,
+        duration_secs: i64,
+        existing_path: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        // 上限は残りの部分を録音する `record` で適用する
+        duration::validate_duration(duration_secs)?;
+        let existing: ProbeInfo = ffprobe::probe(existing_path)?;
+        let recorded_secs: i64 = existing
+            .duration_secs
+            .ok_or_else(|| format!("Failed to get duration of {}", existing_path))?
+            .floor() as i64;
+        if recorded_secs >= duration_secs {
+            info!("{} is already complete ({} seconds)", existing_path, recorded_secs);
+            return Ok(());
+        }
+
+        let remaining_start: DateTime<FixedOffset> = start_time + Duration::seconds(recorded_secs);
+        let remaining_secs: i64 = duration_secs - recorded_secs;
+        info!(
+            "Appending the remaining {} seconds from {} to {}",
+            remaining_secs,
+            Self::format_datetime(remaining_start),
+            existing_path
+        );
+
+        postprocess::ensure_temp_space(existing_path, duration_secs + remaining_secs)?;
+        let part_path: String = postprocess::temp_path(existing_path, "part");
+        let merged_path: String = postprocess::temp_path(existing_path, "merged");
+        // 残りの部分を早めて録音すると既存の録音と重なるため、プリロールは適用しない
+        let result: Result<(), Box<dyn Error>> = self
+            .record_window(
+                station_id,
+                remaining_start,
+                remaining_secs,
+                0,
+                &part_path,
+                &RecordOptions::default(),
+                None,
+            )
+            .and_then(|_| {
+                // 形式が異なるファイルは再エンコードなしでは連結できない
+                let part: ProbeInfo = ffprobe::probe(&part_path)?;
+                if part.audio_codec != existing.audio_codec {
+                    return Err(format!(
+                        "Format mismatch: existing file is {:?} but the remaining part is {:?}",
+                        existing.audio_codec, part.audio_codec
+                    )
+                    .into());
+                }
+                postprocess::concat_files(&[existing_path, &part_path], &merged_path)?;
+                postprocess::move_file(Path::new(&merged_path), Path::new(existing_path))?;
+                Ok(())
+            });
+
+        for path in [&part_path, &merged_path] {
+            if Path::new(path).exists() {
+                let _ = fs::remove_file(path);
+            }
+        }
+        result?;
+        info!("Successfully appended to {}", existing_path);
+        Ok(())
+    }
+
+    /// ffmpeg を実行してストリームを録音し、録音に使用したストリームURLと録音をやり直した回数を返す
+    ///
+    /// `start_time` と `duration_secs` はプリロールを含めた値で、`pre_roll_secs` はそのうちの早めた秒数。
+    /// ffmpeg が失敗した場合は、再試行の方針に従って録音をやり直す（標準出力・FIFO への出力ではやり直さない）。
+    /// `ffmpeg_log` が指定された場合は、やり直した分も含めた ffmpeg のエラー出力をそのファイルに保存する。
+    #[allow(clippy::too_many_arguments)]
+    fn run_ffmpeg(
+        &self,
+        station_id: &str,
+        start_time: DateTime<FixedOffset>,
+        duration_secs: i64,
+        pre_roll_secs: i64,
+        output_path: &str,
+        options: &RecordOptions,
+        events: Option<&Sender<RecordEvent>>,
+        ffmpeg_log: Option<&Path>,
+    ) -> Result<(String, u32), Box<dyn Error>> {
         // 開始時刻、終了時刻をフォーマット
+        // 終了時刻が日付をまたぐ場合も DateTime の加算で翌日に繰り上がる
         let ft: String = Self::format_datetime(start_time);
-        let end_time: DateTime<Local> = start_time + Duration::minutes(duration_minutes);
+        let end_time: DateTime<FixedOffset> = start_time + Duration::seconds(duration_secs);
         let to: String = Self::format_datetime(end_time);
 
         // ストリームURLの作成
-        let stream_url: String = format!(
-            "https://radiko.jp/v2/api/ts/playlist.m3u8?station_id={}&l=15&ft={}&to={}",
-            station_id, ft, to
-        );
-
-        // ffmpeg用のヘッダー（ここではX-Radiko-AuthTokenを指定）
-        let auth_token: &String = self
-            .headers
-            .get("X-Radiko-AuthToken")
-            .ok_or("Missing X-Radiko-AuthToken")?;
-        let header_arg: String = format!("X-RADIKO-AUTHTOKEN: {}", auth_token);
+        let stream_url: String = self.stream_url(station_id, &ft, &to);
 
         info!("Recording {}...", output_path);
 
+        self.wait_for_playlist(station_id, &stream_url, self.auth_token())?;
+        // ffmpeg の起動前に、番組が存在し、トークンでストリームを取得できるかを確認する
+        if !self.skip_eligibility_check {
+            self.check_eligibility(station_id, start_time, &stream_url, self.auth_token())?;
+        }
+        // 確認の途中でプレミアム会員として認可し直した場合に備えて、確認後のトークンを使う
+        let auth_token: Option<&str> = self.auth_token();
+        if auth_token.is_none() {
+            // 認可を省略した場合はトークンなしでリクエストする
+            warn!("Recording without X-Radiko-AuthToken");
+        }
+
+        // セグメントを直接ダウンロードする場合は、連結したファイルを ffmpeg の入力とする
+        let downloaded_path: Option<String> = if options.segment_downloader {
+            postprocess::ensure_temp_space(output_path, duration_secs)?;
+            let path: String = postprocess::temp_path(output_path, "download");
+            segment_downloader::download_segments(&stream_url, auth_token, &path, options.rate_limit_kbps)
+                .inspect_err(|_| {
+                    let _ = fs::remove_file(&path);
+                })?;
+            Some(path)
+        } else {
+            None
+        };
+
         // ffmpegコマンドを実行して録音
-        let status: ExitStatus = Command::new("ffmpeg")
-            .args(&[
-                "-headers",
-                &header_arg,
-                "-i",
-                &stream_url,
-                "-acodec",
-                "copy",
-                "-y",
-                output_path,
-            ])
-            .status()?;
-
-        if !status.success() {
-            return Err(format!("ffmpeg exited with status: {:?}", status).into());
+        let mut command: Command = Command::new("ffmpeg");
+        match &downloaded_path {
+            Some(path) => {
+                command.args(["-i", path]);
+            }
+            None => {
+                // ffmpeg用のヘッダー（X-Radiko-AuthToken と設定で追加したヘッダ）
+                if let Some(headers) = ffmpeg_headers(auth_token, &self.stream_headers)? {
+                    command.args(["-headers", &headers]);
+                }
+                // HLS 取得のオプションは入力オプションのため -i の前に置く
+                let tuning: &FfmpegTuning = &self.ffmpeg_tuning;
+                if let Some(max_reload) = tuning.max_reload {
+                    command.args(["-max_reload", &max_reload.to_string()]);
+                }
+                if let Some(http_multiple) = tuning.http_multiple {
+                    command.args(["-http_multiple", if http_multiple { "1" } else { "0" }]);
+                }
+                if let Some(queue_size) = tuning.thread_queue_size {
+                    command.args(["-thread_queue_size", &queue_size.to_string()]);
+                }
+                command.args(["-i", &stream_url]);
+            }
+        }
+        if let Some(threads) = self.ffmpeg_tuning.threads {
+            command.args(["-threads", &threads.to_string()]);
+        }
+        match &options.reencode {
+            // 再エンコードで元のタイムスタンプを引き継がず、連続したタイムスタンプを振り直す
+            Some(reencode) => {
+                info!("Transcoding to {} {} while recording (single pass)", reencode.codec.encoder(), reencode.bitrate);
+                command.args(["-c:a", reencode.codec.encoder(), "-b:a", &reencode.bitrate]);
+                if let Some(channels) = reencode.channels {
+                    command.args(["-ac", &channels.to_string()]);
+                }
+                command.arg("-y");
+            }
+            None => {
+                command.args(["-acodec", "copy", "-y"]);
+            }
+        }
+        // 録音済みの判定に使うため、プリロールを除いた放送局と時間帯をタグに残す（分割録音では残さない）
+        let window_tags: Vec<(String, String)> = if options.is_split() {
+            Vec::new()
+        } else {
+            library::window_tags(
+                station_id,
+                (start_time + Duration::seconds(pre_roll_secs)).naive_local(),
+                end_time.naive_local(),
+            )
+        };
+        for (key, value) in options.metadata.iter().chain(&window_tags) {
+            command.args(["-metadata", &format!("{}={}", key, value)]);
+        }
+        // ADTS 形式にはタグの領域がないため、ID3v2 タグとして書き込む
+        if !options.is_split() && output_path.ends_with(".aac") {
+            command.args(["-write_id3v2", "1"]);
+        }
+        match options.segment_secs {
+            // segment muxer で指定秒数ごとに連番のファイルへ分割する
+            Some(segment_secs) => {
+                command.args([
+                    "-f",
+                    "segment",
+                    "-segment_time",
+                    &segment_secs.to_string(),
+                    "-reset_timestamps",
+                    "1",
+                    &segment_pattern(output_path),
+                ]);
+            }
+            // segment muxer で指定した位置ごとに連番のファイルへ分割する
+            None if !options.split_at_secs.is_empty() => {
+                // 分割位置は要求した開始時刻からの秒数のため、pre-roll の分ずらす
+                let times: Vec<String> =
+                    options.split_at_secs.iter().map(|s| (s + pre_roll_secs).to_string()).collect();
+                command.args([
+                    "-f",
+                    "segment",
+                    "-segment_times",
+                    &times.join(","),
+                    "-reset_timestamps",
+                    "1",
+                    &segment_pattern(output_path),
+                ]);
+            }
+            // パイプではコンテナを推測できないため、形式を明示する
+            None if output_path == STDOUT_OUTPUT || is_fifo(Path::new(output_path)) => {
+                command.args(["-f", "adts", output_path]);
+            }
+            None => {
+                command.arg(output_path);
+            }
+        }
+        if events.is_some() {
+            command.args(["-progress", "pipe:1", "-nostats"]).stdout(Stdio::piped());
+        }
+        // 出力済みのデータを取り消せないため、パイプへの出力はやり直さない
+        let mut retry_policy: FfmpegRetryPolicy = self.ffmpeg_retry.clone();
+        if output_path == STDOUT_OUTPUT || is_fifo(Path::new(output_path)) {
+            retry_policy.max_retries = 0;
         }
 
+        // ログファイルを作れなくても録音は続ける
+        let log_file: Option<File> = ffmpeg_log.and_then(|path| {
+            File::create(path)
+                .inspect_err(|e| warn!("Failed to create ffmpeg log {}: {}", path.display(), e))
+                .ok()
+        });
+
+        let mut retries: u32 = 0;
+        let result: Result<(), Box<dyn Error>> = loop {
+            let log: Option<File> = log_file.as_ref().and_then(|f| f.try_clone().ok());
+            let (status, stderr): (ExitStatus, String) = match (events, &options.cancel) {
+                (Some(tx), _) => Self::run_with_progress(&mut command, duration_secs, tx, log),
+                (None, Some(cancel)) => Self::run_cancellable(&mut command, cancel, log),
+                (None, None) => Self::run_plain(&mut command, log),
+            }?;
+            if status.success() {
+                break Ok(());
+            }
+            // 空き容量がないまま再試行しても同じく失敗するため、原因が分かるエラーにしてやり直さない
+            if postprocess::is_disk_full(&stderr) {
+                break Err(format!(
+                    "Disk full while writing {}: ffmpeg could not write the recording (free up space or set --temp-dir / output_dir to another disk)",
+                    output_path
+                )
+                .into());
+            }
+            let error: String = format!("ffmpeg exited with status: {:?}", status);
+            if !retry_policy.should_retry(retries, status.code(), &stderr) {
+                break Err(error.into());
+            }
+            retries += 1;
+            http_client::acquire_retry()?;
+            warn!(
+                "{}; retrying the recording ({}/{}) in {} seconds",
+                error, retries, retry_policy.max_retries, FFMPEG_RETRY_DELAY_SECS
+            );
+            thread::sleep(std::time::Duration::from_secs(FFMPEG_RETRY_DELAY_SECS));
+        };
+        if let Some(path) = &downloaded_path {
+            let _ = fs::remove_file(path);
+        }
+        if let (Some(path), Some(file)) = (ffmpeg_log, log_file) {
+            drop(file);
+            match (&result, self.ffmpeg_log) {
+                (Ok(()), FfmpegLogMode::OnFailure) => {
+                    let _ = fs::remove_file(path);
+                }
+                (Ok(()), _) => debug!("ffmpeg output saved to {}", path.display()),
+                (Err(_), _) => warn!("ffmpeg output saved to {}", path.display()),
+            }
+        }
+        result?;
+
         info!("Successfully recorded {}", output_path);
+        Ok((stream_url, retries))
+    }
+
+    /// ffmpeg を実行して終了を待ち、終了ステータスとエラー出力の末尾を返す
+    fn run_plain(command: &mut Command, log: Option<File>) -> Result<(ExitStatus, String), Box<dyn Error>> {
+        let mut child: Child = command.stderr(Stdio::piped()).spawn()?;
+        let stderr: Option<thread::JoinHandle<String>> = ffmpeg_retry::capture_stderr(&mut child, log);
+        let status: ExitStatus = child.wait()?;
+        Ok((status, Self::join_stderr(stderr)))
+    }
+
+    /// エラー出力を読み取るスレッドの終了を待ち、エラー出力の末尾を返す
+    fn join_stderr(handle: Option<thread::JoinHandle<String>>) -> String {
+        handle.and_then(|h| h.join().ok()).unwrap_or_default()
+    }
+
+    /// 録音せずに、指定した時間帯のストリームのコーデックなどを ffprobe で調べる
+    /// 
+    /// # 引数
+    /// - `station_id`: 放送局ID
+    /// - `start_time`: 開始日時（日本標準時）
+    /// - `duration_secs`: 調べる時間帯の長さ（秒）
+    pub fn probe(
+        &self,
+        station_id: &str,
+        start_time: DateTime<FixedOffset>,
+        duration_secs: i64,
+    ) -> Result<StreamInfo, Box<dyn Error>> {
+        let end_time: DateTime<FixedOffset> = start_time + Duration::seconds(duration_secs);
+        let stream_url: String = self.stream_url(
+            station_id,
+            &Self::format_datetime(start_time),
+            &Self::format_datetime(end_time),
+        );
+        info!("Probing stream URL: {}", stream_url);
+        ffprobe::probe_stream(&stream_url, self.auth_token())
+    }
+
+    /// 録音に使うストリーム（プレイリスト）のURLを返す
+    pub fn playlist_url(&self, station_id: &str, start_time: DateTime<FixedOffset>, duration_secs: i64) -> String {
+        let ft: String = Self::format_datetime(start_time);
+        let to: String = Self::format_datetime(start_time + Duration::seconds(duration_secs));
+        self.stream_url(station_id, &ft, &to)
+    }
+
+    /// 放送局のストリームURLを作成する
+    ///
+    /// 設定ファイルで放送局ごとのテンプレートが指定されていればそれを使い、
+    /// なければデフォルトのテンプレートを使う。
+    fn stream_url(&self, station_id: &str, ft: &str, to: &str) -> String {
+        let template: &str = match self.url_templates.get(station_id) {
+            Some(template) => {
+                info!("Using stream URL template for {}: {}", station_id, template);
+                template
+            }
+            None => {
+                debug!("Using default stream URL template for {}", station_id);
+                DEFAULT_STREAM_URL_TEMPLATE
+            }
+        };
+        template
+            .replace("{station_id}", station_id)
+            .replace("{ft}", ft)
+            .replace("{to}", to)
+    }
+
+    /// 番組がタイムフリーで配信されるまで、プレイリストを一定間隔で確認しながら待つ
+    ///
+    /// 放送終了直後の番組はしばらくタイムフリーに現れず、プレイリストが 404 または空になる。
+    /// 待つ時間が設定されていない場合や、HTTP(S) 以外のストリームURLの場合は何もしない。
+    fn wait_for_playlist(&self, station_id: &str, stream_url: &str, auth_token: Option<&str>) -> Result<(), Box<dyn Error>> {
+        if self.retry_window_secs == 0 || !(stream_url.starts_with("http://") || stream_url.starts_with("https://")) {
+            return Ok(());
+        }
+        let started: std::time::Instant = std::time::Instant::now();
+        let window: std::time::Duration = std::time::Duration::from_secs(self.retry_window_secs);
+        let mut attempt: u32 = 0;
+        loop {
+            attempt += 1;
+            let mut request: reqwest::blocking::RequestBuilder = build_client()?.get(stream_url);
+            if let Some(token) = auth_token {
+                request = request.header("X-Radiko-AuthToken", token);
+            }
+            let resp: reqwest::blocking::Response = send_with_retry(request)?;
+            let status: reqwest::StatusCode = resp.status();
+            let available: bool = match status {
+                reqwest::StatusCode::NOT_FOUND => false,
+                // セグメントやバリアントの URI を 1 行も含まないプレイリストは空とみなす
+                s if s.is_success() => resp.text()?.lines().any(|l| !l.trim().is_empty() && !l.starts_with('#')),
+                // それ以外のエラーは待っても解消しないため、そのまま録音に進めて報告させる
+                _ => true,
+            };
+            if available {
+                if attempt > 1 {
+                    info!("{} became available after {} attempts", station_id, attempt);
+                }
+                return Ok(());
+            }
+            if started.elapsed() >= window {
+                return Err(format!(
+                    "{} is still not available after waiting {} minutes ({} attempts)",
+                    station_id,
+                    self.retry_window_secs / 60,
+                    attempt
+                )
+                .into());
+            }
+            info!(
+                "{} is not available yet (attempt {}, HTTP {}); retrying in {} seconds",
+                station_id, attempt, status, self.retry_interval_secs
+            );
+            std::thread::sleep(std::time::Duration::from_secs(self.retry_interval_secs));
+        }
+    }
+
+    /// 認可トークンでストリームのプレイリストを取得し、番組が存在し、エリア・会員種別の上で聴取可能かを確認する
+    ///
+    /// 404 の場合は指定した日時に番組がないものとして、ffmpeg を起動せずにエラーを返す。
+    /// 認可を省略した場合（`auth_token` が `None`）は番組の有無のみを確認する。
+    /// HTTP(S) 以外のストリームURLは確認しない。
+    fn check_eligibility(
+        &self,
+        station_id: &str,
+        start_time: DateTime<FixedOffset>,
+        stream_url: &str,
+        auth_token: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        if !stream_url.starts_with("http://") && !stream_url.starts_with("https://") {
+            return Ok(());
+        }
+        debug!("Checking eligibility of {} in area {}", station_id, self.area_id);
+        let mut request: reqwest::blocking::RequestBuilder = build_client()?.get(stream_url);
+        if let Some(token) = auth_token {
+            request = request.header("X-Radiko-AuthToken", token);
+        }
+        let resp: reqwest::blocking::Response = send_with_retry(request)?;
+        let status: reqwest::StatusCode = resp.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(format!(
+                "No program on {} at {} (HTTP 404); check the start time and duration",
+                station_id,
+                start_time.format("%Y-%m-%d %H:%M:%S")
+            )
+            .into());
+        }
+        if auth_token.is_some() && status != reqwest::StatusCode::OK {
+            if self.fall_back_to_premium(station_id)? {
+                return self.check_eligibility(station_id, start_time, stream_url, self.auth_token());
+            }
+            // エリア外の放送局を指定した場合は、聴取できるエリアを示す
+            match station_area_mismatch(&self.area_id, station_id) {
+                Ok(Some(message)) => return Err(message.into()),
+                Ok(None) => {}
+                Err(e) => debug!("Failed to check the area of {}: {}", station_id, e),
+            }
+            return Err(format!(
+                "Station {} is not eligible for area {} with the current token (HTTP {}); check --area-id or premium membership, or use --skip-eligibility-check",
+                station_id, self.area_id, status
+            )
+            .into());
+        }
         Ok(())
     }
 
+    /// 匿名の認可で聴取できなかった場合に、プレミアム会員として認可し直す（`--prefer-premium auto`）
+    ///
+    /// 認可し直した場合は `true` を返す。プレミアム会員の設定がない場合や、すでに認可し直した場合は `false` を返す。
+    fn fall_back_to_premium(&self, station_id: &str) -> Result<bool, Box<dyn Error>> {
+        let Some(auth) = &self.premium_fallback else {
+            return Ok(false);
+        };
+        if self.premium_headers.get().is_some() {
+            return Ok(false);
+        }
+        info!(
+            "{} is not available with anonymous auth in area {}; falling back to Radiko premium",
+            station_id, self.area_id
+        );
+        let headers: HashMap<String, String> = Self::premium_headers(&self.area_id, auth)?;
+        let _ = self.premium_headers.set(headers);
+        Ok(true)
+    }
+
+    /// ffmpeg を実行し、中止が要求された場合は標準入力に `q` を送って録音ファイルを正常に閉じさせる
+    ///
+    /// `q` を送ってから一定時間内に終了しない場合は強制終了する。中止した場合はエラーを返す。
+    fn run_cancellable(
+        command: &mut Command,
+        cancel: &CancelFlag,
+        log: Option<File>,
+    ) -> Result<(ExitStatus, String), Box<dyn Error>> {
+        let mut child: Child = command.stdin(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        let stderr: Option<thread::JoinHandle<String>> = ffmpeg_retry::capture_stderr(&mut child, log);
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok((status, Self::join_stderr(stderr)));
+            }
+            if cancel.load(Ordering::SeqCst) {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(200));
+        }
+
+        info!("Cancelling ffmpeg (pid {})", child.id());
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(b"q");
+        }
+        let deadline: Instant = Instant::now() + std::time::Duration::from_secs(CANCEL_GRACE_SECS);
+        while child.try_wait()?.is_none() {
+            if Instant::now() >= deadline {
+                warn!("ffmpeg did not exit after cancellation; killing it");
+                child.kill()?;
+                child.wait()?;
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(200));
+        }
+        Self::join_stderr(stderr);
+        Err("Recording cancelled".into())
+    }
+
+    /// ffmpeg の `-progress` 出力を読み取り、進捗率を `Progress` イベントとして送信する
+    ///
+    /// `-progress pipe:1` の指定と標準出力のパイプは呼び出し側で設定しておくこと。
+    fn run_with_progress(
+        command: &mut Command,
+        duration_secs: i64,
+        tx: &Sender<RecordEvent>,
+        log: Option<File>,
+    ) -> Result<(ExitStatus, String), Box<dyn Error>> {
+        let mut child: Child = command.stderr(Stdio::piped()).spawn()?;
+        let stderr: Option<thread::JoinHandle<String>> = ffmpeg_retry::capture_stderr(&mut child, log);
+
+        if let Some(stdout) = child.stdout.take() {
+            let mut last_pct: Option<u32> = None;
+            for line in BufReader::new(stdout).lines() {
+                let line: String = line?;
+                // out_time_us=<マイクロ秒> の行から経過時間を取得
+                let Some(value) = line.strip_prefix("out_time_us=") else {
+                    continue;
+                };
+                let Ok(out_time_us) = value.trim().parse::<i64>() else {
+                    continue;
+                };
+                let pct: f64 =
+                    (out_time_us as f64 / 1_000_000.0 / duration_secs as f64 * 100.0).clamp(0.0, 100.0);
+                // 1% 単位で変化したときのみ送信する
+                if last_pct != Some(pct as u32) {
+                    last_pct = Some(pct as u32);
+                    let _ = tx.send(RecordEvent::Progress { pct });
+                }
+            }
+        }
+        let status: ExitStatus = child.wait()?;
+        Ok((status, Self::join_stderr(stderr)))
+    }
+
     /// 指定エリアの放送局リストを取得する  
     /// 
+    /// 放送局リストは公開されているため、認可トークンは使わない。
+    /// 
     /// # 戻り値
     /// 放送局情報のベクター
     pub fn get_station_list(&self) -> Result<Vec<Station>, Box<dyn Error>> {
-        let url: String = format!("https://radiko.jp/v3/station/list/{}.xml", self.area_id);
-        let resp: reqwest::blocking::Response = reqwest::blocking::get(&url)?;
-        let content: String = resp.text()?;
-
-        // XMLパース
-        let station_list: StationList = from_str(&content)?;
-        Ok(station_list.stations)
+        fetch_station_list(&self.area_id)
     }
 
     /// 認可済みのヘッダを取得する
-    fn make_headers(area_id: &str) -> HashMap<String, String> {
-        let auth_handler: RadikoAuthHandler = RadikoAuthHandler::new(area_id)
-            .expect("Radiko authentication failed");
+    ///
+    /// 設定で認可が無効化されている場合は認可処理を行わず、トークンなしのヘッダを返す。
+    /// 取得済みの認可トークンが設定されている場合は認可処理を行わず、そのトークンを使う。
+    fn make_headers(area_id: &str, config: &Config) -> Result<HashMap<String, String>, Box<dyn Error>> {
+        if let Some(token) = &config.auth.token {
+            let token: &str = token.trim();
+            if token.is_empty() {
+                return Err("The supplied auth token is empty".into());
+            }
+            warn!(
+                "Using the supplied auth token for area {} without authenticating; it may have expired and must belong to that area",
+                area_id
+            );
+            let mut headers: HashMap<String, String> = HashMap::new();
+            headers.insert("X-Radiko-AuthToken".to_string(), token.to_string());
+            headers.insert("Connection".to_string(), "keep-alive".to_string());
+            return Ok(headers);
+        }
+        if config.auth.skip {
+            warn!("Radiko authentication skipped; protected streams will fail");
+            let mut headers: HashMap<String, String> = HashMap::new();
+            headers.insert("Connection".to_string(), "keep-alive".to_string());
+            return Ok(headers);
+        }
+        match (config.auth.prefer_premium, config.auth.premium_credentials()) {
+            (PreferPremium::Always, Some(_)) => return Self::premium_headers(area_id, &config.auth),
+            (PreferPremium::Always, None) => {
+                return Err("--prefer-premium always requires premium_mail and premium_password in [auth] (or --premium-mail and --premium-password)".into())
+            }
+            _ => {}
+        }
+        let auth_handler: RadikoAuthHandler =
+            RadikoAuthHandler::new(area_id, config.auth.profile, &config.auth.headers, config.auth.key.as_deref())?;
         let mut headers: HashMap<String, String> = auth_handler.get_authenticated_headers();
         headers.insert("Connection".to_string(), "keep-alive".to_string());
         debug!("headers: {:?}", headers);
-        headers
+        info!("Authenticated anonymously for area {}", area_id);
+        Ok(headers)
+    }
+
+    /// プレミアム会員としてログインして認可済みのヘッダを取得する
+    fn premium_headers(area_id: &str, auth: &AuthConfig) -> Result<HashMap<String, String>, Box<dyn Error>> {
+        let (mail, password): (&str, &str) = auth
+            .premium_credentials()
+            .ok_or("Radiko premium mail address and password are not configured")?;
+        let session: String = auth_handler::premium_login(mail, password)?;
+        let auth_handler: RadikoAuthHandler =
+            RadikoAuthHandler::premium(area_id, auth.profile, &auth.headers, auth.key.as_deref(), &session)?;
+        let mut headers: HashMap<String, String> = auth_handler.get_authenticated_headers();
+        headers.insert("Connection".to_string(), "keep-alive".to_string());
+        debug!("headers: {:?}", headers);
+        info!("Authenticated with Radiko premium for area {}", area_id);
+        Ok(headers)
     }
 
     /// 日時を "YYYYMMDDHHMMSS" 形式にフォーマットする  
-    fn format_datetime(dt: DateTime<Local>) -> String {
-        dt.format("%Y%m%d%H%M%S").to_string()
+    fn format_datetime(dt: DateTime<FixedOffset>) -> String {
+        dt.with_timezone(&datetime::jst()).format("%Y%m%d%H%M%S").to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    const STATION_LIST_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<stations area_id="JP13" area_name="TOKYO JAPAN">
+  <station>
+    <id>TBS</id>
+    <name>TBSラジオ</name>
+    <ascii_name>TBS RADIO</ascii_name>
+    <ruby>てぃーびーえすらじお</ruby>
+    <areafree>1</areafree>
+    <timefree>1</timefree>
+    <logo width="224" height="100">https://radiko.jp/v2/static/station/logo/TBS/224x100.png</logo>
+    <href>https://www.tbsradio.jp/</href>
+  </station>
+  <station>
+    <id>QRR</id>
+    <name>文化放送</name>
+    <ascii_name>JOQR BUNKA HOSO</ascii_name>
+    <ruby>ぶんかほうそう</ruby>
+    <areafree>1</areafree>
+    <timefree>1</timefree>
+  </station>
+</stations>
+"#;
+
+    /// gzip 圧縮した放送局リストを 1 回だけ返す HTTP サーバーを起動し、その URL を返す
+    fn serve_gzip_once(body: &str) -> String {
+        let mut encoder: GzEncoder<Vec<u8>> = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body.as_bytes()).unwrap();
+        let compressed: Vec<u8> = encoder.finish().unwrap();
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url: String = format!("http://{}/v3/station/list/JP13.xml", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _): (TcpStream, _) = listener.accept().unwrap();
+            let mut request: Vec<u8> = Vec::new();
+            let mut buf: [u8; 1024] = [0; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n: usize = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            let request: String = String::from_utf8_lossy(&request).to_lowercase();
+            assert!(request.contains("accept-encoding: gzip"), "gzip was not requested: {}", request);
+            let header: String = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                compressed.len()
+            );
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(&compressed).unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn parses_gzip_encoded_station_list() {
+        let url: String = serve_gzip_once(STATION_LIST_XML);
+        let content: String = build_client().unwrap().get(&url).send().unwrap().text().unwrap();
+        let stations: Vec<Station> = parse_station_list(&content).unwrap();
+        let ids: Vec<&str> = stations.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["TBS", "QRR"]);
+        assert_eq!(stations[0].name, "TBSラジオ");
+        assert_eq!(stations[1].ruby, "ぶんかほうそう");
+    }
+
+    #[test]
+    fn parses_decoded_station_list() {
+        let stations: Vec<Station> = parse_station_list(STATION_LIST_XML).unwrap();
+        assert_eq!(stations.len(), 2);
+        assert_eq!(stations[1].ascii_name, "JOQR BUNKA HOSO");
+    }
+
+    fn station(id: &str, name: &str, ascii_name: &str, ruby: &str) -> Station {
+        Station {
+            id: id.to_string(),
+            name: name.to_string(),
+            ascii_name: ascii_name.to_string(),
+            ruby: ruby.to_string(),
+        }
+    }
+
+    #[test]
+    fn normalize_name_folds_width_and_kana() {
+        assert_eq!(normalize_name("ＴＢＳラジオ"), normalize_name("tbsらじお"));
+        assert_eq!(normalize_name("ブンカホウソウ"), "ぶんかほうそう");
+        assert_eq!(normalize_name("ｊ－ｗａｖｅ８１．３"), "J-WAVE81.3");
+    }
+
+    #[test]
+    fn find_by_name_matches_across_kana() {
+        let stations: Vec<Station> = vec![
+            station("TBS", "TBSラジオ", "TBS RADIO", "てぃーびーえすらじお"),
+            station("QRR", "文化放送", "JOQR BUNKA HOSO", "ぶんかほうそう"),
+            station("LFR", "ニッポン放送", "NIPPON BROADCASTING", "にっぽんほうそう"),
+        ];
+        let ids = |query: &str| -> Vec<String> {
+            Station::find_by_name(&stations, query).iter().map(|s| s.id.clone()).collect()
+        };
+        // カタカナの検索語がひらがなの読み仮名に一致する
+        assert_eq!(ids("ブンカ"), ["QRR"]);
+        // ひらがなの検索語がカタカナの放送局名に一致する
+        assert_eq!(ids("にっぽん"), ["LFR"]);
+        // 全角英字・全角カタカナの混在した検索語も正規化して比較する
+        assert_eq!(ids("ＴＢＳラジオ"), ["TBS"]);
+        assert_eq!(ids("ＨＯＳＯ"), ["QRR"]);
+        assert_eq!(ids("ほうそう"), ["QRR", "LFR"]);
+        assert!(ids("").is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn station_list_without_stations_is_an_error() {
+        let err: Box<dyn Error> = parse_station_list("<stations area_id=\"JP13\"></stations>").unwrap_err();
+        assert!(err.to_string().contains("No <station> elements"), "{}", err);
+        assert!(parse_station_list("<html><body>Service Unavailable</body></html>").is_err());
+    }
+
+    #[test]
+    fn malformed_station_is_skipped() {
+        let content: &str = "<stations><station><id>TBS</id><name>TBSラジオ</name><ascii_name>TBS RADIO</ascii_name></station><station><id>BAD</id></station></stations>";
+        let stations: Vec<Station> = parse_station_list(content).unwrap();
+        assert_eq!(stations.len(), 1);
+        assert_eq!(stations[0].id, "TBS");
+    }
+
+    #[test]
+    fn split_offsets_are_checked_against_the_clamped_duration() {
+        // 上限はプロセス全体の設定のため、上限を設定するテストはこの 1 つのみとする
+        duration::configure_max_duration(3600, duration::MaxDurationAction::Clamp);
+        let options = |split_at_minutes: &[i64]| RecordOptions {
+            split_at_secs: split_at_minutes.iter().map(|m| m * 60).collect(),
+            ..RecordOptions::default()
+        };
+
+        // 2 時間の録音は 60 秒のプリロールを含めて 1 時間に切り詰められ、録音開始から 59 分までになる
+        assert!(options(&[30, 58]).validate_split_within_cap(7200, 60).is_ok());
+        let error: String = options(&[30, 59]).validate_split_within_cap(7200, 60).unwrap_err().to_string();
+        assert!(error.contains("past the end of the recording"), "{}", error);
+        assert!(error.contains("--max-duration clamps to 1h"), "{}", error);
+        // 上限に収まる録音は通常どおり録音時間と比べる
+        assert!(options(&[20]).validate_split_within_cap(1800, 60).is_ok());
+        assert!(options(&[30]).validate_split_within_cap(1800, 60).is_err());
+        // 上限がプリロール以下の場合は録音できる部分がない
+        assert!(RecordOptions::default().validate_split_within_cap(7200, 3600).is_err());
+
+        assert_eq!(duration::apply_max_duration(7260), Ok(3600));
+        assert_eq!(duration::capped_duration(3600), Ok(3600));
+    }
+}
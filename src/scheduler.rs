@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDateTime, NaiveTime, Weekday};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{CatchUpPolicy, Config, RecurringConfig};
+use crate::datetime::{self, TIMESHIFT_DAYS};
+use crate::duration::parse_duration;
+use crate::health::{self, DaemonStatus, JobStatus, LastError, SharedStatus};
+use crate::library;
+use crate::recorder::{self, CancelFlag, RadikoPlayer, RecordOptions, RecordOutcome};
+
+/// 待機中に状態を確認する間隔の上限（秒）
+const MAX_SLEEP_SECS: i64 = 60;
+
+/// 毎週決まった曜日・時刻に放送される番組の録音定義
+#[derive(Debug, Clone)]
+pub struct RecurringRule {
+    pub station_id: String,
+    pub area_id: Option<String>,
+    pub weekday: Weekday,
+    pub time: NaiveTime,
+    pub duration_secs: i64,
+}
+
+impl RecurringRule {
+    /// 設定ファイルの録音定義を検証して変換する
+    pub fn from_config(config: &RecurringConfig) -> Result<Self, Box<dyn Error>> {
+        let weekday: Weekday = config
+            .weekday
+            .parse::<Weekday>()
+            .map_err(|_| format!("Invalid weekday: {}", config.weekday))?;
+        let time: NaiveTime = NaiveTime::parse_from_str(&config.time, "%H:%M")
+            .or_else(|_| NaiveTime::parse_from_str(&config.time, "%H:%M:%S"))
+            .map_err(|_| format!("Invalid time: {} (expected HH:MM)", config.time))?;
+        let duration_secs: i64 = parse_duration(&config.duration.to_string())?;
+        Ok(Self {
+            station_id: config.station.clone(),
+            area_id: config.area_id.clone(),
+            weekday,
+            time,
+            duration_secs,
+        })
+    }
+
+    /// 状態ファイルで録音済みの回を識別するためのキー
+    pub fn key(&self) -> String {
+        format!("{}-{}-{}", self.station_id, self.weekday, self.time.format("%H%M"))
+    }
+
+    /// 終了時刻が `after` より後となる最初の放送回の開始日時を返す
+    ///
+    /// 放送中の回も対象となる（放送終了後にタイムフリーで録音する）。
+    pub fn next_occurrence(&self, after: NaiveDateTime) -> NaiveDateTime {
+        let days_ahead: i64 = (self.weekday.num_days_from_monday() as i64
+            - after.weekday().num_days_from_monday() as i64)
+            .rem_euclid(7);
+        let mut start: NaiveDateTime = (after.date() + Duration::days(days_ahead)).and_time(self.time);
+        // 前週の回がまだ終わっていない場合はそちらを優先する
+        if start - Duration::days(7) + Duration::seconds(self.duration_secs) > after {
+            start -= Duration::days(7);
+        }
+        while start + Duration::seconds(self.duration_secs) <= after {
+            start += Duration::days(7);
+        }
+        start
+    }
+}
+
+/// 録音待ちの放送回
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Job {
+    rule: usize,
+    start: NaiveDateTime,
+}
+
+/// キューファイルに保存する録音待ちの放送回
+///
+/// 設定ファイルの録音定義の並びが変わっても対応付けられるよう、録音定義はキーで表す。
+#[derive(Debug, Serialize, Deserialize)]
+struct QueuedJob {
+    rule: String,
+    start: NaiveDateTime,
+}
+
+/// 常駐モードで毎週の録音定義に従って録音を繰り返す
+///
+/// 各放送回は放送終了から `delay_minutes` 分後にタイムフリーで録音する。
+/// 録音済みの回は状態ファイルに記録し、停止中に逃した回は
+/// 取りこぼし時の方針（`catch_up`）に従って録音する。
+/// 録音待ちの回はキューファイルに保存し、再起動時に読み込んで引き継ぐ。
+/// `health_port` を指定した場合は、状態を返すヘルスチェック用の HTTP サーバを起動する。
+pub fn run_daemon(
+    area_id: &str,
+    output_dir: &str,
+    config: &Config,
+    health_port: Option<u16>,
+) -> Result<(), Box<dyn Error>> {
+    let rules: Vec<RecurringRule> = config
+        .recurring
+        .iter()
+        .map(RecurringRule::from_config)
+        .collect::<Result<Vec<RecurringRule>, Box<dyn Error>>>()?;
+    if rules.is_empty() {
+        return Err("No recurring recordings defined in the config file".into());
+    }
+
+    let state_path: PathBuf = PathBuf::from(&config.daemon.state_file);
+    let mut state: HashMap<String, NaiveDateTime> = load_state(&state_path)?;
+    let now: NaiveDateTime = datetime::now_jst().naive_local();
+    let delay: Duration = Duration::minutes(config.daemon.delay_minutes);
+
+    let queue_path: PathBuf = PathBuf::from(&config.daemon.queue_file);
+    let mut jobs: Vec<Job> = restore_queue(&queue_path, &rules, &state, now, config.daemon.catch_up)?;
+    for (i, rule) in rules.iter().enumerate() {
+        for job in missed_jobs(i, rule, state.get(&rule.key()), now, config.daemon.catch_up) {
+            if !jobs.contains(&job) {
+                jobs.push(job);
+            }
+        }
+        let next: Job = Job { rule: i, start: rule.next_occurrence(now) };
+        if !jobs.contains(&next) {
+            jobs.push(next);
+        }
+        info!(
+            "Scheduled {} every {} at {} for {} seconds",
+            rule.station_id,
+            rule.weekday,
+            rule.time.format("%H:%M"),
+            rule.duration_secs
+        );
+    }
+
+    save_queue(&queue_path, &jobs, &rules);
+
+    let status: SharedStatus = Arc::new(Mutex::new(DaemonStatus::default()));
+    if let Some(port) = health_port {
+        health::serve(port, Arc::clone(&status))?;
+    }
+
+    loop {
+        update_status(&status, |s| s.queued = queued_jobs(&jobs, &rules, delay));
+
+        // 録音可能になる時刻が最も早い回を選ぶ
+        let (index, ready_at) = jobs
+            .iter()
+            .enumerate()
+            .map(|(i, job)| (i, job.start + Duration::seconds(rules[job.rule].duration_secs) + delay))
+            .min_by_key(|(_, ready_at)| *ready_at)
+            .ok_or("No jobs scheduled")?;
+
+        let wait: i64 = (ready_at - datetime::now_jst().naive_local()).num_seconds();
+        if wait > 0 {
+            sleep(std::time::Duration::from_secs(wait.min(MAX_SLEEP_SECS) as u64));
+            continue;
+        }
+
+        let job: Job = jobs.remove(index);
+        let rule: &RecurringRule = &rules[job.rule];
+        let area_id: &str = rule.area_id.as_deref().unwrap_or(area_id);
+        let current: JobStatus = job_status(rule, job.start);
+        let cancel: CancelFlag = Arc::new(AtomicBool::new(false));
+        update_status(&status, |s| {
+            s.active.insert(current.id.clone(), Arc::clone(&cancel));
+            s.current = Some(current.clone());
+            s.queued = queued_jobs(&jobs, &rules, delay);
+        });
+        let result: Result<Option<u64>, Box<dyn Error>> =
+            record_job(rule, job.start, area_id, output_dir, config, cancel);
+        update_status(&status, |s| {
+            s.active.remove(&current.id);
+            s.current = None;
+            match &result {
+                Ok(Some(bytes)) => {
+                    s.metrics.recordings_total += 1;
+                    s.metrics.bytes_written_total += bytes;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    s.metrics.recordings_total += 1;
+                    s.metrics.recordings_failed_total += 1;
+                    s.last_error = Some(LastError {
+                        at: datetime::now_jst().naive_local(),
+                        message: format!("Failed to record {} at {}: {}", rule.station_id, job.start, e),
+                    });
+                }
+            }
+        });
+        match result {
+            Ok(_) => {
+                state.insert(rule.key(), job.start);
+                if let Err(e) = save_state(&state_path, &state) {
+                    warn!("Failed to save daemon state: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to record {} at {}: {}", rule.station_id, job.start, e),
+        }
+
+        // 同じ録音定義の次の回を登録する（取りこぼし分の録音では登録しない）
+        if !jobs.iter().any(|j| j.rule == job.rule) {
+            jobs.push(Job { rule: job.rule, start: job.start + Duration::days(7) });
+        }
+        save_queue(&queue_path, &jobs, &rules);
+    }
+}
+
+/// ヘルスチェックで返す状態を更新する
+fn update_status(status: &SharedStatus, update: impl FnOnce(&mut DaemonStatus)) {
+    match status.lock() {
+        Ok(mut guard) => update(&mut guard),
+        Err(_) => warn!("Daemon status lock poisoned"),
+    }
+}
+
+/// 放送回の状態を作成する
+fn job_status(rule: &RecurringRule, start: NaiveDateTime) -> JobStatus {
+    JobStatus {
+        id: format!("{}-{}", rule.key(), start.format("%Y%m%d")),
+        station_id: rule.station_id.clone(),
+        start,
+        duration_secs: rule.duration_secs,
+    }
+}
+
+/// 録音待ちの放送回を録音可能になる順に並べる
+fn queued_jobs(jobs: &[Job], rules: &[RecurringRule], delay: Duration) -> Vec<JobStatus> {
+    let mut queued: Vec<(NaiveDateTime, JobStatus)> = jobs
+        .iter()
+        .map(|job| {
+            let rule: &RecurringRule = &rules[job.rule];
+            (job.start + Duration::seconds(rule.duration_secs) + delay, job_status(rule, job.start))
+        })
+        .collect();
+    queued.sort_by_key(|(ready_at, _)| *ready_at);
+    queued.into_iter().map(|(_, status)| status).collect()
+}
+
+/// 停止中に逃した放送回のうち、取りこぼし時の方針に従って録音する回を返す
+///
+/// 状態ファイルに記録がない録音定義（初回起動）は対象外とする。
+fn missed_jobs(
+    rule_index: usize,
+    rule: &RecurringRule,
+    last_recorded: Option<&NaiveDateTime>,
+    now: NaiveDateTime,
+    policy: CatchUpPolicy,
+) -> Vec<Job> {
+    let Some(last) = last_recorded else {
+        return Vec::new();
+    };
+    let mut jobs: Vec<Job> = Vec::new();
+    let window_start: NaiveDateTime = now - Duration::days(TIMESHIFT_DAYS);
+    let mut start: NaiveDateTime = *last + Duration::days(7);
+    while start + Duration::seconds(rule.duration_secs) <= now {
+        match policy {
+            CatchUpPolicy::Timeshift if start >= window_start => {
+                info!("Catching up missed {} at {} via timeshift", rule.station_id, start);
+                jobs.push(Job { rule: rule_index, start });
+            }
+            _ => warn!("Skipping missed {} at {}", rule.station_id, start),
+        }
+        start += Duration::days(7);
+    }
+    jobs
+}
+
+/// キューファイルから前回の録音待ちの回を読み込む
+///
+/// 設定ファイルから削除された録音定義の回と、録音済みの回は破棄する。停止中に録音可能な時刻を過ぎた回は、
+/// 取りこぼし時の方針がタイムフリーで、タイムフリーで遡れる範囲にある場合のみ引き継ぐ。
+fn restore_queue(
+    path: &Path,
+    rules: &[RecurringRule],
+    state: &HashMap<String, NaiveDateTime>,
+    now: NaiveDateTime,
+    policy: CatchUpPolicy,
+) -> Result<Vec<Job>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content: String = fs::read_to_string(path)?;
+    let queued: Vec<QueuedJob> =
+        serde_json::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+    let window_start: NaiveDateTime = now - Duration::days(TIMESHIFT_DAYS);
+    let mut jobs: Vec<Job> = Vec::new();
+    for queued_job in queued {
+        let Some(index) = rules.iter().position(|r| r.key() == queued_job.rule) else {
+            warn!("Dropping queued {} at {}: no longer defined", queued_job.rule, queued_job.start);
+            continue;
+        };
+        let rule: &RecurringRule = &rules[index];
+        // 状態ファイルに録音済みとして記録されている回は引き継がない
+        if state.get(&rule.key()).is_some_and(|last| *last >= queued_job.start) {
+            continue;
+        }
+        let ended: bool = queued_job.start + Duration::seconds(rule.duration_secs) <= now;
+        match policy {
+            _ if !ended => {}
+            CatchUpPolicy::Timeshift if queued_job.start >= window_start => {
+                info!("Catching up queued {} at {} via timeshift", rule.station_id, queued_job.start);
+            }
+            _ => {
+                warn!("Skipping queued {} at {}", rule.station_id, queued_job.start);
+                continue;
+            }
+        }
+        let job: Job = Job { rule: index, start: queued_job.start };
+        if !jobs.contains(&job) {
+            jobs.push(job);
+        }
+    }
+    info!("Restored {} queued recordings from {}", jobs.len(), path.display());
+    Ok(jobs)
+}
+
+/// 録音待ちの回をキューファイルに書き込む（失敗しても常駐は続ける）
+fn save_queue(path: &Path, jobs: &[Job], rules: &[RecurringRule]) {
+    let queued: Vec<QueuedJob> = jobs
+        .iter()
+        .map(|job| QueuedJob { rule: rules[job.rule].key(), start: job.start })
+        .collect();
+    let result: Result<(), Box<dyn Error>> = serde_json::to_string_pretty(&queued)
+        .map_err(|e| e.into())
+        .and_then(|json| fs::write(path, json).map_err(|e| e.into()));
+    if let Err(e) = result {
+        warn!("Failed to save daemon queue: {}", e);
+    }
+}
+
+/// 1 回分の放送を録音する（`cancel` が立った場合は録音を中止する）
+///
+/// 録音ファイルのサイズ（バイト）を返す（録音済みでスキップした場合は `None`）。
+fn record_job(
+    rule: &RecurringRule,
+    start: NaiveDateTime,
+    area_id: &str,
+    output_dir: &str,
+    config: &Config,
+    cancel: CancelFlag,
+) -> Result<Option<u64>, Box<dyn Error>> {
+    let end: NaiveDateTime = start + Duration::seconds(rule.duration_secs);
+    if !config.overwrite && library::skip_if_recorded(Path::new(output_dir), &rule.station_id, start, end).is_some() {
+        return Ok(None);
+    }
+    let start_time: DateTime<FixedOffset> = datetime::to_jst(start);
+    let output_file: PathBuf = recorder::output_file_path(Path::new(output_dir), &rule.station_id, start_time, None, &config.filename)?;
+    let player: RadikoPlayer = RadikoPlayer::new(area_id, config)?;
+    let outcome: RecordOutcome = player.record(
+        &rule.station_id,
+        start_time,
+        rule.duration_secs,
+        output_file.to_str().ok_or("Invalid output file path")?,
+        &RecordOptions {
+            cancel: Some(cancel),
+            ..RecordOptions::default()
+        },
+        None,
+    )?;
+    Ok(Some(outcome.file_size_bytes))
+}
+
+/// 録音済みの回を記録した状態ファイルを読み込む
+fn load_state(path: &Path) -> Result<HashMap<String, NaiveDateTime>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let content: String = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// 録音済みの回を状態ファイルに書き込む
+fn save_state(path: &Path, state: &HashMap<String, NaiveDateTime>) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_json::to_string_pretty(state)?)?;
+    Ok(())
+}
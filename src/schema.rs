@@ -0,0 +1,28 @@
+use serde::Serialize;
+
+/// 構造化出力（JSON）のスキーマのバージョン
+///
+/// フィールドの削除・名前や型の変更など、既存の利用者が解釈できなくなる変更をした場合に上げる。
+/// フィールドの追加では上げない。
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `schema_version` を先頭に付けた出力オブジェクト
+#[derive(Debug, Serialize)]
+pub struct Versioned<'a, T: Serialize> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub data: &'a T,
+}
+
+/// オブジェクトに `schema_version` を付ける
+pub fn versioned<T: Serialize>(data: &T) -> Versioned<'_, T> {
+    Versioned {
+        schema_version: SCHEMA_VERSION,
+        data,
+    }
+}
+
+/// 配列の各オブジェクトに `schema_version` を付ける（配列自体の形は変えない）
+pub fn versioned_list<T: Serialize>(items: &[T]) -> Vec<Versioned<'_, T>> {
+    items.iter().map(versioned).collect()
+}
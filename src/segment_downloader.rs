@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+use reqwest::Url;
+use reqwest::blocking::{Client, Response};
+
+use crate::http_client::{acquire_retry, build_client, send_with_retry};
+
+/// セグメント 1 件あたりの最大試行回数
+const SEGMENT_MAX_ATTEMPTS: u32 = 3;
+
+/// 新しいセグメントが増えないままプレイリストを再取得する最大回数
+const MAX_STALE_RELOADS: u32 = 3;
+
+/// プレイリストの再取得間隔（秒）
+const RELOAD_INTERVAL_SECS: u64 = 5;
+
+/// セグメントのダウンロード速度を制限するトークンバケット
+///
+/// セグメントを 1 件ダウンロードするごとにそのバイト数を消費し、不足分を補充できるまで待つ。
+/// セグメント単位で制御するため、瞬間的には 1 セグメント分（数十〜数百 KB）の超過があり得るが、
+/// 数セグメント以上の区間で平均すると指定した速度にほぼ収まる。
+struct RateLimiter {
+    /// 1 秒あたりに補充するバイト数
+    bytes_per_sec: f64,
+    /// 残りのバイト数（負の場合は待つ必要がある）
+    tokens: f64,
+    /// 最後に補充した時刻
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// `kbps`（KB/s）の速度に制限するリミッタを作成する（バケットの容量は 1 秒分）
+    fn new(kbps: u64) -> Self {
+        let bytes_per_sec: f64 = kbps.saturating_mul(1024) as f64;
+        Self {
+            bytes_per_sec,
+            tokens: bytes_per_sec,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// `bytes` バイトを消費し、制限を超えた分だけ待つ
+    fn consume(&mut self, bytes: usize) {
+        let now: Instant = Instant::now();
+        let elapsed: f64 = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.bytes_per_sec).min(self.bytes_per_sec);
+        self.last_refill = now;
+        self.tokens -= bytes as f64;
+        if self.tokens < 0.0 {
+            let wait: Duration = Duration::from_secs_f64(-self.tokens / self.bytes_per_sec);
+            debug!("rate limit: waiting {:.2}s", wait.as_secs_f64());
+            sleep(wait);
+            self.tokens = 0.0;
+            self.last_refill = Instant::now();
+        }
+    }
+}
+
+/// HLS のプレイリストからセグメントを直接ダウンロードし、1 つのファイルに連結する
+///
+/// ffmpeg の HLS 取得が不安定な場合の代替手段（実験的機能）。
+/// マスタープレイリストの場合は最初のバリアントを使い、メディアプレイリストは
+/// `#EXT-X-ENDLIST` が現れるか新しいセグメントが増えなくなるまで再取得する。
+/// セグメントごとに最大 `SEGMENT_MAX_ATTEMPTS` 回まで再試行する。
+///
+/// # 引数
+/// - `playlist_url`: プレイリストのURL
+/// - `auth_token`: X-Radiko-AuthToken ヘッダの値（認可を省略した場合は `None`）
+/// - `output_path`: 連結したセグメントの出力先ファイルパス
+/// - `rate_limit_kbps`: ダウンロード速度の上限（KB/s、`None` の場合は制限しない）
+pub fn download_segments(
+    playlist_url: &str,
+    auth_token: Option<&str>,
+    output_path: &str,
+    rate_limit_kbps: Option<u64>,
+) -> Result<usize, Box<dyn Error>> {
+    let client: Client = build_client()?;
+    let mut limiter: Option<RateLimiter> = rate_limit_kbps.map(RateLimiter::new);
+    let mut media_url: Url = Url::parse(playlist_url)?;
+    let mut playlist: String = fetch_text(&client, &media_url, auth_token)?;
+
+    // マスタープレイリストの場合はバリアントのプレイリストを取得する
+    if playlist.contains("#EXT-X-STREAM-INF") {
+        let variant: &str = uri_lines(&playlist)
+            .next()
+            .ok_or("No variant found in master playlist")?;
+        media_url = media_url.join(variant)?;
+        debug!("variant playlist: {}", media_url);
+        playlist = fetch_text(&client, &media_url, auth_token)?;
+    }
+
+    let mut output: File = File::create(output_path)?;
+    let mut downloaded: HashSet<Url> = HashSet::new();
+    let mut stale_reloads: u32 = 0;
+    loop {
+        let mut new_segments: usize = 0;
+        for uri in uri_lines(&playlist) {
+            let segment_url: Url = media_url.join(uri)?;
+            if !downloaded.insert(segment_url.clone()) {
+                continue;
+            }
+            let bytes: Vec<u8> = fetch_segment(&client, &segment_url, auth_token)?;
+            output.write_all(&bytes)?;
+            if let Some(limiter) = limiter.as_mut() {
+                limiter.consume(bytes.len());
+            }
+            new_segments += 1;
+        }
+        debug!("downloaded {} new segments", new_segments);
+
+        if playlist.contains("#EXT-X-ENDLIST") {
+            break;
+        }
+        stale_reloads = if new_segments == 0 { stale_reloads + 1 } else { 0 };
+        if stale_reloads >= MAX_STALE_RELOADS {
+            break;
+        }
+        sleep(Duration::from_secs(RELOAD_INTERVAL_SECS));
+        playlist = fetch_text(&client, &media_url, auth_token)?;
+    }
+
+    if downloaded.is_empty() {
+        return Err(format!("No segments found in {}", playlist_url).into());
+    }
+    info!("Downloaded {} segments into {}", downloaded.len(), output_path);
+    Ok(downloaded.len())
+}
+
+/// プレイリストからURI行（コメント・タグ以外の行）を取り出す
+fn uri_lines(playlist: &str) -> impl Iterator<Item = &str> {
+    playlist
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// 認可ヘッダ付きで GET リクエストを送信する
+fn get(client: &Client, url: &Url, auth_token: Option<&str>) -> Result<Response, Box<dyn Error>> {
+    let mut request = client.get(url.clone());
+    if let Some(token) = auth_token {
+        request = request.header("X-Radiko-AuthToken", token);
+    }
+    let res: Response = send_with_retry(request)?;
+    if !res.status().is_success() {
+        return Err(format!("Failed to fetch {}: {}", url, res.status()).into());
+    }
+    Ok(res)
+}
+
+/// プレイリストを取得する
+fn fetch_text(client: &Client, url: &Url, auth_token: Option<&str>) -> Result<String, Box<dyn Error>> {
+    Ok(get(client, url, auth_token)?.text()?)
+}
+
+/// セグメントを取得する（失敗した場合は再試行する）
+fn fetch_segment(client: &Client, url: &Url, auth_token: Option<&str>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut attempt: u32 = 1;
+    loop {
+        match get(client, url, auth_token).and_then(|res| Ok(res.bytes()?.to_vec())) {
+            Ok(bytes) => return Ok(bytes),
+            Err(e) if attempt < SEGMENT_MAX_ATTEMPTS => {
+                warn!("Failed to fetch segment {} ({}/{}): {}", url, attempt, SEGMENT_MAX_ATTEMPTS, e);
+                acquire_retry()?;
+                sleep(Duration::from_secs(attempt as u64));
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
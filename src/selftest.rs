@@ -0,0 +1,99 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use chrono::{DateTime, Duration, FixedOffset, Timelike};
+use log::{info, warn};
+
+use crate::config::Config;
+use crate::datetime;
+use crate::ffprobe::{self, ProbeInfo};
+use crate::recorder::{RadikoPlayer, RecordOptions, Station};
+
+/// セルフテストで録音する長さ（秒）
+const SELFTEST_DURATION_SECS: i64 = 30;
+
+/// セルフテストで録音するタイムフリーの遡り時間（分）
+const SELFTEST_LOOKBACK_MINUTES: i64 = 60;
+
+/// 認可・URL 作成・ffmpeg による録音・ffprobe による検証を一通り実行する
+///
+/// 放送局が指定されていない場合はエリアの放送局リストの先頭を使う。
+/// 録音した一時ファイルは成否にかかわらず削除する。
+pub fn run_selftest(
+    area_id: &str,
+    station_id: Option<&str>,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let player: RadikoPlayer = RadikoPlayer::new(area_id, config)?;
+    println!("[OK] authentication (area: {})", area_id);
+
+    let station_id: String = match station_id {
+        Some(id) => id.to_string(),
+        None => {
+            let stations: Vec<Station> = player.get_station_list()?;
+            stations
+                .first()
+                .map(|s| s.id.clone())
+                .ok_or("No stations available for self-test")?
+        }
+    };
+    println!("[OK] station: {}", station_id);
+
+    // 直近のタイムフリーから、分単位に切り捨てた時刻を開始時刻とする
+    let now: DateTime<FixedOffset> = datetime::now_jst();
+    let start_time: DateTime<FixedOffset> = (now - Duration::minutes(SELFTEST_LOOKBACK_MINUTES))
+        .with_second(0)
+        .and_then(|dt| dt.with_nanosecond(0))
+        .ok_or("Failed to compute self-test start time")?;
+
+    let output_file: PathBuf =
+        std::env::temp_dir().join(format!("radiko_selftest_{}.aac", process::id()));
+    let output_path: &str = output_file.to_str().ok_or("Invalid temporary file path")?;
+
+    let result: Result<(), Box<dyn Error>> =
+        record_and_verify(&player, &station_id, start_time, output_path);
+
+    if output_file.exists() {
+        if let Err(e) = fs::remove_file(&output_file) {
+            warn!("Failed to remove {}: {}", output_path, e);
+        }
+    }
+
+    match &result {
+        Ok(()) => println!("Self-test passed"),
+        Err(e) => println!("Self-test failed: {}", e),
+    }
+    result
+}
+
+/// 短いクリップを録音し、音声ストリームが含まれているかを検証する
+fn record_and_verify(
+    player: &RadikoPlayer,
+    station_id: &str,
+    start_time: DateTime<FixedOffset>,
+    output_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    player.record(
+        station_id,
+        start_time,
+        SELFTEST_DURATION_SECS,
+        output_path,
+        &RecordOptions::default(),
+        None,
+    )?;
+    println!("[OK] recording ({} seconds)", SELFTEST_DURATION_SECS);
+
+    let probe: ProbeInfo = ffprobe::probe(output_path)?;
+    let codec: String = probe
+        .audio_codec
+        .ok_or("Recorded file contains no audio stream")?;
+    let duration_secs: f64 = probe.duration_secs.unwrap_or(0.0);
+    if duration_secs <= 0.0 {
+        return Err("Recorded file has no playable duration".into());
+    }
+    info!("Self-test recording: codec={}, duration={:.1}s", codec, duration_secs);
+    println!("[OK] verification (codec: {}, duration: {:.1}s)", codec, duration_secs);
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use quick_xml::escape::escape;
+
+use crate::program::{html_to_text, Program};
+
+/// 番組情報のサイドカーファイルの形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SidecarFormat {
+    /// プレーンテキスト（`.txt`）
+    Txt,
+    /// Kodi などのメディアサーバ向けの XML（`.nfo`）
+    Nfo,
+}
+
+impl SidecarFormat {
+    /// 出力ファイルの拡張子
+    fn extension(self) -> &'static str {
+        match self {
+            SidecarFormat::Txt => "txt",
+            SidecarFormat::Nfo => "nfo",
+        }
+    }
+}
+
+/// 番組情報をプレーンテキストに変換する
+///
+/// 番組名・放送局・放送日時・出演者・URL の後に、番組説明（`desc`）と補足情報（`info`）を
+/// HTML タグを除いたテキストで続ける。
+pub fn to_text(program: &Program) -> String {
+    let mut lines: Vec<String> = vec![
+        format!("Title: {}", program.title),
+        format!("Station: {}", program.station_id),
+        format!("Aired: {} - {}", program.ft.format("%Y-%m-%d %H:%M"), program.to.format("%H:%M")),
+    ];
+    if !program.pfm.is_empty() {
+        lines.push(format!("Performers: {}", program.pfm));
+    }
+    if !program.url.is_empty() {
+        lines.push(format!("URL: {}", program.url));
+    }
+    for html in [&program.desc, &program.info] {
+        let text: String = html_to_text(html);
+        if !text.is_empty() {
+            lines.push(String::new());
+            lines.push(text);
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+/// 番組情報を Kodi 形式の NFO（`<episodedetails>`）に変換する
+pub fn to_nfo(program: &Program) -> String {
+    let plot: String = [&program.desc, &program.info]
+        .iter()
+        .map(|html| html_to_text(html))
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    let mut lines: Vec<String> = vec![
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#.to_string(),
+        "<episodedetails>".to_string(),
+        format!("  <title>{}</title>", escape(&program.title)),
+        format!("  <plot>{}</plot>", escape(&plot)),
+        format!("  <aired>{}</aired>", program.ft.format("%Y-%m-%d")),
+        format!("  <studio>{}</studio>", escape(&program.station_id)),
+        format!(r#"  <uniqueid type="radiko">{}</uniqueid>"#, escape(&program.id)),
+    ];
+    if !program.pfm.is_empty() {
+        lines.push(format!("  <actor><name>{}</name></actor>", escape(&program.pfm)));
+    }
+    if !program.img.is_empty() {
+        lines.push(format!("  <thumb>{}</thumb>", escape(&program.img)));
+    }
+    lines.push("</episodedetails>".to_string());
+    lines.join("\n") + "\n"
+}
+
+/// 録音ファイルと同じ場所に、拡張子を形式に合わせた番組情報のファイルを書き出し、そのパスを返す
+pub fn write_sidecar(audio_path: &str, program: &Program, format: SidecarFormat) -> Result<String, Box<dyn Error>> {
+    let path: String = Path::new(audio_path)
+        .with_extension(format.extension())
+        .to_string_lossy()
+        .to_string();
+    let content: String = match format {
+        SidecarFormat::Txt => to_text(program),
+        SidecarFormat::Nfo => to_nfo(program),
+    };
+    fs::write(&path, content)?;
+    Ok(path)
+}
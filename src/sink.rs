@@ -0,0 +1,43 @@
+use std::error::Error;
+use std::fmt::Debug;
+use std::path::Path;
+
+use log::info;
+
+use crate::postprocess;
+
+/// 録音ファイルの保存先
+///
+/// 録音は常に作業用の一時ファイルに書き込み、録音の完了後に `finalize` で保存先に引き渡す。
+/// S3 や WebDAV などへのアップロードは、このトレイトを実装して `RecordOptions::sink` に指定する。
+pub trait OutputSink: Debug + Send + Sync {
+    /// 録音済みの一時ファイルを保存先に移し、保存先のパス（または URL）を返す
+    ///
+    /// # 引数
+    /// - `local_temp_path`: 録音済みの一時ファイルのパス（引き渡し後は削除してよい）
+    /// - `destination`: 保存先のファイルパス（出力ディレクトリとファイル名テンプレートから決まる）
+    /// - `metadata`: 録音ファイルに埋め込んだメタデータ（`title`, `artist` などのキーと値）
+    fn finalize(
+        &self,
+        local_temp_path: &Path,
+        destination: &str,
+        metadata: &[(String, String)],
+    ) -> Result<String, Box<dyn Error>>;
+}
+
+/// ローカルのファイルシステムに保存する（デフォルトの保存先）
+#[derive(Debug, Default)]
+pub struct LocalSink;
+
+impl OutputSink for LocalSink {
+    fn finalize(
+        &self,
+        local_temp_path: &Path,
+        destination: &str,
+        _metadata: &[(String, String)],
+    ) -> Result<String, Box<dyn Error>> {
+        postprocess::move_file(local_temp_path, Path::new(destination))?;
+        info!("Saved {}", destination);
+        Ok(destination.to_string())
+    }
+}
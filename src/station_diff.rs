@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+use serde::Serialize;
+
+use crate::recorder::Station;
+
+/// 名前が変更された放送局
+#[derive(Debug, Serialize)]
+pub struct RenamedStation {
+    pub id: String,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+/// 2 つの放送局リストの差分
+#[derive(Debug, Default, Serialize)]
+pub struct StationDiff {
+    /// 新しいリストにのみ存在する放送局
+    pub added: Vec<Station>,
+    /// 古いリストにのみ存在する放送局
+    pub removed: Vec<Station>,
+    /// 両方に存在し、名前が変わった放送局
+    pub renamed: Vec<RenamedStation>,
+}
+
+impl StationDiff {
+    /// 差分がないかどうか
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+    }
+}
+
+/// `--station-list --format json` で保存した放送局リストを読み込む
+pub fn load_station_list(path: &str) -> Result<Vec<Station>, Box<dyn Error>> {
+    let content: String =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let stations: Vec<Station> =
+        serde_json::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+    Ok(stations)
+}
+
+/// 放送局IDをキーに 2 つの放送局リストを比較する
+///
+/// 結果は新しいリスト（削除分は古いリスト）の並び順に従う。
+pub fn diff_stations(old: &[Station], new: &[Station]) -> StationDiff {
+    let old_by_id: HashMap<&str, &Station> = old.iter().map(|s| (s.id.as_str(), s)).collect();
+    let new_by_id: HashMap<&str, &Station> = new.iter().map(|s| (s.id.as_str(), s)).collect();
+
+    let mut diff: StationDiff = StationDiff::default();
+    for station in new {
+        match old_by_id.get(station.id.as_str()) {
+            None => diff.added.push(station.clone()),
+            Some(old_station) if old_station.name != station.name => {
+                diff.renamed.push(RenamedStation {
+                    id: station.id.clone(),
+                    old_name: old_station.name.clone(),
+                    new_name: station.name.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    diff.removed = old
+        .iter()
+        .filter(|s| !new_by_id.contains_key(s.id.as_str()))
+        .cloned()
+        .collect();
+    diff
+}